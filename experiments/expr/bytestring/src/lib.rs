@@ -1637,7 +1637,11 @@ pub const fn compute_length(s: &str) -> usize {
             i += 1; // Skip ']'
             n += 1; // item_byte(Tag::Arity(number))
         } else if b == b'$' {
+            // Skip an optional capture name ($name)
             i += 1;
+            while i < len && bytes[i] != b' ' {
+                i += 1;
+            }
             n += 1; // item_byte(Tag::NewVar)
         } else if b == b'_' {
             // Parse _number
@@ -1696,6 +1700,9 @@ pub const fn parse<const N: usize>(s: &str) -> [u8; N] {
             }
         } else if b == b'$' {
             i += 1; // Skip '$'
+            while i < len && bytes[i] != b' ' {
+                i += 1; // Skip the capture name, it is only recorded by the named macros
+            }
             arr[pos] = item_byte(Tag::NewVar);
             pos += 1;
         } else if b == b'_' {
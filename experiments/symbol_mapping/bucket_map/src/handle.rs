@@ -58,7 +58,10 @@ impl<'a> WritePermit<'a> {
 
     // as minimal as it might be, we want the critical section as small as posible, so we index first
     let sym_table_lock = &self.to_symbol[hash as usize % MAX_WRITER_THREADS].0;
-    let bytes_guard_lock = &self.to_bytes[MAPPING_THREAD_INDEX.get().unwrap() as usize].0;
+    let stable = self.is_stable();
+    let stable_candidate = if stable { stable_symbol(bytes) } else { [0; SYM_LEN] };
+    let bytes_index = if stable { stable_candidate[SYMBOL_THREAD_PERMIT_BYTE_POS] } else { MAPPING_THREAD_INDEX.get().unwrap() };
+    let bytes_guard_lock = &self.to_bytes[bytes_index as usize].0;
     let sym = 'lock_scope_sym : {
       let mut sym_guard = sym_table_lock.write().unwrap();
       // try once more to see if we need to make the symbol
@@ -83,15 +86,25 @@ impl<'a> WritePermit<'a> {
           Slab::register_bytes(slab_ptr,bytes)
         }
       };
-      let new_sym = thread_permission.next_symbol.fetch_add(1, atomic::Ordering::Relaxed).to_be_bytes();
-
-      '_lock_scope_bytes : {
+      let new_sym = '_lock_scope_bytes : {
         let mut bytes_guard = bytes_guard_lock.write().unwrap();
 
+        let new_sym = if stable {
+          // probe past collisions (and the all-zero symbol, which is reserved for De Bruijn introductions)
+          let mut candidate = stable_candidate;
+          while u64::from_be_bytes(candidate) == 0 || bytes_guard.get(candidate).is_some() {
+            candidate = next_stable_symbol(candidate);
+          }
+          candidate
+        } else {
+          thread_permission.next_symbol.fetch_add(1, atomic::Ordering::Relaxed).to_be_bytes()
+        };
+
         let old_thin = bytes_guard.insert(new_sym.as_slice(), thin_bytes_ptr);
         core::debug_assert!(matches!(old_thin, Option::None));
 
-      }
+        new_sym
+      };
       let old_sym = sym_guard.insert(bytes, new_sym);
       core::debug_assert!(matches!(old_sym, Option::None));
 
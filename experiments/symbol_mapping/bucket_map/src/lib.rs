@@ -66,6 +66,8 @@ type AlignArray<T> = [AlignCache<T>; MAX_WRITER_THREADS];
 enum SharedMappingFlags {
   KeepSlabsAlive = 1 << 0,
   HeapAllocated   = 1 << 1,
+  StableSymbols   = 1 << 2,
+  InlineSmall     = 1 << 3,
 }
 pub(crate) const PEARSON_BOUND : usize = 8;
 
@@ -90,6 +92,35 @@ impl SharedMapping {
     }
   }
 
+  /// Like [`SharedMapping::new`], but symbols are derived from a hash of their bytes rather than insertion order,
+  /// so the same set of symbols maps to the same [`Symbol`]s however (and by however many threads) it was inserted.
+  /// Only hash collisions, which are resolved by probing, depend on insertion order.
+  pub fn new_stable()->SharedMappingHandle {
+    unsafe {
+      let ptr = alloc::alloc::alloc(alloc::alloc::Layout::new::<MaybeUninit<SharedMapping>>()) as *mut MaybeUninit<SharedMapping>;
+      SharedMapping::init(ptr, SharedMappingFlags::HeapAllocated as u64 | SharedMappingFlags::StableSymbols as u64)
+    }
+  }
+
+  /// Whether this mapping assigns [`Symbol`]s by hash, see [`SharedMapping::new_stable`]
+  pub fn is_stable(&self) -> bool {
+    self.flags.load(atomic::Ordering::Acquire) & SharedMappingFlags::StableSymbols as u64 != 0
+  }
+
+  /// Like [`SharedMapping::new`], but marks the mapping as one whose users store symbols shorter than a [`Symbol`] inline
+  /// instead of interning them; the mapping itself only ever sees the longer symbols.
+  pub fn new_inlining()->SharedMappingHandle {
+    unsafe {
+      let ptr = alloc::alloc::alloc(alloc::alloc::Layout::new::<MaybeUninit<SharedMapping>>()) as *mut MaybeUninit<SharedMapping>;
+      SharedMapping::init(ptr, SharedMappingFlags::HeapAllocated as u64 | SharedMappingFlags::InlineSmall as u64)
+    }
+  }
+
+  /// Whether symbols shorter than a [`Symbol`] are stored inline, see [`SharedMapping::new_inlining`]
+  pub fn inlines_small(&self) -> bool {
+    self.flags.load(atomic::Ordering::Acquire) & SharedMappingFlags::InlineSmall as u64 != 0
+  }
+
   /// This is unsafe because this could be done inside a stack frame, which makes safety guarantees more difficult.
   /// This has been made public for use in initializing a static.
   pub const unsafe fn init(uninit : *mut MaybeUninit<SharedMapping>, init_flags: u64)-> SharedMappingHandle {
@@ -123,10 +154,21 @@ impl SharedMapping {
     }.map(|t| unsafe {&*t.as_raw_slice()})
   }
 
+  /// Calls `f` with every [`Symbol`] and its bytes, one bucket at a time (each bucket is read-locked while it is visited)
+  pub fn for_each_symbol(&self, mut f : impl FnMut(Symbol, &[u8])) {
+    for each in self.to_bytes.iter() {
+      let lock = each.0.read().unwrap();
+      for (path, thin) in lock.iter() {
+        core::debug_assert_eq!(path.len(), SYM_LEN);
+        f(path[..].try_into().unwrap(), unsafe { &*thin.as_raw_slice() });
+      }
+    }
+  }
+
   /// This function is not inherently unsafe, but should only be used as a last resort when
   /// the lifetime of references to the backing symbol table must linger.
   pub unsafe fn keep_slabs_alive(&self) {
-    self.flags.store(SharedMappingFlags::KeepSlabsAlive as u64, atomic::Ordering::Release);
+    self.flags.fetch_or(SharedMappingFlags::KeepSlabsAlive as u64, atomic::Ordering::Release);
   }
 
   /// try to get a [`Symbol`] if it is already in the map.
@@ -192,6 +234,27 @@ impl ThreadPermission {
 }
 
 
+/// The initial [`Symbol`] candidate for `bytes` in a stable mapping: the bucket byte comes from the Pearson hash
+/// (top bit cleared), the low 5 bytes from a 64-bit FNV-1a hash.
+fn stable_symbol(bytes : &[u8]) -> Symbol {
+  const SYMBOL_ID_MASK : u64 = (1 << (u64::BITS - u8::BITS*3)) - 1;
+
+  let mut hash : u64 = 0xcbf29ce484222325;
+  for each in bytes {
+    hash = (hash ^ *each as u64).wrapping_mul(0x100000001b3);
+  }
+  let bucket = bounded_pearson_hash::<PEARSON_BOUND>(bytes) as u64 & MAX_WRITER_THREAD_INDEX as u64;
+  ((bucket << (u64::BITS - u8::BITS*3)) | (hash & SYMBOL_ID_MASK)).to_be_bytes()
+}
+
+/// The candidate following `sym` within the same bucket, used to probe past collisions.
+fn next_stable_symbol(sym : Symbol) -> Symbol {
+  const SYMBOL_ID_MASK : u64 = (1 << (u64::BITS - u8::BITS*3)) - 1;
+
+  let raw = u64::from_be_bytes(sym);
+  ((raw & !SYMBOL_ID_MASK) | (raw.wrapping_add(1) & SYMBOL_ID_MASK)).to_be_bytes()
+}
+
 /// micro-Pearson hash, this is just to spread the buckets threads deposit into, hoping to avoid degenerate cases.
 /// `SELECTION` determines how many bytes will be selected for the hash.
 /// `SELECTION` must be greater than 1, otherwise it would always return 0 (defeating the purpose of the hash).
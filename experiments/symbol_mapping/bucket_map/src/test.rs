@@ -199,4 +199,23 @@ fn allocate_many() {
   // println!("{:?}", load.to_bytes[0].0.read().unwrap().val_count());
   // std::fs::remove_file(path).unwrap(); 
 
-}
\ No newline at end of file
+}
+#[test]
+fn stable_symbols_ignore_insertion_order() {
+  let bytes : [&[u8]; 5] = [b"abc", b"def", b"foo", b"bar", b"a much longer symbol than the others"];
+
+  let forward = SharedMapping::new_stable();
+  let permit = forward.try_aquire_permission().unwrap();
+  let forward_syms = bytes.map(|bs| permit.get_sym_or_insert(bs));
+  drop(permit);
+
+  let backward = SharedMapping::new_stable();
+  let permit = backward.try_aquire_permission().unwrap();
+  for bs in bytes.iter().rev() { permit.get_sym_or_insert(bs); }
+  drop(permit);
+
+  for (idx, each) in forward_syms.iter().enumerate() {
+    core::assert_eq!(Some(*each), backward.get_sym(bytes[idx]));
+    core::assert_eq!(Some(bytes[idx]), backward.get_bytes(*each));
+  }
+}
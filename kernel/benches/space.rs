@@ -0,0 +1,92 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use mork::space::Space;
+use mork::{item_byte, BytesTrieMap, Expr, Tag, TrieMap};
+
+const BIG_METTA: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../benchmarks/logic-query/resources/big.metta");
+const SMALL_METTA: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../benchmarks/logic-query/resources/small.metta");
+
+fn corpus(path: &str) -> Vec<u8> {
+    std::fs::read(path).unwrap_or_else(|e| panic!("could not read {path}: {e}"))
+}
+
+// `$` and `_1`, loading every expression as-is
+fn identity() -> ([u8; 1], [u8; 1]) {
+    ([item_byte(Tag::NewVar)], [item_byte(Tag::VarRef(0))])
+}
+
+fn load_sexpr(c: &mut Criterion) {
+    let mut group = c.benchmark_group("load_sexpr");
+    group.sample_size(10);
+    for (name, path) in [("small", SMALL_METTA), ("big", BIG_METTA)] {
+        let input = corpus(path);
+        group.throughput(Throughput::Bytes(input.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(name), &input, |b, input| {
+            b.iter(|| {
+                let (mut pattern, mut template) = identity();
+                let mut s = Space::new();
+                s.load_sexpr(&input[..], Expr{ ptr: pattern.as_mut_ptr() }, Expr{ ptr: template.as_mut_ptr() }).unwrap()
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("batched", name), &input, |b, input| {
+            b.iter(|| {
+                let (mut pattern, mut template) = identity();
+                let mut s = Space::new();
+                s.load_sexpr_batched(&input[..], Expr{ ptr: pattern.as_mut_ptr() }, Expr{ ptr: template.as_mut_ptr() }).unwrap()
+            })
+        });
+    }
+    group.finish();
+}
+
+fn query(c: &mut Criterion) {
+    let (mut pattern, mut template) = identity();
+    let mut s = Space::new();
+    s.load_sexpr(&corpus(BIG_METTA)[..], Expr{ ptr: pattern.as_mut_ptr() }, Expr{ ptr: template.as_mut_ptr() }).unwrap();
+
+    let mut group = c.benchmark_group("query_big");
+    group.sample_size(10);
+    // (axiom $) and (axiom (= $ $)) over the whole corpus, and an early-exiting top-10
+    let mut axiom = vec![item_byte(Tag::Arity(2)), item_byte(Tag::SymbolSize(5))];
+    axiom.extend_from_slice(b"axiom");
+    axiom.push(item_byte(Tag::NewVar));
+    let mut equation = axiom[..axiom.len() - 1].to_vec();
+    equation.extend_from_slice(&[item_byte(Tag::Arity(3)), item_byte(Tag::SymbolSize(1)), b'=', item_byte(Tag::NewVar), item_byte(Tag::NewVar)]);
+    for (name, p) in [("axiom", &axiom), ("equation", &equation)] {
+        group.bench_function(name, |b| b.iter(|| {
+            let mut k = 0usize;
+            s.query(Expr{ ptr: p.as_ptr().cast_mut() }, |_, e| { k += 1; black_box(e); });
+            k
+        }));
+    }
+    group.bench_function("axiom_top10", |b| b.iter(|| {
+        s.query_limited(Expr{ ptr: axiom.as_ptr().cast_mut() }, 10, |e| { black_box(e); })
+    }));
+    group.finish();
+}
+
+fn random_map(n: usize, seed: u64) -> BytesTrieMap<u64> {
+    let mut m = BytesTrieMap::new();
+    let mut x = seed;
+    for i in 0..n {
+        // xorshift keys of varying length, so maps built from different seeds overlap on short prefixes
+        x ^= x << 13; x ^= x >> 7; x ^= x << 17;
+        let len = 2 + (x % 7) as usize;
+        m.insert(&x.to_be_bytes()[..len], i as u64);
+    }
+    m
+}
+
+fn triemap_algebra(c: &mut Criterion) {
+    let mut group = c.benchmark_group("triemap");
+    for n in [1_000usize, 10_000, 100_000] {
+        let a = random_map(n, 0x9E37_79B9_7F4A_7C15);
+        let b = random_map(n, 0xD1B5_4A32_D192_ED03);
+        group.throughput(Throughput::Elements(n as u64));
+        group.bench_with_input(BenchmarkId::new("union", n), &n, |bench, _| bench.iter(|| a.union(&b).len()));
+        group.bench_with_input(BenchmarkId::new("intersection", n), &n, |bench, _| bench.iter(|| a.intersection(&b).len()));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, load_sexpr, query, triemap_algebra);
+criterion_main!(benches);
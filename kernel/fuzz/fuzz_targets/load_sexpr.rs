@@ -0,0 +1,13 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use mork::space::Space;
+use mork::{item_byte, Expr, Tag};
+
+// Arbitrary bytes through `load_sexpr` with the identity pattern/template: malformed input must come back as `Err`
+fuzz_target!(|data: &[u8]| {
+    let pattern = [item_byte(Tag::NewVar)];
+    let template = [item_byte(Tag::VarRef(0))];
+    let mut s = Space::new();
+    let _ = s.load_sexpr(data, Expr{ ptr: pattern.as_ptr().cast_mut() }, Expr{ ptr: template.as_ptr().cast_mut() });
+});
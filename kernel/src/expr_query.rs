@@ -1,7 +1,8 @@
 // Expression Query Layer Implementation
 // Support structured key queries on S-expressions
 
-use std::collections::{BTreeMap, VecDeque};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
 use crate::triemap_derivation::{BytesTrieMap, TrieMap};
 
 /// Represents different types of expression patterns for querying
@@ -28,12 +29,66 @@ pub struct ExprQueryEngine {
     structure_index: BytesTrieMap<Vec<ExprId>>,
     /// Symbol table for efficient symbol lookup
     symbol_index: BTreeMap<Vec<u8>, Vec<ExprId>>,
+    /// Symbol index keyed by interned id, used instead of `symbol_index` when interning is enabled
+    interned_index: BTreeMap<u32, Vec<ExprId>>,
+    /// Shared symbol table backing `interned_index`
+    interner: SymbolInterner,
     /// Arity index for quick arity-based queries
     arity_index: BTreeMap<usize, Vec<ExprId>>,
-    /// Expression storage
-    expressions: BTreeMap<ExprId, StoredExpression>,
+    /// Expression storage, with symbols interned when `intern_symbols` is enabled
+    expressions: BTreeMap<ExprId, (StoredStructure, ExprMetadata)>,
     /// Next available expression ID
     next_id: ExprId,
+    /// Configuration
+    config: ExprQueryConfig,
+}
+
+/// Configuration for query engine behavior
+#[derive(Debug, Clone, Default)]
+pub struct ExprQueryConfig {
+    /// Compare symbols ignoring ASCII case (stored structures keep their original case)
+    pub case_insensitive: bool,
+    /// Store and index symbols as `u32` ids into a shared table instead of by their own bytes
+    pub intern_symbols: bool,
+}
+
+/// Table assigning each distinct symbol a dense `u32` id
+#[derive(Debug, Clone, Default)]
+pub struct SymbolInterner {
+    symbols: Vec<Vec<u8>>,
+    ids: HashMap<Vec<u8>, u32>,
+}
+
+impl SymbolInterner {
+    /// Id for `symbol`, allocating one if it is new
+    pub fn intern(&mut self, symbol: &[u8]) -> u32 {
+        if let Some(&id) = self.ids.get(symbol) {
+            return id;
+        }
+        let id = self.symbols.len() as u32;
+        self.symbols.push(symbol.to_vec());
+        self.ids.insert(symbol.to_vec(), id);
+        id
+    }
+    
+    /// Id for `symbol` if it has been interned
+    pub fn get(&self, symbol: &[u8]) -> Option<u32> {
+        self.ids.get(symbol).copied()
+    }
+    
+    /// Bytes of an interned symbol
+    pub fn resolve(&self, id: u32) -> Option<&[u8]> {
+        self.symbols.get(id as usize).map(Vec::as_slice)
+    }
+    
+    /// Number of distinct symbols interned
+    pub fn len(&self) -> usize {
+        self.symbols.len()
+    }
+    
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
 }
 
 /// Unique identifier for expressions
@@ -48,7 +103,7 @@ pub struct StoredExpression {
 }
 
 /// Structure of an expression for indexing
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum ExprStructure {
     Symbol(Vec<u8>),
     Variable(String),
@@ -58,6 +113,92 @@ pub enum ExprStructure {
     },
 }
 
+impl ExprStructure {
+    /// Post-order fold: children are folded first and their results handed to `on_compound` with the arity
+    pub fn fold<T>(
+        &self,
+        on_symbol: &mut impl FnMut(&[u8]) -> T,
+        on_var: &mut impl FnMut(&str) -> T,
+        on_compound: &mut impl FnMut(usize, Vec<T>) -> T,
+    ) -> T {
+        match self {
+            ExprStructure::Symbol(s) => on_symbol(s),
+            ExprStructure::Variable(v) => on_var(v),
+            ExprStructure::Compound { arity, children } => {
+                let folded = children.iter().map(|c| c.fold(on_symbol, on_var, on_compound)).collect();
+                on_compound(*arity, folded)
+            },
+        }
+    }
+    
+    /// Bottom-up rewrite: children are rewritten first, then `f` may replace the rebuilt node.
+    /// Nodes for which `f` returns `None` are kept as they are.
+    pub fn rewrite(&self, f: &mut impl FnMut(&ExprStructure) -> Option<ExprStructure>) -> ExprStructure {
+        let rebuilt = match self {
+            ExprStructure::Compound { arity, children } => ExprStructure::Compound {
+                arity: *arity,
+                children: children.iter().map(|c| c.rewrite(f)).collect(),
+            },
+            leaf => leaf.clone(),
+        };
+        f(&rebuilt).unwrap_or(rebuilt)
+    }
+    
+    /// Nesting depth, counting a leaf as 1
+    pub fn depth(&self) -> usize {
+        self.fold(&mut |_| 1, &mut |_| 1, &mut |_, depths| 1 + depths.into_iter().max().unwrap_or(0))
+    }
+    
+    /// Number of nodes, leaves and compounds alike
+    pub fn size(&self) -> usize {
+        self.fold(&mut |_| 1, &mut |_| 1, &mut |_, sizes| 1 + sizes.into_iter().sum::<usize>())
+    }
+    
+    /// Number of symbol occurrences
+    pub fn symbol_count(&self) -> usize {
+        self.fold(&mut |_| 1, &mut |_| 0, &mut |_, counts| counts.into_iter().sum())
+    }
+    
+    /// Number of variable occurrences (repeats included, unlike `free_variables`)
+    pub fn variable_count(&self) -> usize {
+        self.fold(&mut |_| 0, &mut |_| 1, &mut |_, counts| counts.into_iter().sum())
+    }
+    
+    /// Distinct variable names, in order of first occurrence
+    pub fn free_variables(&self) -> Vec<String> {
+        let mut seen = std::collections::HashSet::new();
+        self.fold(
+            &mut |_| Vec::new(),
+            &mut |v| if seen.insert(v.to_string()) { vec![v.to_string()] } else { Vec::new() },
+            &mut |_, names| names.concat(),
+        )
+    }
+    
+    /// Same arities and symbols in the same places, with any variable matching any other variable.
+    /// Coarser than alpha-equivalence: `(f $x $y)` has the shape of `(f $a $a)`.
+    pub fn same_shape(&self, other: &ExprStructure) -> bool {
+        match (self, other) {
+            (ExprStructure::Symbol(a), ExprStructure::Symbol(b)) => a == b,
+            (ExprStructure::Variable(_), ExprStructure::Variable(_)) => true,
+            (ExprStructure::Compound { arity: a, children: ac }, ExprStructure::Compound { arity: b, children: bc }) =>
+                a == b && ac.len() == bc.len() && ac.iter().zip(bc).all(|(x, y)| x.same_shape(y)),
+            _ => false,
+        }
+    }
+}
+
+/// An expression as the engine keeps it: an `ExprStructure` whose symbols may be ids into the engine's interner
+#[derive(Debug, Clone)]
+enum StoredStructure {
+    Symbol(Vec<u8>),
+    Interned(u32),
+    Variable(String),
+    Compound {
+        arity: usize,
+        children: Vec<StoredStructure>,
+    },
+}
+
 /// Metadata associated with expressions
 #[derive(Debug, Clone)]
 pub struct ExprMetadata {
@@ -85,12 +226,19 @@ pub struct QueryStats {
 
 impl ExprQueryEngine {
     pub fn new() -> Self {
+        Self::with_config(ExprQueryConfig::default())
+    }
+    
+    pub fn with_config(config: ExprQueryConfig) -> Self {
         Self {
             structure_index: BytesTrieMap::new(),
             symbol_index: BTreeMap::new(),
+            interned_index: BTreeMap::new(),
+            interner: SymbolInterner::default(),
             arity_index: BTreeMap::new(),
             expressions: BTreeMap::new(),
             next_id: 1,
+            config,
         }
     }
     
@@ -100,20 +248,15 @@ impl ExprQueryEngine {
         self.next_id += 1;
         
         let metadata = ExprMetadata {
-            depth: self.calculate_depth(&structure),
-            symbol_count: self.count_symbols(&structure),
-            variable_count: self.count_variables(&structure),
+            depth: structure.depth(),
+            symbol_count: structure.symbol_count(),
+            variable_count: structure.variable_count(),
             created_at: std::time::SystemTime::now(),
         };
         
-        let expr = StoredExpression {
-            id,
-            structure: structure.clone(),
-            metadata,
-        };
-        
-        self.expressions.insert(id, expr);
-        self.index_expression(id, &structure);
+        let stored = self.store_structure(&structure);
+        self.index_expression(id, &stored);
+        self.expressions.insert(id, (stored, metadata));
         
         id
     }
@@ -153,13 +296,13 @@ impl ExprQueryEngine {
             };
         }
         
-        // Start with the first pattern
-        let mut result_ids: std::collections::HashSet<ExprId> = 
+        // Start with the first pattern; BTreeSet keeps the ids in ascending order
+        let mut result_ids: BTreeSet<ExprId> = 
             self.find_matches(&patterns[0], &mut stats).into_iter().collect();
         
         // Intersect with results from subsequent patterns
         for pattern in &patterns[1..] {
-            let matches: std::collections::HashSet<ExprId> = 
+            let matches: BTreeSet<ExprId> = 
                 self.find_matches(pattern, &mut stats).into_iter().collect();
             result_ids = result_ids.intersection(&matches).cloned().collect();
         }
@@ -180,10 +323,10 @@ impl ExprQueryEngine {
             filters_applied: 0,
         };
         
-        let mut result_ids: std::collections::HashSet<ExprId> = std::collections::HashSet::new();
+        let mut result_ids: BTreeSet<ExprId> = BTreeSet::new();
         
         for pattern in patterns {
-            let matches: std::collections::HashSet<ExprId> = 
+            let matches: BTreeSet<ExprId> = 
                 self.find_matches(pattern, &mut stats).into_iter().collect();
             result_ids = result_ids.union(&matches).cloned().collect();
         }
@@ -195,9 +338,13 @@ impl ExprQueryEngine {
         }
     }
     
-    /// Get expression by ID
-    pub fn get_expression(&self, id: ExprId) -> Option<&StoredExpression> {
-        self.expressions.get(&id)
+    /// Get expression by ID; interned symbols are resolved back to their bytes
+    pub fn get_expression(&self, id: ExprId) -> Option<StoredExpression> {
+        self.expressions.get(&id).map(|(stored, metadata)| StoredExpression {
+            id,
+            structure: self.load_structure(stored),
+            metadata: metadata.clone(),
+        })
     }
     
     /// Get all expressions with a specific arity
@@ -207,24 +354,33 @@ impl ExprQueryEngine {
     
     /// Get all expressions containing a specific symbol
     pub fn query_by_symbol(&self, symbol: &[u8]) -> Vec<ExprId> {
-        self.symbol_index.get(symbol).cloned().unwrap_or_default()
+        let key = self.normalize_symbol(symbol);
+        if self.config.intern_symbols {
+            return self.interner.get(&key)
+                .and_then(|id| self.interned_index.get(&id))
+                .cloned()
+                .unwrap_or_default();
+        }
+        self.symbol_index.get(key.as_ref()).cloned().unwrap_or_default()
     }
     
     /// Remove an expression from the query engine
     pub fn remove(&mut self, id: ExprId) -> Option<StoredExpression> {
-        if let Some(expr) = self.expressions.remove(&id) {
-            self.unindex_expression(id, &expr.structure);
-            Some(expr)
-        } else {
-            None
-        }
+        let (stored, metadata) = self.expressions.remove(&id)?;
+        self.unindex_expression(id, &stored);
+        Some(StoredExpression { id, structure: self.load_structure(&stored), metadata })
+    }
+    
+    /// The symbol table used when `intern_symbols` is enabled
+    pub fn interner(&self) -> &SymbolInterner {
+        &self.interner
     }
     
     /// Get statistics about the query engine
     pub fn stats(&self) -> EngineStats {
         EngineStats {
             total_expressions: self.expressions.len(),
-            unique_symbols: self.symbol_index.len(),
+            unique_symbols: if self.config.intern_symbols { self.interned_index.len() } else { self.symbol_index.len() },
             indexed_arities: self.arity_index.len(),
             structure_index_size: self.structure_index.len(),
         }
@@ -247,8 +403,8 @@ impl ExprQueryEngine {
                 stats.expressions_scanned += self.expressions.len();
                 stats.filters_applied += 1;
                 self.expressions.iter()
-                    .filter_map(|(id, expr)| {
-                        if self.matches_pattern(&expr.structure, pattern) {
+                    .filter_map(|(id, (stored, _))| {
+                        if self.matches_pattern(stored, pattern) {
                             Some(*id)
                         } else {
                             None
@@ -263,8 +419,8 @@ impl ExprQueryEngine {
                 // Further filter by pattern matching
                 stats.filters_applied += 1;
                 candidates.retain(|&id| {
-                    if let Some(expr) = self.expressions.get(&id) {
-                        self.matches_pattern(&expr.structure, pattern)
+                    if let Some((stored, _)) = self.expressions.get(&id) {
+                        self.matches_pattern(stored, pattern)
                     } else {
                         false
                     }
@@ -277,8 +433,8 @@ impl ExprQueryEngine {
                 stats.expressions_scanned += self.expressions.len();
                 stats.filters_applied += 1;
                 self.expressions.iter()
-                    .filter_map(|(id, expr)| {
-                        if self.matches_pattern(&expr.structure, pattern) {
+                    .filter_map(|(id, (stored, _))| {
+                        if self.matches_pattern(stored, pattern) {
                             Some(*id)
                         } else {
                             None
@@ -289,12 +445,13 @@ impl ExprQueryEngine {
         }
     }
     
-    fn matches_pattern(&self, structure: &ExprStructure, pattern: &ExprPattern) -> bool {
+    fn matches_pattern(&self, structure: &StoredStructure, pattern: &ExprPattern) -> bool {
         match (structure, pattern) {
             (_, ExprPattern::Any) => true,
-            (ExprStructure::Symbol(s), ExprPattern::Symbol(p)) => s == p,
-            (ExprStructure::Variable(v), ExprPattern::Variable(p)) => v == p,
-            (ExprStructure::Compound { arity: sa, children: sc }, 
+            (StoredStructure::Symbol(_) | StoredStructure::Interned(_), ExprPattern::Symbol(p)) =>
+                self.symbol_bytes(structure).is_some_and(|s| self.normalize_symbol(s) == self.normalize_symbol(p)),
+            (StoredStructure::Variable(v), ExprPattern::Variable(p)) => v == p,
+            (StoredStructure::Compound { arity: sa, children: sc }, 
              ExprPattern::Compound { arity: pa, patterns: pp }) => {
                 sa == pa && sc.len() == pp.len() && 
                 sc.iter().zip(pp.iter()).all(|(child, pat)| self.matches_pattern(child, pat))
@@ -304,15 +461,59 @@ impl ExprQueryEngine {
         }
     }
     
-    fn index_expression(&mut self, id: ExprId, structure: &ExprStructure) {
+    /// `structure` as the engine keeps it, interning its symbols if `intern_symbols` is enabled
+    fn store_structure(&mut self, structure: &ExprStructure) -> StoredStructure {
         match structure {
-            ExprStructure::Symbol(symbol) => {
-                self.symbol_index.entry(symbol.clone()).or_default().push(id);
+            ExprStructure::Symbol(symbol) if self.config.intern_symbols => StoredStructure::Interned(self.interner.intern(symbol)),
+            ExprStructure::Symbol(symbol) => StoredStructure::Symbol(symbol.clone()),
+            ExprStructure::Variable(v) => StoredStructure::Variable(v.clone()),
+            ExprStructure::Compound { arity, children } => StoredStructure::Compound {
+                arity: *arity,
+                children: children.iter().map(|c| self.store_structure(c)).collect(),
             },
-            ExprStructure::Variable(_) => {
+        }
+    }
+    
+    fn load_structure(&self, stored: &StoredStructure) -> ExprStructure {
+        match stored {
+            StoredStructure::Symbol(_) | StoredStructure::Interned(_) => ExprStructure::Symbol(self.symbol_bytes(stored).unwrap_or_default().to_vec()),
+            StoredStructure::Variable(v) => ExprStructure::Variable(v.clone()),
+            StoredStructure::Compound { arity, children } => ExprStructure::Compound {
+                arity: *arity,
+                children: children.iter().map(|c| self.load_structure(c)).collect(),
+            },
+        }
+    }
+    
+    /// The bytes of a stored symbol, resolving interned ones
+    fn symbol_bytes<'a>(&'a self, stored: &'a StoredStructure) -> Option<&'a [u8]> {
+        match stored {
+            StoredStructure::Symbol(symbol) => Some(symbol),
+            StoredStructure::Interned(symbol_id) => self.interner.resolve(*symbol_id),
+            _ => None,
+        }
+    }
+    
+    fn index_expression(&mut self, id: ExprId, structure: &StoredStructure) {
+        match structure {
+            StoredStructure::Symbol(symbol) => {
+                let key = self.normalize_symbol(symbol).into_owned();
+                self.symbol_index.entry(key).or_default().push(id);
+            },
+            StoredStructure::Interned(symbol_id) => {
+                // case-insensitive engines index the normalized spelling, which may be a different symbol
+                let symbol_id = if self.config.case_insensitive {
+                    let key = self.interner.resolve(*symbol_id).unwrap_or_default().to_ascii_lowercase();
+                    self.interner.intern(&key)
+                } else {
+                    *symbol_id
+                };
+                self.interned_index.entry(symbol_id).or_default().push(id);
+            },
+            StoredStructure::Variable(_) => {
                 // Variables indexed separately if needed
             },
-            ExprStructure::Compound { arity, children } => {
+            StoredStructure::Compound { arity, children } => {
                 self.arity_index.entry(*arity).or_default().push(id);
                 
                 // Index children recursively
@@ -327,20 +528,33 @@ impl ExprQueryEngine {
         }
     }
     
-    fn unindex_expression(&mut self, id: ExprId, structure: &ExprStructure) {
+    fn unindex_expression(&mut self, id: ExprId, structure: &StoredStructure) {
         match structure {
-            ExprStructure::Symbol(symbol) => {
-                if let Some(ids) = self.symbol_index.get_mut(symbol) {
+            StoredStructure::Symbol(symbol) => {
+                let key = self.normalize_symbol(symbol).into_owned();
+                if let Some(ids) = self.symbol_index.get_mut(&key) {
                     ids.retain(|&x| x != id);
                     if ids.is_empty() {
-                        self.symbol_index.remove(symbol);
+                        self.symbol_index.remove(&key);
                     }
                 }
             },
-            ExprStructure::Variable(_) => {
+            StoredStructure::Interned(symbol_id) => {
+                // The interned id stays allocated so that ids remain stable
+                let key = self.normalize_symbol(self.interner.resolve(*symbol_id).unwrap_or_default()).into_owned();
+                if let Some(symbol_id) = self.interner.get(&key) {
+                    if let Some(ids) = self.interned_index.get_mut(&symbol_id) {
+                        ids.retain(|&x| x != id);
+                        if ids.is_empty() {
+                            self.interned_index.remove(&symbol_id);
+                        }
+                    }
+                }
+            },
+            StoredStructure::Variable(_) => {
                 // Remove from variable index if implemented
             },
-            ExprStructure::Compound { arity, children } => {
+            StoredStructure::Compound { arity, children } => {
                 if let Some(ids) = self.arity_index.get_mut(arity) {
                     ids.retain(|&x| x != id);
                     if ids.is_empty() {
@@ -360,23 +574,31 @@ impl ExprQueryEngine {
         }
     }
     
-    fn create_structural_key(&self, structure: &ExprStructure) -> Vec<u8> {
+    fn normalize_symbol<'a>(&self, symbol: &'a [u8]) -> Cow<'a, [u8]> {
+        if self.config.case_insensitive {
+            Cow::Owned(symbol.to_ascii_lowercase())
+        } else {
+            Cow::Borrowed(symbol)
+        }
+    }
+    
+    fn create_structural_key(&self, structure: &StoredStructure) -> Vec<u8> {
         let mut key = Vec::new();
         self.encode_structure(&mut key, structure);
         key
     }
     
-    fn encode_structure(&self, buffer: &mut Vec<u8>, structure: &ExprStructure) {
+    fn encode_structure(&self, buffer: &mut Vec<u8>, structure: &StoredStructure) {
         match structure {
-            ExprStructure::Symbol(symbol) => {
+            StoredStructure::Symbol(_) | StoredStructure::Interned(_) => {
                 buffer.push(1); // Symbol marker
-                buffer.extend_from_slice(symbol);
+                buffer.extend_from_slice(self.symbol_bytes(structure).unwrap_or_default());
             },
-            ExprStructure::Variable(var) => {
+            StoredStructure::Variable(var) => {
                 buffer.push(2); // Variable marker
                 buffer.extend_from_slice(var.as_bytes());
             },
-            ExprStructure::Compound { arity, children } => {
+            StoredStructure::Compound { arity, children } => {
                 buffer.push(3); // Compound marker
                 buffer.extend_from_slice(&arity.to_be_bytes());
                 for child in children {
@@ -385,35 +607,6 @@ impl ExprQueryEngine {
             },
         }
     }
-    
-    fn calculate_depth(&self, structure: &ExprStructure) -> usize {
-        match structure {
-            ExprStructure::Symbol(_) | ExprStructure::Variable(_) => 1,
-            ExprStructure::Compound { children, .. } => {
-                1 + children.iter().map(|c| self.calculate_depth(c)).max().unwrap_or(0)
-            },
-        }
-    }
-    
-    fn count_symbols(&self, structure: &ExprStructure) -> usize {
-        match structure {
-            ExprStructure::Symbol(_) => 1,
-            ExprStructure::Variable(_) => 0,
-            ExprStructure::Compound { children, .. } => {
-                children.iter().map(|c| self.count_symbols(c)).sum()
-            },
-        }
-    }
-    
-    fn count_variables(&self, structure: &ExprStructure) -> usize {
-        match structure {
-            ExprStructure::Symbol(_) => 0,
-            ExprStructure::Variable(_) => 1,
-            ExprStructure::Compound { children, .. } => {
-                children.iter().map(|c| self.count_variables(c)).sum()
-            },
-        }
-    }
 }
 
 /// Statistics about the query engine
@@ -531,6 +724,212 @@ mod tests {
         assert_eq!(stats.unique_symbols, 2);
     }
     
+    #[test]
+    fn test_case_insensitive_symbols() {
+        let mut engine = ExprQueryEngine::with_config(ExprQueryConfig { case_insensitive: true, ..ExprQueryConfig::default() });
+        
+        let id1 = engine.insert(ExprStructure::Symbol(b"add".to_vec()));
+        let id2 = engine.insert(ExprStructure::Compound {
+            arity: 2,
+            children: vec![
+                ExprStructure::Symbol(b"MUL".to_vec()),
+                ExprStructure::Variable("x".to_string()),
+            ],
+        });
+        
+        assert_eq!(engine.query_by_symbol(b"Add"), vec![id1]);
+        assert_eq!(engine.query_by_symbol(b"mul"), vec![id2]);
+        let result = engine.query(&ExprPattern::Symbol(b"ADD".to_vec()));
+        assert_eq!(result.matched_ids, vec![id1]);
+        
+        // Original case is preserved in storage
+        let stored = engine.get_expression(id2).unwrap();
+        if let ExprStructure::Compound { children, .. } = &stored.structure {
+            assert_eq!(children[0], ExprStructure::Symbol(b"MUL".to_vec()));
+        } else {
+            panic!("expected compound");
+        }
+        
+        // Case-sensitive engines keep distinguishing case
+        let mut strict = ExprQueryEngine::new();
+        strict.insert(ExprStructure::Symbol(b"add".to_vec()));
+        assert!(strict.query_by_symbol(b"Add").is_empty());
+    }
+    
+    #[test]
+    fn test_structure_measures_match_metadata() {
+        let mut engine = ExprQueryEngine::new();
+        let expr = ExprStructure::Compound {
+            arity: 3,
+            children: vec![
+                ExprStructure::Symbol(b"f".to_vec()),
+                ExprStructure::Variable("x".to_string()),
+                ExprStructure::Compound {
+                    arity: 2,
+                    children: vec![
+                        ExprStructure::Variable("x".to_string()),
+                        ExprStructure::Symbol(b"a".to_vec()),
+                    ],
+                },
+            ],
+        };
+        
+        assert_eq!((expr.depth(), expr.size(), expr.symbol_count(), expr.variable_count()), (3, 6, 2, 2));
+        let id = engine.insert(expr.clone());
+        let metadata = &engine.get_expression(id).unwrap().metadata;
+        assert_eq!(metadata.depth, expr.depth());
+        assert_eq!(metadata.symbol_count, expr.symbol_count());
+        assert_eq!(metadata.variable_count, expr.variable_count());
+    }
+    
+    #[test]
+    fn test_fold_depth() {
+        let expr = ExprStructure::Compound {
+            arity: 3,
+            children: vec![
+                ExprStructure::Symbol(b"f".to_vec()),
+                ExprStructure::Variable("x".to_string()),
+                ExprStructure::Compound {
+                    arity: 2,
+                    children: vec![
+                        ExprStructure::Symbol(b"g".to_vec()),
+                        ExprStructure::Symbol(b"a".to_vec()),
+                    ],
+                },
+            ],
+        };
+        
+        let depth = expr.fold(&mut |_| 1, &mut |_| 1, &mut |_, depths: Vec<usize>| 1 + depths.into_iter().max().unwrap_or(0));
+        assert_eq!(depth, 3);
+        assert_eq!(depth, expr.depth());
+        
+        let symbols = expr.fold(&mut |s| vec![s.to_vec()], &mut |_| vec![], &mut |_, parts: Vec<Vec<Vec<u8>>>| parts.concat());
+        assert_eq!(symbols, vec![b"f".to_vec(), b"g".to_vec(), b"a".to_vec()]);
+    }
+    
+    #[test]
+    fn test_rewrite_symbols() {
+        let x = || ExprStructure::Symbol(b"x".to_vec());
+        let expr = ExprStructure::Compound {
+            arity: 3,
+            children: vec![
+                ExprStructure::Symbol(b"f".to_vec()),
+                x(),
+                ExprStructure::Compound { arity: 2, children: vec![ExprStructure::Symbol(b"g".to_vec()), x()] },
+            ],
+        };
+        
+        let rewritten = expr.rewrite(&mut |node| match node {
+            ExprStructure::Symbol(s) if s == b"x" => Some(ExprStructure::Symbol(b"y".to_vec())),
+            _ => None,
+        });
+        
+        let y = || ExprStructure::Symbol(b"y".to_vec());
+        assert_eq!(rewritten, ExprStructure::Compound {
+            arity: 3,
+            children: vec![
+                ExprStructure::Symbol(b"f".to_vec()),
+                y(),
+                ExprStructure::Compound { arity: 2, children: vec![ExprStructure::Symbol(b"g".to_vec()), y()] },
+            ],
+        });
+    }
+    
+    #[test]
+    fn test_free_variables() {
+        let var = |name: &str| ExprStructure::Variable(name.to_string());
+        // (f $x (g $y $x))
+        let expr = ExprStructure::Compound {
+            arity: 3,
+            children: vec![
+                ExprStructure::Symbol(b"f".to_vec()),
+                var("x"),
+                ExprStructure::Compound { arity: 3, children: vec![ExprStructure::Symbol(b"g".to_vec()), var("y"), var("x")] },
+            ],
+        };
+        assert_eq!(expr.free_variables(), vec!["x".to_string(), "y".to_string()]);
+        assert!(ExprStructure::Symbol(b"a".to_vec()).free_variables().is_empty());
+    }
+    
+    #[test]
+    fn test_same_shape() {
+        let var = |name: &str| ExprStructure::Variable(name.to_string());
+        let app = |children: Vec<ExprStructure>| ExprStructure::Compound { arity: children.len(), children };
+        let f = || ExprStructure::Symbol(b"f".to_vec());
+        let g = || ExprStructure::Symbol(b"g".to_vec());
+        assert!(app(vec![f(), var("x"), var("y")]).same_shape(&app(vec![f(), var("a"), var("a")])));
+        assert!(!app(vec![f(), var("x")]).same_shape(&app(vec![g(), var("x")])));
+        assert!(!app(vec![f(), var("x")]).same_shape(&app(vec![f(), f()])));
+        assert!(!app(vec![f(), var("x")]).same_shape(&app(vec![f(), var("x"), var("y")])));
+    }
+    
+    #[test]
+    fn test_symbol_interning() {
+        let mut engine = ExprQueryEngine::with_config(ExprQueryConfig {
+            intern_symbols: true,
+            ..ExprQueryConfig::default()
+        });
+        let symbols: [&[u8]; 5] = [b"add", b"sub", b"x", b"y", b"z"];
+        for i in 0..10_000 {
+            engine.insert(ExprStructure::Compound {
+                arity: 3,
+                children: vec![
+                    ExprStructure::Symbol(symbols[i % 2].to_vec()),
+                    ExprStructure::Symbol(symbols[2 + i % 3].to_vec()),
+                    ExprStructure::Symbol(symbols[2 + (i + 1) % 3].to_vec()),
+                ],
+            });
+        }
+        
+        assert_eq!(engine.interner().len(), symbols.len());
+        assert_eq!(engine.stats().unique_symbols, symbols.len());
+        assert_eq!(engine.query_by_symbol(b"add").len(), 5_000);
+        assert!(engine.query_by_symbol(b"mul").is_empty());
+        
+        let id = engine.interner().get(b"x").unwrap();
+        assert_eq!(engine.interner().resolve(id), Some(&b"x"[..]));
+        
+        // Stored expressions come back with their symbols resolved
+        let first = engine.query_by_symbol(b"add")[0];
+        assert_eq!(engine.get_expression(first).unwrap().structure, ExprStructure::Compound {
+            arity: 3,
+            children: vec![
+                ExprStructure::Symbol(b"add".to_vec()),
+                ExprStructure::Symbol(b"x".to_vec()),
+                ExprStructure::Symbol(b"y".to_vec()),
+            ],
+        });
+        let matched = engine.query(&ExprPattern::Compound {
+            arity: 3,
+            patterns: vec![ExprPattern::Symbol(b"sub".to_vec()), ExprPattern::Symbol(b"y".to_vec()), ExprPattern::Any],
+        });
+        assert_eq!(matched.matched_ids.len(), 1_667);
+        assert_eq!(engine.remove(first).unwrap().structure.symbol_count(), 3);
+        assert_eq!(engine.query_by_symbol(b"add").len(), 4_999);
+    }
+    
+    #[test]
+    fn test_or_query_order() {
+        let mut engine = ExprQueryEngine::new();
+        for i in 0..50 {
+            let op: &[u8] = if i % 2 == 0 { b"add" } else { b"sub" };
+            engine.insert(ExprStructure::Compound {
+                arity: 2,
+                children: vec![ExprStructure::Symbol(op.to_vec()), ExprStructure::Symbol(i.to_string().into_bytes())],
+            });
+        }
+        
+        let patterns = [ExprPattern::Symbol(b"sub".to_vec()), ExprPattern::Symbol(b"add".to_vec())];
+        let first = engine.query_or(&patterns).matched_ids;
+        let second = engine.query_or(&patterns).matched_ids;
+        assert_eq!(first, second);
+        assert_eq!(first, (1..=50).collect::<Vec<ExprId>>());
+        
+        let and = engine.query_and(&[ExprPattern::Symbol(b"add".to_vec()), ExprPattern::Compound { arity: 2, patterns: vec![ExprPattern::Any, ExprPattern::Any] }]).matched_ids;
+        assert!(and.windows(2).all(|w| w[0] < w[1]));
+        assert_eq!(and.len(), 25);
+    }
+    
     #[test]
     fn test_remove_expression() {
         let mut engine = ExprQueryEngine::new();
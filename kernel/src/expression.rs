@@ -0,0 +1,58 @@
+use crate::space::{serialize_fact, subexpr_len};
+#[cfg(feature="interning")]
+use crate::space::symbol_bytes;
+use crate::stubs::{byte_item, Expr, OwnedExpr, SharedMappingHandle, Tag};
+
+/// An owned expression with accessors for its parts, so results need not be taken apart byte by byte
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Expression(pub OwnedExpr);
+
+impl From<OwnedExpr> for Expression {
+    fn from(e: OwnedExpr) -> Self { Self(e) }
+}
+
+impl Expression {
+    pub fn as_expr(&self) -> Expr {
+        self.0.borrow()
+    }
+
+    /// The number of children if this is a compound, `None` for a symbol or variable
+    pub fn arity(&self) -> Option<u8> {
+        match byte_item(*self.0.as_slice().first()?) {
+            Tag::Arity(a) => Some(a),
+            _ => None,
+        }
+    }
+
+    /// The `i`-th (0-based) child of a compound
+    pub fn child(&self, i: usize) -> Option<Expression> {
+        if i >= self.arity()? as usize { return None }
+        let bytes = self.0.as_slice();
+        let mut start = 1;
+        for _ in 0..i { start += subexpr_len(bytes, start) }
+        Some(Self(OwnedExpr::from(bytes[start..start + subexpr_len(bytes, start)].to_vec())))
+    }
+
+    /// The text of this expression if it is a symbol, resolved through `sm` when interned
+    #[cfg_attr(not(feature="interning"), allow(unused_variables))]
+    pub fn symbol(&self, sm: &SharedMappingHandle) -> Option<Vec<u8>> {
+        let bytes = self.0.as_slice();
+        let Tag::SymbolSize(n) = byte_item(*bytes.first()?) else { return None };
+        let stored = &bytes[1..1 + n as usize];
+        #[cfg(feature="interning")]
+        let stored = symbol_bytes(sm, stored);
+        Some(stored.to_vec())
+    }
+
+    /// The text of the first child of a compound, if that child is a symbol
+    pub fn head_symbol(&self, sm: &SharedMappingHandle) -> Option<Vec<u8>> {
+        self.child(0)?.symbol(sm)
+    }
+
+    /// The expression as `dump_sexpr` would write it
+    pub fn to_sexpr(&self, sm: &SharedMappingHandle) -> String {
+        let mut out = vec![];
+        serialize_fact(sm, self.as_expr(), &mut out);
+        String::from_utf8(out).unwrap()
+    }
+}
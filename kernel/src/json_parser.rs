@@ -16,6 +16,12 @@ pub enum Error {
     UnexpectedEndOfJson,
     ExceededDepthLimit,
     FailedUtf8Parsing,
+    /// `NaN`, `Infinity` or `-Infinity`, which JSON does not allow unless the parser is told to
+    NonFiniteNumber {
+        literal: &'static str,
+        line: usize,
+        column: usize,
+    },
     #[allow(unused)]
     WrongType(String),
 }
@@ -41,6 +47,11 @@ impl fmt::Display for Error {
             UnexpectedEndOfJson   => write!(f, "Unexpected end of JSON"),
             ExceededDepthLimit    => write!(f, "Exceeded depth limit"),
             FailedUtf8Parsing     => write!(f, "Failed to parse UTF-8 bytes"),
+            NonFiniteNumber {
+                ref literal,
+                ref line,
+                ref column,
+            } => write!(f, "Non-finite number {} at ({}:{})", literal, line, column),
             WrongType(ref s)      => write!(f, "Wrong type, expected: {}", s),
         }
     }
@@ -55,6 +66,7 @@ impl error::Error for Error {
             UnexpectedEndOfJson        => "Unexpected end of JSON",
             ExceededDepthLimit         => "Exceeded depth limit",
             FailedUtf8Parsing          => "Failed to read bytes as UTF-8 from JSON",
+            NonFiniteNumber { .. }     => "Non-finite number",
             WrongType(_)               => "Wrong type",
         }
     }
@@ -71,6 +83,8 @@ pub (crate) trait Transcriber {
 
     fn write_string(&mut self, s: &str) -> ();
     fn write_number(&mut self, negative: bool, mantissa: u64, exponent: i16) -> ();
+    /// Only called when the parser allows non-finite numbers; `literal` is `NaN`, `Infinity` or `-Infinity`
+    fn write_non_finite(&mut self, literal: &str) -> ();
     fn write_true(&mut self) -> ();
     fn write_false(&mut self) -> ();
     fn write_null(&mut self) -> ();
@@ -94,6 +108,7 @@ impl Transcriber for DebugTranscriber {
         if negative { if exponent != 0 { println!("write {}e{}", mantissa, exponent) } else { println!("write {}", mantissa) } }
         else { if exponent != 0 { println!("write -{}e{}", mantissa, exponent) } else { println!("write -{}", mantissa) } }
     }
+    fn write_non_finite(&mut self, literal: &str) -> () { println!("write {}", literal) }
     fn write_true(&mut self) -> () { println!("write true") }
     fn write_false(&mut self) -> () { println!("write false") }
     fn write_null(&mut self) -> () { println!("write null") }
@@ -117,6 +132,7 @@ impl <W : Write> Transcriber for WriteTranscriber<W> {
         self.w.write(mantissa.to_string().as_bytes()).unwrap();
         if exponent != 0 { self.w.write("e".as_bytes()).unwrap(); self.w.write(exponent.to_string().as_bytes()).unwrap(); }
     }
+    fn write_non_finite(&mut self, literal: &str) -> () { self.w.write(literal.as_bytes()).unwrap(); }
     fn write_true(&mut self) -> () { self.w.write("true".as_bytes()).unwrap(); }
     fn write_false(&mut self) -> () { self.w.write("false".as_bytes()).unwrap(); }
     fn write_null(&mut self) -> () { self.w.write("null".as_bytes()).unwrap(); }
@@ -151,6 +167,9 @@ pub (crate) struct Parser<'a> {
 
     // Length of the source
     length: usize,
+
+    // Accept `NaN`, `Infinity` and `-Infinity` as numbers
+    allow_non_finite: bool,
 }
 
 // Read a byte from the source.
@@ -445,9 +464,34 @@ impl<'a> Parser<'a> {
             byte_ptr: source.as_ptr(),
             index: 0,
             length: source.len(),
+            allow_non_finite: false,
         }
     }
 
+    /// Accept the `NaN`, `Infinity` and `-Infinity` some producers emit, passing them to
+    /// `Transcriber::write_non_finite`, instead of failing with `Error::NonFiniteNumber`
+    pub fn allow_non_finite(mut self, allow: bool) -> Self {
+        self.allow_non_finite = allow;
+        self
+    }
+
+    // Called once a whole non-finite literal starting at `start` has been read
+    fn non_finite<T : Transcriber>(&mut self, t: &mut T, literal: &'static str, start: usize) -> Result<()> {
+        if self.allow_non_finite { t.write_non_finite(literal); return Ok(()) }
+
+        let (lineno, col) = self.source[..start]
+            .lines()
+            .enumerate()
+            .last()
+            .unwrap_or((0, ""));
+
+        Err(Error::NonFiniteNumber {
+            literal: literal,
+            line: lineno + 1,
+            column: col.chars().count() + 1,
+        })
+    }
+
     // Check if we are at the end of the source.
     #[inline(always)]
     fn is_eof(&mut self) -> bool {
@@ -778,6 +822,10 @@ impl<'a> Parser<'a> {
                             expect_number!(self, _mantissa, exponent, ch);
                             t.write_number(true, _mantissa, exponent);
                         },
+                        b'I' => {
+                            expect_sequence!(self, b'n', b'f', b'i', b'n', b'i', b't', b'y');
+                            self.non_finite(t, "-Infinity", self.index - 9)?;
+                        },
                         _    => return self.unexpected_character()
                     };
                 }
@@ -793,6 +841,14 @@ impl<'a> Parser<'a> {
                     expect_sequence!(self, b'u', b'l', b'l');
                     t.write_null();
                 },
+                b'N' => {
+                    expect_sequence!(self, b'a', b'N');
+                    self.non_finite(t, "NaN", self.index - 3)?;
+                },
+                b'I' => {
+                    expect_sequence!(self, b'n', b'f', b'i', b'n', b'i', b't', b'y');
+                    self.non_finite(t, "Infinity", self.index - 8)?;
+                },
                 _    => return self.unexpected_character()
             };
 
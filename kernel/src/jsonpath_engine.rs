@@ -3,11 +3,15 @@
 
 use std::collections::{HashMap, VecDeque};
 use serde_json::{Value, Map};
+use regex::Regex;
+use crate::lru::LruMap;
 
 /// JSONPath query engine for structured JSON access
 pub struct JsonPathEngine {
     /// Cache for compiled JSONPath expressions
-    compiled_cache: HashMap<String, CompiledPath>,
+    compiled_cache: LruMap<String, CompiledPath>,
+    /// Entries dropped to stay within `max_cache_entries`
+    cache_evictions: usize,
     /// Configuration options
     config: JsonPathConfig,
 }
@@ -19,8 +23,22 @@ pub struct JsonPathConfig {
     pub max_depth: usize,
     /// Whether to cache compiled paths
     pub enable_caching: bool,
+    /// Most compiled paths kept; the least recently used one is evicted to make room
+    pub max_cache_entries: usize,
     /// Whether to allow non-standard extensions
     pub allow_extensions: bool,
+    /// How result paths are spelled
+    pub path_style: PathStyle,
+}
+
+/// Notation used for the paths reported alongside query results
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PathStyle {
+    /// `$.store.book[0]`
+    #[default]
+    Dot,
+    /// `$['store']['book'][0]`
+    Bracket,
 }
 
 impl Default for JsonPathConfig {
@@ -28,7 +46,9 @@ impl Default for JsonPathConfig {
         Self {
             max_depth: 100,
             enable_caching: true,
+            max_cache_entries: 1024,
             allow_extensions: false,
+            path_style: PathStyle::Dot,
         }
     }
 }
@@ -55,6 +75,8 @@ pub enum PathSegment {
     Slice { start: Option<i64>, end: Option<i64>, step: Option<i64> },
     /// Wildcard (*) - all children
     Wildcard,
+    /// Object members whose key matches a regex [~/pattern/]
+    KeyRegex(String),
     /// Recursive descent (..)
     RecursiveDescent,
     /// Filter expression [?(...)]
@@ -143,7 +165,8 @@ impl JsonPathEngine {
     
     pub fn with_config(config: JsonPathConfig) -> Self {
         Self {
-            compiled_cache: HashMap::new(),
+            compiled_cache: LruMap::new(config.max_cache_entries),
+            cache_evictions: 0,
             config,
         }
     }
@@ -158,7 +181,7 @@ impl JsonPathEngine {
                 (cached.clone(), true)
             } else {
                 let compiled = self.compile_path(path)?;
-                self.compiled_cache.insert(path.to_string(), compiled.clone());
+                self.cache_evictions += self.compiled_cache.insert(path.to_string(), compiled.clone());
                 (compiled, false)
             }
         } else {
@@ -168,9 +191,11 @@ impl JsonPathEngine {
         let mut context = EvaluationContext::new(&self.config);
         let results = self.evaluate_path(json, &compiled, &mut context)?;
         
+        let (values, paths) = results.into_iter().map(|r| (r.value.clone(), r.path)).unzip();
+        
         Ok(QueryResult {
-            values: results.into_iter().map(|r| r.value).collect(),
-            paths: results.into_iter().map(|r| r.path).collect(),
+            values,
+            paths,
             execution_time: start_time.elapsed(),
             cache_hit,
         })
@@ -208,14 +233,15 @@ impl JsonPathEngine {
         CacheStats {
             size: self.compiled_cache.len(),
             enabled: self.config.enable_caching,
+            evictions: self.cache_evictions,
         }
     }
     
     // Private evaluation methods
     
-    fn evaluate_path(&self, json: &Value, compiled: &CompiledPath, context: &mut EvaluationContext) -> Result<Vec<EvaluationResult>, JsonPathError> {
+    fn evaluate_path<'a>(&self, json: &'a Value, compiled: &CompiledPath, context: &mut EvaluationContext) -> Result<Vec<EvaluationResult<'a>>, JsonPathError> {
         let mut results = vec![EvaluationResult {
-            value: json.clone(),
+            value: json,
             path: if compiled.is_absolute { "$".to_string() } else { "@".to_string() },
         }];
         
@@ -226,14 +252,19 @@ impl JsonPathEngine {
         Ok(results)
     }
     
-    fn apply_segment(&self, inputs: Vec<EvaluationResult>, segment: &PathSegment, context: &mut EvaluationContext) -> Result<Vec<EvaluationResult>, JsonPathError> {
+    fn apply_segment<'a>(&self, inputs: Vec<EvaluationResult<'a>>, segment: &PathSegment, context: &mut EvaluationContext) -> Result<Vec<EvaluationResult<'a>>, JsonPathError> {
         let mut outputs = Vec::new();
+        // Compiled once for all the inputs rather than per object
+        let key_regex = match segment {
+            PathSegment::KeyRegex(pattern) => Some(compile_key_regex(pattern)?),
+            _ => None,
+        };
         
         for input in inputs {
             match segment {
                 PathSegment::Root => {
                     outputs.push(EvaluationResult {
-                        value: input.value.clone(),
+                        value: input.value,
                         path: "$".to_string(),
                     });
                 },
@@ -252,6 +283,9 @@ impl JsonPathEngine {
                 PathSegment::Wildcard => {
                     self.apply_wildcard(&input, &mut outputs)?;
                 },
+                PathSegment::KeyRegex(_) => {
+                    self.apply_key_regex(&input, key_regex.as_ref().unwrap(), &mut outputs);
+                },
                 PathSegment::RecursiveDescent => {
                     self.apply_recursive_descent(&input, &mut outputs, context)?;
                 },
@@ -271,35 +305,48 @@ impl JsonPathEngine {
         Ok(outputs)
     }
     
-    fn apply_child_access(&self, input: &EvaluationResult, key: &str, outputs: &mut Vec<EvaluationResult>) -> Result<(), JsonPathError> {
-        if let Value::Object(obj) = &input.value {
+    /// Path of the member `key` of the value at `base`
+    fn key_path(&self, base: &str, key: &str) -> String {
+        match self.config.path_style {
+            PathStyle::Dot => format!("{}.{}", base, key),
+            PathStyle::Bracket => format!("{}['{}']", base, key.replace('\\', "\\\\").replace('\'', "\\'")),
+        }
+    }
+    
+    /// Path of element `idx` of the array at `base`
+    fn index_path(&self, base: &str, idx: impl std::fmt::Display) -> String {
+        format!("{}[{}]", base, idx)
+    }
+    
+    fn apply_child_access<'a>(&self, input: &EvaluationResult<'a>, key: &str, outputs: &mut Vec<EvaluationResult<'a>>) -> Result<(), JsonPathError> {
+        if let Value::Object(obj) = input.value {
             if let Some(value) = obj.get(key) {
                 outputs.push(EvaluationResult {
-                    value: value.clone(),
-                    path: format!("{}.{}", input.path, key),
+                    value,
+                    path: self.key_path(&input.path, key),
                 });
             }
         }
         Ok(())
     }
     
-    fn apply_index_access(&self, input: &EvaluationResult, idx: i64, outputs: &mut Vec<EvaluationResult>) -> Result<(), JsonPathError> {
-        if let Value::Array(arr) = &input.value {
+    fn apply_index_access<'a>(&self, input: &EvaluationResult<'a>, idx: i64, outputs: &mut Vec<EvaluationResult<'a>>) -> Result<(), JsonPathError> {
+        if let Value::Array(arr) = input.value {
             let len = arr.len() as i64;
             let index = if idx < 0 { len + idx } else { idx };
             
             if index >= 0 && (index as usize) < arr.len() {
                 outputs.push(EvaluationResult {
-                    value: arr[index as usize].clone(),
-                    path: format!("{}[{}]", input.path, idx),
+                    value: &arr[index as usize],
+                    path: self.index_path(&input.path, idx),
                 });
             }
         }
         Ok(())
     }
     
-    fn apply_slice_access(&self, input: &EvaluationResult, start: Option<i64>, end: Option<i64>, step: Option<i64>, outputs: &mut Vec<EvaluationResult>) -> Result<(), JsonPathError> {
-        if let Value::Array(arr) = &input.value {
+    fn apply_slice_access<'a>(&self, input: &EvaluationResult<'a>, start: Option<i64>, end: Option<i64>, step: Option<i64>, outputs: &mut Vec<EvaluationResult<'a>>) -> Result<(), JsonPathError> {
+        if let Value::Array(arr) = input.value {
             let len = arr.len() as i64;
             let step = step.unwrap_or(1);
             
@@ -307,46 +354,46 @@ impl JsonPathEngine {
                 return Err(JsonPathError::EvaluationError("Step cannot be zero".to_string()));
             }
             
-            let start = start.unwrap_or(if step > 0 { 0 } else { len - 1 });
-            let end = end.unwrap_or(if step > 0 { len } else { -1 });
-            
-            let mut i = start;
-            let mut index = 0;
+            // Normalize negative bounds against the array length, then clamp them so the
+            // number of selected elements is known before iterating; the count never adds to
+            // `step`, which may be as large as `i64::MAX` or as small as `i64::MIN`
+            let normalize = |i: i64| if i < 0 { len + i } else { i };
+            let (first, count) = if step > 0 {
+                let lower = start.map(normalize).unwrap_or(0).clamp(0, len);
+                let upper = end.map(normalize).unwrap_or(len).clamp(0, len);
+                (lower, if upper > lower { (upper - lower - 1) / step + 1 } else { 0 })
+            } else {
+                let upper = start.map(normalize).unwrap_or(len - 1).clamp(-1, len - 1);
+                let lower = end.map(normalize).unwrap_or(-1).clamp(-1, len - 1);
+                (upper, if upper > lower { ((upper - lower - 1) as u64 / step.unsigned_abs()) as i64 + 1 } else { 0 })
+            };
             
-            while (step > 0 && i < end && i < len) || (step < 0 && i > end && i >= 0) {
-                if i >= 0 && (i as usize) < arr.len() {
-                    outputs.push(EvaluationResult {
-                        value: arr[i as usize].clone(),
-                        path: format!("{}[{}]", input.path, i),
-                    });
-                }
-                i += step;
-                index += 1;
-                
-                // Prevent infinite loops
-                if index > len {
-                    break;
-                }
+            for n in 0..count {
+                let i = first + n * step;
+                outputs.push(EvaluationResult {
+                    value: &arr[i as usize],
+                    path: self.index_path(&input.path, i),
+                });
             }
         }
         Ok(())
     }
     
-    fn apply_wildcard(&self, input: &EvaluationResult, outputs: &mut Vec<EvaluationResult>) -> Result<(), JsonPathError> {
-        match &input.value {
+    fn apply_wildcard<'a>(&self, input: &EvaluationResult<'a>, outputs: &mut Vec<EvaluationResult<'a>>) -> Result<(), JsonPathError> {
+        match input.value {
             Value::Object(obj) => {
                 for (key, value) in obj {
                     outputs.push(EvaluationResult {
-                        value: value.clone(),
-                        path: format!("{}.{}", input.path, key),
+                        value,
+                        path: self.key_path(&input.path, key),
                     });
                 }
             },
             Value::Array(arr) => {
                 for (idx, value) in arr.iter().enumerate() {
                     outputs.push(EvaluationResult {
-                        value: value.clone(),
-                        path: format!("{}[{}]", input.path, idx),
+                        value,
+                        path: self.index_path(&input.path, idx),
                     });
                 }
             },
@@ -355,52 +402,63 @@ impl JsonPathEngine {
         Ok(())
     }
     
-    fn apply_recursive_descent(&self, input: &EvaluationResult, outputs: &mut Vec<EvaluationResult>, context: &mut EvaluationContext) -> Result<(), JsonPathError> {
+    fn apply_key_regex<'a>(&self, input: &EvaluationResult<'a>, key_regex: &Regex, outputs: &mut Vec<EvaluationResult<'a>>) {
+        if let Value::Object(obj) = input.value {
+            for (key, value) in obj.iter().filter(|(key, _)| key_regex.is_match(key)) {
+                outputs.push(EvaluationResult {
+                    value,
+                    path: self.key_path(&input.path, key),
+                });
+            }
+        }
+    }
+    
+    fn apply_recursive_descent<'a>(&self, input: &EvaluationResult<'a>, outputs: &mut Vec<EvaluationResult<'a>>, context: &mut EvaluationContext) -> Result<(), JsonPathError> {
         if context.depth >= self.config.max_depth {
             return Err(JsonPathError::RecursionLimit);
         }
         
         context.depth += 1;
         
+        // Only references and their paths are queued; values are cloned once, when the query result is built
         let mut queue = VecDeque::new();
         queue.push_back(input.clone());
         
         while let Some(current) = queue.pop_front() {
-            outputs.push(current.clone());
-            
-            match &current.value {
+            match current.value {
                 Value::Object(obj) => {
                     for (key, value) in obj {
                         queue.push_back(EvaluationResult {
-                            value: value.clone(),
-                            path: format!("{}.{}", current.path, key),
+                            value,
+                            path: self.key_path(&current.path, key),
                         });
                     }
                 },
                 Value::Array(arr) => {
                     for (idx, value) in arr.iter().enumerate() {
                         queue.push_back(EvaluationResult {
-                            value: value.clone(),
-                            path: format!("{}[{}]", current.path, idx),
+                            value,
+                            path: self.index_path(&current.path, idx),
                         });
                     }
                 },
                 _ => {}
             }
+            outputs.push(current);
         }
         
         context.depth -= 1;
         Ok(())
     }
     
-    fn apply_filter(&self, input: &EvaluationResult, filter: &FilterExpression, outputs: &mut Vec<EvaluationResult>, context: &mut EvaluationContext) -> Result<(), JsonPathError> {
-        match &input.value {
+    fn apply_filter<'a>(&self, input: &EvaluationResult<'a>, filter: &FilterExpression, outputs: &mut Vec<EvaluationResult<'a>>, context: &mut EvaluationContext) -> Result<(), JsonPathError> {
+        match input.value {
             Value::Array(arr) => {
                 for (idx, item) in arr.iter().enumerate() {
                     if self.evaluate_filter(item, filter, context)? {
                         outputs.push(EvaluationResult {
-                            value: item.clone(),
-                            path: format!("{}[{}]", input.path, idx),
+                            value: item,
+                            path: self.index_path(&input.path, idx),
                         });
                     }
                 }
@@ -409,8 +467,8 @@ impl JsonPathEngine {
                 for (key, value) in obj {
                     if self.evaluate_filter(value, filter, context)? {
                         outputs.push(EvaluationResult {
-                            value: value.clone(),
-                            path: format!("{}.{}", input.path, key),
+                            value,
+                            path: self.key_path(&input.path, key),
                         });
                     }
                 }
@@ -533,8 +591,8 @@ impl JsonPathEngine {
 
 /// Helper structures for evaluation
 #[derive(Debug, Clone)]
-struct EvaluationResult {
-    value: Value,
+struct EvaluationResult<'a> {
+    value: &'a Value,
     path: String,
 }
 
@@ -553,6 +611,8 @@ impl EvaluationContext {
 pub struct CacheStats {
     pub size: usize,
     pub enabled: bool,
+    /// Compiled paths dropped to respect `max_cache_entries`
+    pub evictions: usize,
 }
 
 /// Simple JSONPath parser
@@ -637,11 +697,25 @@ impl PathParser {
             return Ok(PathSegment::Wildcard);
         }
         
+        // Checked before unions and slices, whose separators may appear in the pattern
+        if let Some(pattern) = content.strip_prefix("~/").and_then(|rest| rest.strip_suffix('/')) {
+            compile_key_regex(pattern)?;
+            return Ok(PathSegment::KeyRegex(pattern.to_string()));
+        }
+        
         if content.starts_with('?') {
             // Filter expression - simplified parsing
             return Ok(PathSegment::Filter(FilterExpression::Exists("dummy".to_string())));
         }
         
+        if content.contains(',') {
+            // Union expression: every part is a full selector (index, slice, key or wildcard)
+            let segments = content.split(',')
+                .map(|part| self.parse_bracket_content(part))
+                .collect::<Result<Vec<_>, _>>()?;
+            return Ok(PathSegment::Union(segments));
+        }
+        
         if content.contains(':') {
             // Slice expression
             let parts: Vec<&str> = content.split(':').collect();
@@ -652,21 +726,6 @@ impl PathParser {
             return Ok(PathSegment::Slice { start, end, step });
         }
         
-        if content.contains(',') {
-            // Union expression - simplified
-            let parts: Vec<&str> = content.split(',').collect();
-            let mut segments = Vec::new();
-            for part in parts {
-                let part = part.trim();
-                if let Ok(index) = part.parse::<i64>() {
-                    segments.push(PathSegment::Index(index));
-                } else {
-                    segments.push(PathSegment::Child(part.to_string()));
-                }
-            }
-            return Ok(PathSegment::Union(segments));
-        }
-        
         // Try to parse as index
         if let Ok(index) = content.parse::<i64>() {
             return Ok(PathSegment::Index(index));
@@ -703,6 +762,10 @@ impl PathParser {
     }
 }
 
+fn compile_key_regex(pattern: &str) -> Result<Regex, JsonPathError> {
+    Regex::new(pattern).map_err(|e| JsonPathError::InvalidFilter(format!("key pattern /{}/: {}", pattern, e)))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -754,6 +817,103 @@ mod tests {
         assert_eq!(result.values[1], json!(3));
     }
     
+    #[test]
+    fn test_path_styles() {
+        let data = json!({"store": {"book": [{"title": "A"}, {"title": "B"}]}});
+        
+        let mut dot = JsonPathEngine::new();
+        let result = dot.query(&data, "$.store.book[1].title").unwrap();
+        assert_eq!(result.paths, vec!["$.store.book[1].title"]);
+        
+        let mut bracket = JsonPathEngine::with_config(JsonPathConfig {
+            path_style: PathStyle::Bracket,
+            ..JsonPathConfig::default()
+        });
+        let result = bracket.query(&data, "$.store.book[1].title").unwrap();
+        assert_eq!(result.paths, vec!["$['store']['book'][1]['title']"]);
+        let result = bracket.query(&data, "$.store.book[*].title").unwrap();
+        assert_eq!(result.paths, vec!["$['store']['book'][0]['title']", "$['store']['book'][1]['title']"]);
+    }
+    
+    #[test]
+    fn test_recursive_descent_large_document() {
+        let books: Vec<Value> = (0..1000).map(|i| json!({"title": format!("book {}", i), "price": i as f64 + 0.5})).collect();
+        let data = json!({"store": {"book": books, "bicycle": {"color": "red", "price": 19.95}}});
+        
+        let mut engine = JsonPathEngine::new();
+        let result = engine.query(&data, "$..price").unwrap();
+        assert_eq!(result.values.len(), 1001);
+        assert_eq!(result.paths.len(), 1001);
+        assert!(result.paths.contains(&"$.store.book[999].price".to_string()));
+    }
+    
+    #[test]
+    fn test_mixed_union() {
+        let data = json!({"items": ["a", "b", "c", "d", "e", "f"]});
+        let mut engine = JsonPathEngine::new();
+        
+        let result = engine.query(&data, "$.items[0, 2:4]").unwrap();
+        assert_eq!(result.values, vec![json!("a"), json!("c"), json!("d")]);
+        assert_eq!(result.paths, vec!["$.items[0]", "$.items[2]", "$.items[3]"]);
+        
+        let result = engine.query(&data, "$.items[-1, *]").unwrap();
+        assert_eq!(result.values.len(), 7);
+        assert_eq!(result.values[0], json!("f"));
+        
+        match engine.compile_path("$.items[0, 2:4, -1]").unwrap().segments.last().unwrap() {
+            PathSegment::Union(parts) => assert_eq!(parts.len(), 3),
+            other => panic!("expected union, got {:?}", other),
+        }
+    }
+    
+    #[test]
+    fn test_key_regex_selector() {
+        let data = json!({"contact": {"phone_home": "1", "phone_work": "2", "email": "e", "cellphone": "3"}});
+        let mut engine = JsonPathEngine::new();
+        
+        let result = engine.query(&data, "$.contact[~/^phone/]").unwrap();
+        assert_eq!(result.values, vec![json!("1"), json!("2")]);
+        assert_eq!(result.paths, vec!["$.contact.phone_home", "$.contact.phone_work"]);
+        
+        let result = engine.query(&data, "$.contact[~/phone|mail/]").unwrap();
+        assert_eq!(result.values.len(), 4);
+        assert!(engine.query(&data, "$[~/^phone/]").unwrap().values.is_empty());
+        assert!(matches!(engine.query(&data, "$.contact[~/(phone/]"), Err(JsonPathError::InvalidFilter(_))));
+    }
+    
+    #[test]
+    fn test_slice_steps() {
+        let data = json!({"items": [0, 1, 2, 3, 4, 5, 6]});
+        let mut engine = JsonPathEngine::new();
+        
+        let result = engine.query(&data, "$.items[0:5:2]").unwrap();
+        assert_eq!(result.values, vec![json!(0), json!(2), json!(4)]);
+        
+        let result = engine.query(&data, "$.items[5:0:-2]").unwrap();
+        assert_eq!(result.values, vec![json!(5), json!(3), json!(1)]);
+        assert_eq!(result.paths, vec!["$.items[5]", "$.items[3]", "$.items[1]"]);
+        
+        let result = engine.query(&data, "$.items[3:3]").unwrap();
+        assert!(result.values.is_empty());
+        
+        let result = engine.query(&data, "$.items[::-100]").unwrap();
+        assert_eq!(result.values, vec![json!(6)]);
+        
+        let result = engine.query(&data, "$.items[-2:]").unwrap();
+        assert_eq!(result.values, vec![json!(5), json!(6)]);
+        
+        assert!(engine.query(&data, "$.items[0:5:0]").is_err());
+        
+        let result = engine.query(&data, "$.items[0:2:9223372036854775807]").unwrap();
+        assert_eq!(result.values, vec![json!(0)]);
+        
+        let result = engine.query(&data, "$.items[::-9223372036854775808]").unwrap();
+        assert_eq!(result.values, vec![json!(6)]);
+        
+        let result = engine.query(&data, "$.items[3:0:-9223372036854775808]").unwrap();
+        assert_eq!(result.values, vec![json!(3)]);
+    }
+    
     #[test]
     fn test_compilation_cache() {
         let mut engine = JsonPathEngine::new();
@@ -771,6 +931,27 @@ mod tests {
         assert_eq!(stats.size, 1);
     }
     
+    #[test]
+    fn test_cache_evicts_least_recently_used() {
+        let mut engine = JsonPathEngine::with_config(JsonPathConfig {
+            max_cache_entries: 2,
+            ..JsonPathConfig::default()
+        });
+        let data = json!({"a": 1, "b": 2, "c": 3});
+        
+        engine.query(&data, "$.a").unwrap();
+        engine.query(&data, "$.b").unwrap();
+        assert!(engine.query(&data, "$.a").unwrap().cache_hit);
+        // $.b is now the least recently used and makes room for $.c
+        engine.query(&data, "$.c").unwrap();
+        
+        let stats = engine.cache_stats();
+        assert_eq!(stats.size, 2);
+        assert_eq!(stats.evictions, 1);
+        assert!(engine.query(&data, "$.a").unwrap().cache_hit);
+        assert!(!engine.query(&data, "$.b").unwrap().cache_hit);
+    }
+    
     #[test]
     fn test_multiple_queries() {
         let mut engine = JsonPathEngine::new();
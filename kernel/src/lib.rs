@@ -1,4 +1,6 @@
 pub mod space;
+pub mod value_space;
+pub mod expression;
 mod json_parser;
 pub mod prefix;
 mod stubs;
@@ -8,16 +10,19 @@ pub mod triemap_derivation;
 pub mod expr_query;
 pub mod jsonpath_engine;
 pub mod pattern_matching;
+pub mod sexpr_tokenizer;
+mod lru;
 
 // Integration tests for deliverable validation
 #[cfg(test)]
 mod integration_tests;
 
 // Re-export key functionality
-pub use triemap_derivation::{TrieMap, BytesTrieMap};
+pub use triemap_derivation::{TrieMap, BytesTrieMap, ArenaTrieMap, SharedTrieMap, NodeBackend, Ordered, Dense, Hashed};
 pub use expr_query::{ExprQueryEngine, ExprPattern, ExprStructure};
 pub use jsonpath_engine::JsonPathEngine;
 pub use pattern_matching::UnificationEngine;
+pub use sexpr_tokenizer::tokenize_sexpr;
 
 // Re-export stubs for missing dependencies
 pub use stubs::*;
@@ -28,11 +33,13 @@ mod tests {
     use std::io::Read;
     use std::time::Instant;
     use mork_frontend::bytestring_parser::Parser as SExprParser;
-    use mork_bytestring::{Expr, parse, compute_length, ExprZipper, serialize};
-    use crate::{expr, sexpr, prefix};
+    use mork_bytestring::{Expr, parse, compute_length, ExprZipper, serialize, item_byte, Tag};
+    use crate::{expr, sexpr, prefix, named_expr, transform_expr};
     use crate::json_parser::{Parser, DebugTranscriber, WriteTranscriber};
     use crate::prefix::Prefix;
     use crate::space::*;
+    use crate::value_space::ValueSpace;
+    use crate::expression::Expression;
 
 
     fn set_from_newlines(input : &str) -> std::collections::BTreeSet<&str> {
@@ -52,8 +59,199 @@ mod tests {
         s.dump_sexpr(expr!(s, "[2] my [2] prefix $"), expr!(s, "_1"), &mut res).unwrap();
 
         // the order changed in the test for some reason so we need to use sets to not be concerened by this
+        // `"test"` is the symbol test, which needs no quotes
         let out = String::from_utf8(res).unwrap();
-        assert_eq!(set_from_newlines(input), set_from_newlines(&out));
+        assert_eq!(set_from_newlines("((nested and) (singleton))\n(foo bar)\n(1 test 2)\n"), set_from_newlines(&out));
+    }
+
+    #[test]
+    fn longest_common_template_prefix() {
+        let s = Space::new();
+        let templates = [expr!(s, "[2] my $"), expr!(s, "[2] my [2] prefix $"), expr!(s, "[3] my prefix $")];
+        assert_eq!(crate::prefix::longest_common_prefix(&templates[..2]).as_slice(), prefix!(s, "[2] my").path());
+        assert!(crate::prefix::longest_common_prefix(&templates).as_slice().is_empty());
+    }
+
+    #[test]
+    fn debug_bytes_trace() {
+        let mut buf = [item_byte(Tag::Arity(2)), item_byte(Tag::SymbolSize(2)), b'a', b'b', item_byte(Tag::NewVar)];
+        let e = crate::stubs::Expr{ ptr: buf.as_mut_ptr() };
+        assert_eq!(crate::stubs::debug_bytes(e), "Arity(2)\nSymbolSize(2) \"ab\"\nNewVar");
+    }
+
+    #[test]
+    fn sample_dump_is_reproducible() {
+        let mut s = Space::new();
+        let facts: String = (0..200).map(|i| format!("(fact {i})\n")).collect();
+        assert_eq!(200, s.load_sexpr(facts.as_bytes(), expr!(s, "$"), expr!(s, "_1")).unwrap());
+
+        let sample = |seed| {
+            let mut out = Vec::new();
+            assert_eq!(10, s.sample_dump(expr!(s, "[2] fact $"), expr!(s, "_1"), 10, seed, &mut out).unwrap());
+            String::from_utf8(out).unwrap()
+        };
+        let first = sample(42);
+        assert_eq!(first, sample(42));
+        assert_eq!(set_from_newlines(&first).len(), 10);
+        assert!(set_from_newlines(&first).iter().all(|n| n.parse::<usize>().unwrap() < 200));
+
+        let mut all = Vec::new();
+        assert_eq!(200, s.sample_dump(expr!(s, "[2] fact $"), expr!(s, "_1"), 1000, 42, &mut all).unwrap());
+    }
+
+    #[test]
+    fn dump_csv_roundtrip() {
+        let csv_input = "0,123,foo\n1,321,bar\n";
+        let mut s = Space::new();
+        assert_eq!(s.load_csv(csv_input.as_bytes(), expr!(s, "$"), expr!(s, "_1"), b',').unwrap(), 2);
+        let mut res = Vec::<u8>::new();
+        assert_eq!(s.dump_as_csv(&mut res, expr!(s, "$"), expr!(s, "_1"), b',').unwrap(), 2);
+        assert_eq!(set_from_newlines(csv_input), set_from_newlines(&String::from_utf8(res).unwrap()));
+    }
+
+    #[test]
+    fn sized_buffers() {
+        let mut s = Space::new();
+        assert_eq!(s.load_csv_sized::<256>("1,2\n3,4\n".as_bytes(), expr!(s, "$"), expr!(s, "_1"), b',').unwrap(), 2);
+        let mut res = Vec::<u8>::new();
+        assert_eq!(s.dump_sexpr_sized::<256, _>(expr!(s, "[3] $ $ $"), expr!(s, "[2] _2 _3"), &mut res).unwrap(), 2);
+        assert_eq!(set_from_newlines(&String::from_utf8(res).unwrap()), set_from_newlines("(1 2)\n(3 4)\n"));
+
+        // 63 rows of 63 one-letter symbols encode to about 8000 bytes, past the default dump buffer
+        let row = format!("({})", "x ".repeat(63).trim_end());
+        let wide = format!("(table {})\n", std::iter::repeat(row.as_str()).take(62).collect::<Vec<_>>().join(" "));
+        let mut big = Space::new();
+        assert_eq!(big.load_sexpr(wide.as_bytes(), expr!(big, "$"), expr!(big, "_1")).unwrap(), 1);
+        let mut res = Vec::<u8>::new();
+        assert_eq!(big.dump_sexpr_sized::<16384, _>(expr!(big, "$"), expr!(big, "_1"), &mut res).unwrap(), 1);
+        assert_eq!(String::from_utf8(res).unwrap(), wide);
+    }
+
+    #[test]
+    fn empty_compounds_and_symbols() {
+        let mut s = Space::new();
+        assert_eq!(s.load_sexpr("()\n(a ())\n(\"\" b)\n".as_bytes(), expr!(s, "$"), expr!(s, "_1")).unwrap(), 3);
+        let mut res = Vec::<u8>::new();
+        s.dump_all_sexpr(&mut res).unwrap();
+        assert_eq!(set_from_newlines(&String::from_utf8(res).unwrap()), set_from_newlines("()\n(a ())\n(\"\" b)\n"));
+
+        let mut c = Space::new();
+        assert_eq!(c.load_csv("a,b\nc,,d\n".as_bytes(), expr!(c, "$"), expr!(c, "_1"), b','),
+                   Err(SpaceError::EmptySymbol { row: 1, column: 1 }.to_string()));
+
+        let mut j = Space::new();
+        assert_eq!(j.load_json(r#"{"name": ""}"#.as_bytes()).unwrap(), 1);
+        let mut res = Vec::<u8>::new();
+        j.dump_all_sexpr(&mut res).unwrap();
+        assert_eq!(String::from_utf8(res).unwrap(), "(name \"\")\n");
+    }
+
+    #[test]
+    fn malformed_sexpr_is_an_error() {
+        for input in [")", "(a (b c)", "(a))", "(\"unterminated"] {
+            let mut s = Space::new();
+            assert!(s.load_sexpr(input.as_bytes(), expr!(s, "$"), expr!(s, "_1")).is_err(), "{input}");
+        }
+        let mut s = Space::new();
+        assert!(s.load_sexpr(b"(a b)\n)", expr!(s, "$"), expr!(s, "_1")).is_err());
+
+        // a compound holds at most 63 children
+        let mut s = Space::new();
+        let err = s.load_sexpr(format!("({})", "x ".repeat(64)).as_bytes(), expr!(s, "$"), expr!(s, "_1")).unwrap_err();
+        assert!(err.starts_with("parse error TooManyArguments "), "{err}");
+        let mut s = Space::new();
+        assert_eq!(s.load_sexpr(format!("({})", "x ".repeat(63)).as_bytes(), expr!(s, "$"), expr!(s, "_1")), Ok(1));
+
+        // an over-long symbol is well-formed and must not overflow the load buffers
+        let mut s = Space::new();
+        assert_eq!(s.load_sexpr(format!("({})", "a".repeat(4096)).as_bytes(), expr!(s, "$"), expr!(s, "_1")), Ok(1));
+    }
+
+    #[test]
+    fn batched_load_matches_load_sexpr() {
+        let mut s = Space::new();
+        assert_eq!(16, s.load_sexpr(SEXPRS0.as_bytes(), expr!(s, "$"), expr!(s, "_1")).unwrap());
+        let mut batched = Space::new();
+        assert_eq!(16, batched.load_sexpr_batched(SEXPRS0.as_bytes(), expr!(batched, "$"), expr!(batched, "_1")).unwrap());
+
+        let (mut a, mut b) = (Vec::new(), Vec::new());
+        s.dump_all_sexpr(&mut a).unwrap();
+        batched.dump_all_sexpr(&mut b).unwrap();
+        assert_eq!(String::from_utf8(a).unwrap(), String::from_utf8(b).unwrap());
+        assert_eq!(s.btm.val_count(), batched.btm.val_count());
+    }
+
+    #[test]
+    fn parallel_load_matches_serial() {
+        let mut corpus = String::from(SEXPRS0);
+        for i in 0..500 { corpus.push_str(&format!("(edge {} {})\n(step {}\n  (from \"n;{}\"))\n", i, i + 1, i, i)); }
+
+        let mut serial = Space::new();
+        let read = serial.load_sexpr(corpus.as_bytes(), expr!(serial, "$"), expr!(serial, "_1")).unwrap();
+        let mut parallel = Space::new();
+        assert_eq!(read, parallel.load_sexpr_parallel(corpus.as_bytes(), expr!(parallel, "$"), expr!(parallel, "_1"), 4).unwrap());
+
+        let (mut a, mut b) = (Vec::new(), Vec::new());
+        serial.dump_all_sexpr(&mut a).unwrap();
+        parallel.dump_all_sexpr(&mut b).unwrap();
+        assert_eq!(String::from_utf8(a).unwrap(), String::from_utf8(b).unwrap());
+        assert_eq!(serial.btm.val_count(), parallel.btm.val_count());
+    }
+
+    #[test]
+    fn load_large_expression_with_repeating_template() {
+        // 40 groups of 60 one-byte atoms: about 5 KB of source, and several times that once atoms are interned
+        let group = format!("({})", vec!["a"; 60].join(" "));
+        let src = format!("({})\n", vec![group.as_str(); 40].join(" "));
+        let mut s = Space::new();
+        assert_eq!(s.load_sexpr(src.as_bytes(), expr!(s, "$"), expr!(s, "[4] thrice _1 _1 _1")).unwrap(), 1);
+        let mut out = vec![];
+        s.dump_all_sexpr(&mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), format!("(thrice {} {} {})\n", src.trim_end(), src.trim_end(), src.trim_end()));
+    }
+
+    #[test]
+    fn load_lines_reports_each_line() {
+        let mut s = Space::new();
+        let mut seen = vec![];
+        let added = s.load_sexpr_lines("(a 1)\n(b 2))\n\n; note\n(c\n 3)\n(d\n".as_bytes(), expr!(s, "$"), expr!(s, "_1"), |n, r| seen.push((n, r.is_ok())));
+        // the stray `)` fails after `(b 2)` loaded, and the unclosed `(d` at the end of the input
+        assert_eq!(seen, vec![(0, true), (1, true), (1, false), (4, true), (6, false)]);
+        assert_eq!(added, 3);
+    }
+
+    #[test]
+    fn load_lines_reports_every_expression_on_a_line() {
+        let mut s = Space::new();
+        let mut seen = vec![];
+        let added = s.load_sexpr_lines("(a 1) (b 2)\n(c 3)\n".as_bytes(), expr!(s, "$"), expr!(s, "_1"), |n, r| seen.push((n, r.is_ok())));
+        assert_eq!(seen, vec![(0, true), (0, true), (1, true)]);
+        assert_eq!(added, 3);
+        assert_eq!(s.dump_as_string(expr!(s, "$"), expr!(s, "_1")).unwrap(), "(a 1)\n(b 2)\n(c 3)\n");
+    }
+
+    #[test]
+    fn load_tagged_files() {
+        let dir = std::env::temp_dir();
+        let family = dir.join("mork_load_tagged_family.metta");
+        let broken = dir.join("mork_load_tagged_broken.metta");
+        let places = dir.join("mork_load_tagged_places.metta");
+        std::fs::write(&family, "(parent Tom Bob)\n(parent Bob Ann)\n").unwrap();
+        std::fs::write(&broken, "(parent Ann").unwrap();
+        std::fs::write(&places, "(city Paris)\n").unwrap();
+
+        let mut s = Space::new();
+        let results = s.load_sexpr_files(&[(&family, expr!(s, "[2] family _1")),
+                                           (&broken, expr!(s, "[2] broken _1")),
+                                           (&dir.join("mork_load_tagged_missing.metta"), expr!(s, "[2] missing _1")),
+                                           (&places, expr!(s, "[2] places _1"))]);
+        assert_eq!(results[0], Ok(2));
+        assert!(results[1].is_err());
+        assert!(results[2].is_err());
+        assert_eq!(results[3], Ok(1));
+
+        assert_eq!(s.traverse_pattern(expr!(s, "[2] family $"), |_, _| {}), 2);
+        assert_eq!(s.traverse_pattern(expr!(s, "[2] places $"), |_, _| {}), 1);
     }
 
     #[test]
@@ -67,6 +265,35 @@ mod tests {
         assert_eq!(reconstruction, String::from_utf8(res).unwrap());
     }
 
+    #[cfg(feature="parquet")]
+    #[test]
+    fn parse_parquet() {
+        use std::sync::Arc;
+        use parquet::data_type::{ByteArray, ByteArrayType, Int64Type};
+        use parquet::file::writer::SerializedFileWriter;
+        use parquet::schema::parser::parse_message_type;
+
+        let path = std::env::temp_dir().join("mork_parse_parquet.parquet");
+        let schema = Arc::new(parse_message_type("message row { REQUIRED INT64 id; REQUIRED BYTE_ARRAY name (UTF8); }").unwrap());
+        let mut writer = SerializedFileWriter::new(File::create(&path).unwrap(), schema, Default::default()).unwrap();
+        let mut rg = writer.next_row_group().unwrap();
+        let mut col = rg.next_column().unwrap().unwrap();
+        col.typed::<Int64Type>().write_batch(&[123, 321], None, None).unwrap();
+        col.close().unwrap();
+        let mut col = rg.next_column().unwrap().unwrap();
+        col.typed::<ByteArrayType>().write_batch(&[ByteArray::from("foo"), ByteArray::from("bar")], None, None).unwrap();
+        col.close().unwrap();
+        rg.close().unwrap();
+        writer.close().unwrap();
+
+        let reconstruction = "(0 (id 123) (name foo))\n(1 (id 321) (name bar))\n";
+        let mut s = Space::new();
+        assert_eq!(s.load_parquet(&path, expr!(s, "$"), expr!(s, "_1")).unwrap(), 2);
+        let mut res = Vec::<u8>::new();
+        s.dump_sexpr(expr!(s, "$"), expr!(s, "_1"), &mut res).unwrap();
+        assert_eq!(set_from_newlines(reconstruction), set_from_newlines(&String::from_utf8(res).unwrap()));
+    }
+
     #[test]
     fn reconstruct_json() {
         let json_input = r#"{"first_name": "John", "last_name": "Smith", "is_alive": true, "age": 27, "address": {"street_address": "21 2nd Street", "city": "New York", "state": "NY", "postal_code": "10021-3100"}, "phone_numbers": [{"type": "home", "number": "212 555-1234"}, {"type": "office", "number": "646 555-4567"}], "children": ["Catherine", "Thomas", "Trevor"], "spouse": null}"#;
@@ -88,6 +315,19 @@ mod tests {
         assert_eq!(json_output, String::from_utf8(wt.w).unwrap());
     }
 
+    #[test]
+    fn non_finite_json_numbers() {
+        let mut p = Parser::new(r#"{"x": Infinity}"#);
+        let mut wt = WriteTranscriber{ w: Vec::<u8>::new() };
+        assert_eq!(p.parse(&mut wt), Err(crate::json_parser::Error::NonFiniteNumber { literal: "Infinity", line: 1, column: 7 }));
+
+        let json_input = r#"{"x": Infinity, "y": [NaN, -Infinity, 1]}"#;
+        let mut p = Parser::new(json_input).allow_non_finite(true);
+        let mut wt = WriteTranscriber{ w: Vec::<u8>::new() };
+        p.parse(&mut wt).unwrap();
+        assert_eq!(json_input, String::from_utf8(wt.w).unwrap());
+    }
+
     const SEXPRS0: &str = r#"(first_name John)
 (last_name Smith)
 (is_alive true)
@@ -104,6 +344,25 @@ mod tests {
 (children (1 Thomas))
 (children (2 Trevor))
 (spouse null)
+"#;
+
+    /// The document behind `SEXPRS0` as `load_json` stores it: strings are single symbols, quoted when dumped
+    const JSON_SEXPRS0: &str = r#"(first_name John)
+(last_name Smith)
+(is_alive true)
+(age 27)
+(address (street_address "21 2nd Street"))
+(address (city "New York"))
+(address (state NY))
+(address (postal_code 10021-3100))
+(phone_numbers (0 (type home)))
+(phone_numbers (0 (number "212 555-1234")))
+(phone_numbers (1 (type office)))
+(phone_numbers (1 (number "646 555-4567")))
+(children (0 Catherine))
+(children (1 Thomas))
+(children (2 Trevor))
+(spouse null)
 "#;
 
     #[test]
@@ -132,7 +391,575 @@ mod tests {
         s.dump_sexpr(expr!(s, "$"), expr!(s, "_1"), &mut res).unwrap();
 
         let out = String::from_utf8(res).unwrap();
-        assert_eq!(set_from_newlines(SEXPRS0), set_from_newlines(&out));
+        assert_eq!(set_from_newlines(JSON_SEXPRS0), set_from_newlines(&out));
+    }
+
+    #[test]
+    fn quoted_symbols_roundtrip() {
+        let mut s = Space::new();
+        assert_eq!(1, s.load_json(r#"{"city": "New York"}"#.as_bytes()).unwrap());
+        let mut first = Vec::<u8>::new();
+        s.dump_all_sexpr(&mut first).unwrap();
+        assert_eq!("(city \"New York\")\n", String::from_utf8(first.clone()).unwrap());
+
+        let mut t = Space::new();
+        assert_eq!(1, t.load_sexpr(&first[..], expr!(t, "$"), expr!(t, "_1")).unwrap());
+        let mut second = Vec::<u8>::new();
+        t.dump_all_sexpr(&mut second).unwrap();
+        assert_eq!(first, second);
+
+        // the quoted spelling and the loaded string are one symbol
+        assert_eq!(1, s.load_sexpr(&first[..], expr!(s, "$"), expr!(s, "_1")).unwrap());
+        assert_eq!(s.btm.val_count(), 1);
+    }
+
+    #[test]
+    fn quoted_symbols_dump_load_dump() {
+        let input = concat!("(say \"she said \\\"hi\\\"\" \"back\\\\slash\" \"$not_a_var\" \";not_a_comment\" \"(x)\")\n",
+                            "(say \"plain\" \"\")\n");
+        let mut s = Space::new();
+        assert_eq!(2, s.load_sexpr(input.as_bytes(), expr!(s, "$"), expr!(s, "_1")).unwrap());
+        let mut first = Vec::<u8>::new();
+        s.dump_all_sexpr(&mut first).unwrap();
+        let first = String::from_utf8(first).unwrap();
+        assert_eq!(set_from_newlines(&first), set_from_newlines(concat!(
+            "(say \"she said \\\"hi\\\"\" \"back\\\\slash\" \"$not_a_var\" \";not_a_comment\" \"(x)\")\n",
+            "(say plain \"\")\n")));
+
+        let mut t = Space::new();
+        assert_eq!(2, t.load_sexpr(first.as_bytes(), expr!(t, "$"), expr!(t, "_1")).unwrap());
+        let mut second = Vec::<u8>::new();
+        t.dump_all_sexpr(&mut second).unwrap();
+        assert_eq!(first, String::from_utf8(second).unwrap());
+    }
+
+    #[test]
+    fn exclusive_path_conflict() {
+        let mut s = Space::new();
+        assert_eq!(16, s.load_sexpr(SEXPRS0.as_bytes(), expr!(s, "$"), expr!(s, "_1")).unwrap());
+
+        let zh = s.btm.zipper_head();
+        let outer = [item_byte(Tag::Arity(2))];
+        let wz = Space::exclusive_write_zipper(&zh, &outer[..]).unwrap();
+        let nested = [item_byte(Tag::Arity(2)), item_byte(Tag::SymbolSize(8))];
+        assert_eq!(Space::exclusive_write_zipper(&zh, &nested[..]).err(), Some(SpaceError::PathConflict));
+        let sibling = [item_byte(Tag::Arity(3))];
+        assert!(Space::exclusive_write_zipper(&zh, &sibling[..]).is_ok());
+        drop(wz);
+        assert!(Space::exclusive_write_zipper(&zh, &nested[..]).is_ok());
+    }
+
+    #[test]
+    fn overlapping_load_fails_before_parsing() {
+        let s = Space::new();
+        let zh = s.btm.zipper_head();
+        let wz = Space::exclusive_write_zipper(&zh, &[item_byte(Tag::Arity(2))][..]).unwrap();
+        // the input does not parse, so getting `PathConflict` back means parsing never started
+        let r = s.load_sexpr_exclusive(&zh, "(parent Tom".as_bytes(), expr!(s, "$"), expr!(s, "[2] parent _1"));
+        assert_eq!(r, Err(SpaceError::PathConflict.to_string()));
+        assert_eq!(s.btm.val_count(), 0);
+
+        // with the region free the same load gets as far as the parser
+        drop(wz);
+        let r = s.load_sexpr_exclusive(&zh, "(parent Tom".as_bytes(), expr!(s, "$"), expr!(s, "[2] parent _1"));
+        assert!(r.is_err());
+        assert_ne!(r, Err(SpaceError::PathConflict.to_string()));
+    }
+
+    #[cfg(feature="interning")]
+    #[test]
+    fn stable_symbols_load_order() {
+        let reversed: String = SEXPRS0.lines().rev().map(|l| format!("{l}\n")).collect();
+        let dir = std::env::temp_dir();
+
+        let mut forward = Space::with_stable_symbols();
+        assert_eq!(16, forward.load_sexpr(SEXPRS0.as_bytes(), expr!(forward, "$"), expr!(forward, "_1")).unwrap());
+        forward.backup_paths(dir.join("mork_stable_forward.paths")).unwrap();
+
+        let mut backward = Space::with_stable_symbols();
+        assert_eq!(16, backward.load_sexpr(reversed.as_bytes(), expr!(backward, "$"), expr!(backward, "_1")).unwrap());
+        backward.backup_paths(dir.join("mork_stable_backward.paths")).unwrap();
+
+        assert_eq!(std::fs::read(dir.join("mork_stable_forward.paths")).unwrap(),
+                   std::fs::read(dir.join("mork_stable_backward.paths")).unwrap());
+    }
+
+    #[cfg(feature="interning")]
+    #[test]
+    fn sorted_dump_ignores_load_order() {
+        let reversed: String = SEXPRS0.lines().rev().map(|l| format!("{l}\n")).collect();
+        let mut forward = Space::new();
+        assert_eq!(16, forward.load_sexpr(SEXPRS0.as_bytes(), expr!(forward, "$"), expr!(forward, "_1")).unwrap());
+        let mut backward = Space::new();
+        assert_eq!(16, backward.load_sexpr(reversed.as_bytes(), expr!(backward, "$"), expr!(backward, "_1")).unwrap());
+
+        let sorted = DumpOptions { sort_by_symbol: true };
+        let (mut a, mut b) = (Vec::new(), Vec::new());
+        assert_eq!(16, forward.dump_all_sexpr_with_options(&mut a, &sorted).unwrap());
+        assert_eq!(16, backward.dump_all_sexpr_with_options(&mut b, &sorted).unwrap());
+        assert_eq!(a, b);
+        let mut lines: Vec<&str> = std::str::from_utf8(&a).unwrap().lines().collect();
+        assert_eq!(lines.first(), Some(&"(address (city New York))"));
+        lines.sort();
+        assert_eq!(lines.join("\n") + "\n", String::from_utf8(a).unwrap());
+    }
+
+    #[cfg(feature="interning")]
+    #[test]
+    fn symbol_table_stats_stable_on_reload() {
+        let mut s = Space::new();
+        assert_eq!(16, s.load_sexpr(SEXPRS0.as_bytes(), expr!(s, "$"), expr!(s, "_1")).unwrap());
+        let first = s.symbol_table_stats();
+        assert!(first.count > 0);
+        assert!(first.bytes > first.count);
+        assert_eq!(16, s.load_sexpr(SEXPRS0.as_bytes(), expr!(s, "$"), expr!(s, "_1")).unwrap());
+        assert_eq!(s.symbol_table_stats(), first);
+    }
+
+    #[cfg(feature="interning")]
+    #[test]
+    fn inline_symbols_roundtrip() {
+        let input = "(a (bb longsymbol))\n(parent_of (Tom Bob) grandchildren)\n(x \"quoted string\" 1234567)\n";
+        let mut s = Space::with_inline_symbols();
+        assert_eq!(3, s.load_sexpr(input.as_bytes(), expr!(s, "$"), expr!(s, "_1")).unwrap());
+
+        let mut res = Vec::<u8>::new();
+        s.dump_all_sexpr(&mut res).unwrap();
+        assert_eq!(set_from_newlines(input), set_from_newlines(&String::from_utf8(res).unwrap()));
+
+        assert!(s.sm.get_sym(b"Tom").is_none());
+        assert!(s.sm.get_sym(b"longsymbol").is_some());
+    }
+
+    #[test]
+    fn inline_symbols_mode() {
+        assert!(Space::with_inline_symbols().sm.inlines_small());
+        assert!(!Space::new().sm.inlines_small());
+        assert!(!Space::with_stable_symbols().sm.inlines_small());
+    }
+
+    #[test]
+    fn intern_and_resolve_symbol() {
+        let s = Space::new();
+        let sym = s.intern_symbol(b"Catherine");
+        assert_eq!(s.intern_symbol(b"Catherine"), sym);
+        assert_eq!(s.resolve_symbol(&sym), Some(b"Catherine".to_vec()));
+    }
+
+    #[test]
+    fn symbol_table_roundtrip() {
+        let a = Space::new();
+        let ids = [a.intern_symbol(b"Catherine"), a.intern_symbol(b"Thomas")];
+        let mut table = vec![];
+        a.dump_symbol_table(&mut table).unwrap();
+
+        let b = Space::new();
+        b.intern_symbol(b"Pam");
+        let remap = b.load_symbol_table(&mut &table[..]).unwrap();
+        for (old, new) in remap.iter() {
+            assert!(ids.contains(old));
+            assert_eq!(b.resolve_symbol(new), a.resolve_symbol(old));
+        }
+        #[cfg(feature="interning")]
+        assert_eq!(remap.len(), ids.len());
+    }
+
+    #[test]
+    fn compact_after_removals() {
+        let mut s = Space::new();
+        let facts: String = (0..100).map(|i| format!("(fact {i} (note n{i}))\n")).collect();
+        assert_eq!(100, s.load_sexpr(facts.as_bytes(), expr!(s, "$"), expr!(s, "_1")).unwrap());
+        let mut all = vec![];
+        s.query(expr!(s, "[3] fact $ $"), |_, e| all.push(unsafe { e.span().as_ref().unwrap() }.to_vec()));
+        for path in all.iter().step_by(2) {
+            s.btm.remove(&path[..]);
+        }
+
+        let dangling = s.dangling_paths();
+        let stats = s.compact();
+        assert_eq!(stats, CompactStats { reclaimed: dangling, facts: 50 });
+        assert_eq!(s.dangling_paths(), 0);
+        let mut left = 0;
+        s.query(expr!(s, "[3] fact $ $"), |_, _| left += 1);
+        assert_eq!(left, 50);
+        assert_eq!(s.compact(), CompactStats { reclaimed: 0, facts: 50 });
+    }
+
+    #[test]
+    fn durable_snapshot_survives_interrupted_write() {
+        let mut s = Space::new();
+        assert_eq!(16, s.load_sexpr(SEXPRS0.as_bytes(), expr!(s, "$"), expr!(s, "_1")).unwrap());
+        let path = std::env::temp_dir().join(format!("mork_durable_snapshot_survives_interrupted_write_{}.paths", std::process::id()));
+        s.snapshot(&path, &SnapshotOptions::default()).unwrap();
+        let before = std::fs::read(&path).unwrap();
+
+        let interrupted = write_snapshot_file(&path, &SnapshotOptions::default(), |file| {
+            std::io::Write::write_all(file, b"partial")?;
+            Err::<(), _>(std::io::Error::new(std::io::ErrorKind::Other, "interrupted"))
+        });
+        assert!(interrupted.is_err());
+        assert_eq!(std::fs::read(&path).unwrap(), before);
+        assert!(!path.with_extension("paths.tmp").exists());
+
+        let mut restored = Space::new();
+        restored.restore_paths(&path).unwrap();
+        assert_eq!(restored.btm.val_count(), 16);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn snapshot_header_without_loading() {
+        let mut s = Space::new();
+        assert_eq!(16, s.load_sexpr(SEXPRS0.as_bytes(), expr!(s, "$"), expr!(s, "_1")).unwrap());
+        let path = std::env::temp_dir().join(format!("mork_snapshot_header_without_loading_{}.snap", std::process::id()));
+        let metadata: std::collections::BTreeMap<String, String> = [("created", "2024-05-01T12:00:00Z"), ("source", "sexprs0.metta"), ("schema", "3")]
+            .into_iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        let written = s.snapshot_with_metadata(&path, &SnapshotOptions::default(), metadata.clone()).unwrap();
+
+        let header = read_snapshot_header(&path).unwrap();
+        assert_eq!(header, written);
+        assert_eq!(header.version, SNAPSHOT_VERSION);
+        assert_eq!(header.metadata, metadata);
+        assert!(header.symbol_table_offset > SNAPSHOT_MAGIC.len() as u64);
+        assert!(header.symbol_table_offset <= std::fs::metadata(&path).unwrap().len());
+
+        let plain = std::env::temp_dir().join(format!("mork_snapshot_header_without_loading_{}.paths", std::process::id()));
+        s.snapshot(&plain, &SnapshotOptions::default()).unwrap();
+        assert!(read_snapshot_header(&plain).is_err());
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(&plain).unwrap();
+    }
+
+    #[test]
+    fn value_space_timestamps() {
+        let mut s = ValueSpace::<u64>::new();
+        let mut clock = 100u64;
+        assert_eq!(16, s.load_sexpr_with_values(SEXPRS0.as_bytes(), expr!(s, "$"), expr!(s, "_1"), |_| { clock += 1; clock }).unwrap());
+
+        assert_eq!(s.get(expr!(s, "[2] first_name John")), Some(&101));
+        assert_eq!(s.get(expr!(s, "[2] spouse null")), Some(&116));
+        assert_eq!(s.get(expr!(s, "[2] spouse Jane")), None);
+
+        let mut out = Vec::new();
+        assert_eq!(16, s.dump_sexpr_with_values(&mut out, |t| t.to_string()).unwrap());
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.lines().any(|l| l == "(age 27)\t104"));
+    }
+
+    #[test]
+    fn apply_diff_replicates() {
+        let mut a = Space::with_stable_symbols();
+        assert_eq!(16, a.load_sexpr(SEXPRS0.as_bytes(), expr!(a, "$"), expr!(a, "_1")).unwrap());
+        let mut b = Space::with_stable_symbols();
+        let stale: String = SEXPRS0.lines().skip(3).map(|l| format!("{l}\n")).chain(["(spouse Jane)\n".to_string()]).collect();
+        assert_eq!(14, b.load_sexpr(stale.as_bytes(), expr!(b, "$"), expr!(b, "_1")).unwrap());
+
+        let diff = a.diff(&b);
+        assert_eq!((diff.added.len(), diff.removed.len()), (3, 1));
+        b.apply_diff(&diff).unwrap();
+        let (mut da, mut db) = (Vec::new(), Vec::new());
+        a.dump_all_sexpr(&mut da).unwrap();
+        b.dump_all_sexpr(&mut db).unwrap();
+        assert_eq!(String::from_utf8(da).unwrap(), String::from_utf8(db).unwrap());
+
+        assert!(matches!(b.apply_diff(&diff), Err(SpaceError::DiffConflict { .. })));
+        assert_eq!(a.diff(&b), SpaceDiff::default());
+    }
+
+    #[test]
+    fn expressions_containing_symbol() {
+        let mut s = Space::new();
+        assert_eq!(16, s.load_sexpr(SEXPRS0.as_bytes(), expr!(s, "$"), expr!(s, "_1")).unwrap());
+
+        let mut found = vec![];
+        assert_eq!(4, s.expressions_containing(b"address", |e| found.push(sexpr!(s, e))));
+        assert!(found.iter().all(|e| e.starts_with("(address ")));
+        assert_eq!(3, s.expressions_containing(b"0", |_| {}));
+        assert_eq!(0, s.expressions_containing(b"nowhere", |_| {}));
+    }
+
+    #[test]
+    fn query_under_address() {
+        let mut s = Space::new();
+        assert_eq!(16, s.load_sexpr(SEXPRS0.as_bytes(), expr!(s, "$"), expr!(s, "_1")).unwrap());
+
+        let mut found = vec![];
+        assert_eq!(4, s.query_under(&prefix!(s, "[2] address"), expr!(s, "[2] $ $"), |e| found.push(sexpr!(s, e))));
+        assert!(found.iter().all(|e| e.starts_with("(address ")));
+        assert_eq!(1, s.query_under(&prefix!(s, "[2] address"), expr!(s, "[2] city $"), |_| {}));
+        assert_eq!(0, s.query_under(&prefix!(s, "[2] children"), expr!(s, "[2] city $"), |_| {}));
+    }
+
+    #[test]
+    fn query_ordered_by_capture() {
+        let mut s = Space::new();
+        assert_eq!(16, s.load_sexpr(SEXPRS0.as_bytes(), expr!(s, "$"), expr!(s, "_1")).unwrap());
+        for order in [CaptureOrder::Bytes, CaptureOrder::Numeric] {
+            let ordered: Vec<String> = s.query_ordered_by(expr!(s, "[2] children [2] $ $"), 0, order)
+                .iter().map(|e| sexpr!(s, e.borrow())).collect();
+            assert_eq!(ordered, vec!["(children (0 Catherine))", "(children (1 Thomas))", "(children (2 Trevor))"]);
+        }
+
+        let mut w = Space::new();
+        w.load_sexpr("(weight 10)\n(weight 9.5)\n(weight 100.25)\n(weight heavy)\n".as_bytes(), expr!(w, "$"), expr!(w, "_1")).unwrap();
+        let ordered: Vec<String> = w.query_ordered_by(expr!(w, "[2] weight $"), 0, CaptureOrder::Numeric)
+            .iter().map(|e| sexpr!(w, e.borrow())).collect();
+        assert_eq!(ordered, vec!["(weight 9.5)", "(weight 10)", "(weight 100.25)", "(weight heavy)"]);
+    }
+
+    #[test]
+    fn query_channel_matches_query() {
+        let mut s = Space::new();
+        assert_eq!(16, s.load_sexpr(SEXPRS0.as_bytes(), expr!(s, "$"), expr!(s, "_1")).unwrap());
+        let pattern = expr!(s, "[2] children $");
+
+        let mut expected = std::collections::BTreeSet::new();
+        s.query_with_config(pattern, &MatchConfig::default(), |e| { expected.insert(sexpr!(s, e)); }).unwrap();
+        let received: std::collections::BTreeSet<String> = s.query_channel(pattern, 1).into_iter().map(|e| sexpr!(s, e.borrow())).collect();
+        assert_eq!(received.len(), 3);
+        assert_eq!(received, expected);
+    }
+
+    #[test]
+    fn index_lookup_by_key() {
+        let mut s = Space::new();
+        assert_eq!(16, s.load_sexpr(SEXPRS0.as_bytes(), expr!(s, "$"), expr!(s, "_1")).unwrap());
+        let index = s.build_index(expr!(s, "[2] phone_numbers [2] $ $"), 0);
+        assert_eq!(index.len(), 2);
+
+        let mut office: Vec<String> = index.get(expr!(s, "1")).iter().map(|f| sexpr!(s, Expr{ ptr: f.as_ptr().cast_mut() })).collect();
+        office.sort();
+        assert_eq!(office, vec!["(phone_numbers (1 (number 646 555-4567)))", "(phone_numbers (1 (type office)))"]);
+        assert!(index.get(expr!(s, "7")).is_empty());
+    }
+
+    #[test]
+    fn tokenizer_agrees_with_loader() {
+        use crate::sexpr_tokenizer::TokenKind;
+        let src = "(parent Tom \"Bob Smith\")\n; a comment\n(edge $x (node $x $ $y $))\n(empty ())\n";
+
+        // rebuild the source from the tokens in the form `dump_all_sexpr` writes
+        let mut lexed = String::new();
+        let mut depth = 0;
+        let mut introduced = 0;
+        for token in crate::tokenize_sexpr(src) {
+            let kind = token.unwrap().kind;
+            if depth > 0 && kind != TokenKind::Close && !lexed.ends_with('(') { lexed.push(' ') }
+            match kind {
+                TokenKind::Open => { lexed.push('('); depth += 1 }
+                TokenKind::Close => {
+                    lexed.push(')');
+                    depth -= 1;
+                    if depth == 0 { lexed.push('\n'); introduced = 0 }
+                }
+                TokenKind::Symbol(symbol) => lexed.push_str(symbol),
+                TokenKind::Variable(i) if i == introduced => { lexed.push('$'); introduced += 1 }
+                TokenKind::Variable(i) => lexed.push_str(&format!("_{}", i + 1)),
+            }
+        }
+
+        let mut s = Space::new();
+        assert_eq!(3, s.load_sexpr(src.as_bytes(), expr!(s, "$"), expr!(s, "_1")).unwrap());
+        let mut loaded = Vec::<u8>::new();
+        s.dump_all_sexpr(&mut loaded).unwrap();
+        let loaded = String::from_utf8(loaded).unwrap();
+        assert_eq!(set_from_newlines(&lexed), set_from_newlines(&loaded));
+    }
+
+    #[test]
+    fn query_at_exact_depth() {
+        let mut s = Space::new();
+        s.load_sexpr("(a 1)\n(b (c 2))\n(d (e (f 3)))\n(g (h (i (j 4))))\n(k (l 5) (m (n 6)))\n(o () p)\n".as_bytes(), expr!(s, "$"), expr!(s, "_1")).unwrap();
+        let mut found = vec![];
+        assert_eq!(2, s.query_with_config(expr!(s, "{depth=3}"), &MatchConfig::default(), |e| found.push(sexpr!(s, e))).unwrap());
+        found.sort();
+        assert_eq!(found, vec!["(d (e (f 3)))", "(k (l 5) (m (n 6)))"]);
+        assert_eq!(1, s.query_with_config(expr!(s, "{depth=1}"), &MatchConfig::default(), |_| {}).unwrap());
+        // `()` nests one level, so `(o () p)` two
+        assert_eq!(2, s.query_with_config(expr!(s, "{depth=2}"), &MatchConfig::default(), |_| {}).unwrap());
+        assert_eq!(1, s.query_with_config(expr!(s, "[3] o {depth=1} p"), &MatchConfig::default(), |_| {}).unwrap());
+
+        // the token captures like `$`, inside a pattern as well
+        assert_eq!(0, s.query_with_config(expr!(s, "[2] b {depth=3}"), &MatchConfig::default(), |_| {}).unwrap());
+        assert_eq!(1, s.query_with_config(expr!(s, "[2] b {depth=1}"), &MatchConfig::default(), |_| {}).unwrap());
+        assert_eq!(1, s.query_with_config(expr!(s, "[2] $ {depth=0}"), &MatchConfig::default(), |_| {}).unwrap());
+        let mut captured = vec![];
+        s.traverse_pattern(expr!(s, "[3] k {depth=1} {depth=2}"), |refs, _| captured.push(refs.len()));
+        assert_eq!(captured, vec![2]);
+
+        let compiled = s.compile_query(expr!(s, "[2] $ {depth=2}")).unwrap();
+        assert_eq!(1, s.run_query(&compiled, |_, _| {}));
+    }
+
+    #[test]
+    fn depth_token_spelling_in_data_is_a_symbol() {
+        let mut s = Space::new();
+        s.load_sexpr("(limit {depth=2})\n".as_bytes(), expr!(s, "$"), expr!(s, "_1")).unwrap();
+        assert_eq!(s.dump_as_string(expr!(s, "$"), expr!(s, "_1")).unwrap(), "(limit {depth=2})\n");
+        assert_eq!(1, s.query_with_config(expr!(s, "[2] limit {depth=0}"), &MatchConfig::default(), |_| {}).unwrap());
+    }
+
+    #[test]
+    fn binary_frame_across_symbol_tables() {
+        let mut sender = Space::new();
+        sender.load_sexpr("(ancestor_of Abraham_Simpson $x)\n".as_bytes(), expr!(sender, "$"), expr!(sender, "_1")).unwrap();
+        let e = expr!(sender, "[4] parent_of Homer_Jay_Simpson $ [2] Bartholomew_Simpson _1");
+        let mut frame = vec![];
+        serialize_binary(e, &sender.sm, &mut frame).unwrap();
+
+        let mut receiver = Space::new();
+        receiver.load_sexpr("(unrelated_symbol another_one Bartholomew)\n".as_bytes(), expr!(receiver, "$"), expr!(receiver, "_1")).unwrap();
+        let received = deserialize_binary(&mut &frame[..], &receiver.sm).unwrap();
+        assert_eq!(sexpr!(receiver, received.borrow()), sexpr!(sender, e));
+        assert!(deserialize_binary(&mut &frame[..frame.len() - 1], &receiver.sm).is_err());
+    }
+
+    #[test]
+    fn children_at_prefix() {
+        let mut s = Space::new();
+        assert_eq!(16, s.load_sexpr(SEXPRS0.as_bytes(), expr!(s, "$"), expr!(s, "_1")).unwrap());
+        assert_eq!(s.children_at(&Prefix::NONE), vec![item_byte(Tag::Arity(2))]);
+        assert_eq!(s.children_at(&prefix!(s, "[2] address")), vec![item_byte(Tag::Arity(2))]);
+        assert_eq!(s.children_at(&prefix!(s, "[2] age")), vec![item_byte(Tag::SymbolSize(2))]);
+        assert!(s.children_at(&prefix!(s, "[2] nobody")).is_empty());
+    }
+
+    #[test]
+    fn expression_accessors() {
+        let mut s = Space::new();
+        s.load_sexpr("(address (city New York))\n".as_bytes(), expr!(s, "$"), expr!(s, "_1")).unwrap();
+        let facts = s.query_ordered_by(expr!(s, "$"), 0, CaptureOrder::Bytes);
+        let e = Expression::from(facts[0].clone());
+
+        assert_eq!(e.arity(), Some(2));
+        assert_eq!(e.head_symbol(&s.sm), Some(b"address".to_vec()));
+        let city = e.child(1).unwrap();
+        assert_eq!(city.to_sexpr(&s.sm), "(city New York)");
+        assert_eq!(city.arity(), Some(3));
+        assert_eq!(city.head_symbol(&s.sm), Some(b"city".to_vec()));
+        assert_eq!(city.child(2).unwrap().symbol(&s.sm), Some(b"York".to_vec()));
+        assert_eq!(city.child(3), None);
+        assert_eq!(city.child(0).unwrap().arity(), None);
+        assert_eq!(e.to_sexpr(&s.sm), "(address (city New York))");
+    }
+
+    #[test]
+    fn query_symbols_by_prefix() {
+        let mut s = Space::new();
+        assert_eq!(16, s.load_sexpr(SEXPRS0.as_bytes(), expr!(s, "$"), expr!(s, "_1")).unwrap());
+
+        let found: Vec<_> = s.query_symbols(b"phone").iter().map(|sym| s.resolve_symbol(sym).unwrap()).collect();
+        assert_eq!(found, vec![b"phone_numbers".to_vec()]);
+        let found: Vec<_> = s.query_symbols(b"T").iter().map(|sym| s.resolve_symbol(sym).unwrap()).collect();
+        assert!(found.contains(&b"Thomas".to_vec()) && found.contains(&b"Trevor".to_vec()));
+        assert!(s.query_symbols(b"nonexistent").is_empty());
+    }
+
+    #[test]
+    fn subscribe_children() {
+        let mut s = Space::new();
+        let fired = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = fired.clone();
+        let id = s.subscribe(expr!(s, "[2] children $"), move |_| { counter.fetch_add(1, std::sync::atomic::Ordering::Relaxed); });
+
+        assert_eq!(16, s.load_sexpr(SEXPRS0.as_bytes(), expr!(s, "$"), expr!(s, "_1")).unwrap());
+        assert_eq!(3, fired.load(std::sync::atomic::Ordering::Relaxed));
+
+        s.unsubscribe(id);
+        s.load_sexpr("(children (3 Tess))".as_bytes(), expr!(s, "$"), expr!(s, "_1")).unwrap();
+        assert_eq!(3, fired.load(std::sync::atomic::Ordering::Relaxed));
+    }
+
+    #[test]
+    fn derived_view_follows_inserts() {
+        let mut s = Space::new();
+        assert_eq!(16, s.load_sexpr(SEXPRS0.as_bytes(), expr!(s, "$"), expr!(s, "_1")).unwrap());
+        let count = |s: &Space, pattern| s.query_collect(pattern, &MatchConfig::default()).unwrap().results.len();
+
+        let view = s.derived_view(expr!(s, "[2] children [2] $ $"), expr!(s, "[2] child _2"));
+        assert_eq!(view.len(), 3);
+        assert_eq!(count(&s, expr!(s, "[2] child $")), 3);
+
+        s.load_sexpr("(children (3 Tess))\n".as_bytes(), expr!(s, "$"), expr!(s, "_1")).unwrap();
+        assert_eq!(view.len(), 4);
+        assert_eq!(count(&s, expr!(s, "[2] child Tess")), 1);
+
+        assert_eq!(s.remove_derived_view(view), 4);
+        assert_eq!(count(&s, expr!(s, "[2] child $")), 0);
+        s.load_sexpr("(children (4 Ada))\n".as_bytes(), expr!(s, "$"), expr!(s, "_1")).unwrap();
+        assert_eq!(count(&s, expr!(s, "[2] child $")), 0);
+        assert_eq!(count(&s, expr!(s, "[2] children $")), 5);
+    }
+
+    #[test]
+    fn removing_view_keeps_loaded_copies() {
+        let mut s = Space::new();
+        s.load_sexpr("(children (2 Pam))\n(children (1 Bob))\n".as_bytes(), expr!(s, "$"), expr!(s, "_1")).unwrap();
+        let count = |s: &Space, pattern| s.query_collect(pattern, &MatchConfig::default()).unwrap().results.len();
+
+        let view = s.derived_view(expr!(s, "[2] children [2] $ $"), expr!(s, "[2] child _2"));
+        assert_eq!(view.len(), 2);
+        // loading a fact the view derived makes it user data
+        s.load_sexpr("(child Pam)\n".as_bytes(), expr!(s, "$"), expr!(s, "_1")).unwrap();
+        assert_eq!(view.len(), 1);
+
+        assert_eq!(s.remove_derived_view(view), 1);
+        assert_eq!(count(&s, expr!(s, "[2] child $")), 1);
+        assert_eq!(count(&s, expr!(s, "[2] child Pam")), 1);
+    }
+
+    #[test]
+    fn self_feeding_view_is_bounded() {
+        let mut s = Space::new();
+        s.load_sexpr("(s z)\n".as_bytes(), expr!(s, "$"), expr!(s, "_1")).unwrap();
+        let view = s.derived_view(expr!(s, "[2] s $"), expr!(s, "[2] s [2] s _1"));
+        assert_eq!(view.len(), MAX_DERIVED_PASSES);
+
+        // the output the last round derived is written by the next loader, which again stops after the bound
+        s.load_sexpr("(t z)\n".as_bytes(), expr!(s, "$"), expr!(s, "_1")).unwrap();
+        assert_eq!(view.len(), 2*MAX_DERIVED_PASSES);
+        assert_eq!(s.btm.val_count(), 2 + 2*MAX_DERIVED_PASSES);
+
+        {
+            let zh = s.btm.zipper_head();
+            let r = s.load_sexpr_exclusive(&zh, "(u z)".as_bytes(), expr!(s, "$"), expr!(s, "[2] parent _1"));
+            assert_eq!(r, Err(SpaceError::DerivedViewsActive.to_string()));
+        }
+        assert_eq!(s.remove_derived_view(view), 2*MAX_DERIVED_PASSES);
+        let zh = s.btm.zipper_head();
+        assert_eq!(s.load_sexpr_exclusive(&zh, "(u z)".as_bytes(), expr!(s, "$"), expr!(s, "[2] parent _1")), Ok(1));
+    }
+
+    #[test]
+    fn read_snapshot_is_isolated() {
+        let mut s = Space::new();
+        assert_eq!(16, s.load_sexpr(SEXPRS0.as_bytes(), expr!(s, "$"), expr!(s, "_1")).unwrap());
+        let snapshot = s.read_snapshot();
+
+        s.load_sexpr("(children (3 Tess))\n".as_bytes(), expr!(s, "$"), expr!(s, "_1")).unwrap();
+        let children = |s: &Space| s.query_collect(expr!(s, "[2] children $"), &MatchConfig::default()).unwrap().results.len();
+        assert_eq!(children(&snapshot), 3);
+        assert_eq!(children(&s), 4);
+        assert_eq!(snapshot.query_collect(expr!(s, "[2] children [2] 3 Tess"), &MatchConfig::default()).unwrap().results.len(), 0);
+        assert_eq!(s.query_collect(expr!(s, "[2] children [2] 3 Tess"), &MatchConfig::default()).unwrap().results.len(), 1);
+    }
+
+    #[test]
+    fn serialize_paths_roundtrip() {
+        let mut s = Space::new();
+        assert_eq!(16, s.load_sexpr(SEXPRS0.as_bytes(), expr!(s, "$"), expr!(s, "_1")).unwrap());
+
+        let mut buf = Vec::<u8>::new();
+        s.serialize_paths(expr!(s, "$"), &mut buf).unwrap();
+
+        let mut restored = Space { btm: crate::stubs::BytesTrieMap::new(), sm: s.sym_table(), subscriptions: Default::default() };
+        restored.deserialize_paths(&mut &buf[..]).unwrap();
+
+        let mut original_dump = Vec::<u8>::new();
+        s.dump_sexpr(expr!(s, "$"), expr!(s, "_1"), &mut original_dump).unwrap();
+        let mut restored_dump = Vec::<u8>::new();
+        restored.dump_sexpr(expr!(restored, "$"), expr!(restored, "_1"), &mut restored_dump).unwrap();
+        assert_eq!(String::from_utf8(original_dump).unwrap(), String::from_utf8(restored_dump).unwrap());
     }
 
     #[test]
@@ -152,6 +979,212 @@ mod tests {
         });
     }
 
+    #[test]
+    fn query_limited_stops_early() {
+        let mut s = Space::new();
+        assert_eq!(16, s.load_sexpr(SEXPRS0.as_bytes(), expr!(s, "$"), expr!(s, "_1")).unwrap());
+
+        let mut calls = 0;
+        assert_eq!(1, s.query_limited(expr!(s, "[2] children [2] $ $"), 1, |_| calls += 1));
+        assert_eq!(1, calls);
+
+        assert_eq!(3, s.query_limited(expr!(s, "[2] children [2] $ $"), 10, |_| {}));
+    }
+
+    #[test]
+    fn compiled_query_reuse() {
+        let mut s = Space::new();
+        assert_eq!(16, s.load_sexpr(SEXPRS0.as_bytes(), expr!(s, "$"), expr!(s, "_1")).unwrap());
+
+        let compiled = s.compile_query(expr!(s, "[2] children [2] $ $")).unwrap();
+        let run = |s: &Space| {
+            let mut found = vec![];
+            s.run_query(&compiled, |refs, e| {
+                assert_eq!(refs.len(), 2);
+                found.push(sexpr!(s, e));
+            });
+            found
+        };
+        let first = run(&s);
+        assert_eq!(first.len(), 3);
+        assert_eq!(first, run(&s));
+    }
+
+    #[test]
+    fn canonical_variable_numbering() {
+        let s = Space::new();
+        let mut a = [0u8; 64];
+        let mut b = [0u8; 64];
+        let ca = canonicalize_variables(expr!(s, "[4] f _2 _1 _2"), &mut a);
+        let cb = canonicalize_variables(expr!(s, "[4] f _1 _2 _1"), &mut b);
+        let canonical = expr!(s, "[4] f $ $ _1");
+        unsafe {
+            assert_eq!(ca.span().as_ref().unwrap(), cb.span().as_ref().unwrap());
+            assert_eq!(ca.span().as_ref().unwrap(), canonical.span().as_ref().unwrap());
+        }
+    }
+
+    #[test]
+    fn forward_reference_is_rejected() {
+        let s = Space::new();
+        assert!(s.compile_query(expr!(s, "[3] parent $ _1")).is_ok());
+        assert_eq!(s.compile_query(expr!(s, "[3] parent _1 $")).unwrap_err(), PatternError::ForwardReference(0));
+        assert_eq!(s.compile_query(expr!(s, "[3] $ _2 $")).unwrap_err(), PatternError::ForwardReference(1));
+    }
+
+    #[test]
+    fn checked_queries_report_forward_reference() {
+        let mut s = Space::new();
+        s.load_sexpr("(parent Tom Bob)\n".as_bytes(), expr!(s, "$"), expr!(s, "_1")).unwrap();
+        let refused = SpaceError::Pattern(PatternError::ForwardReference(0));
+        assert_eq!(s.query_with_config(expr!(s, "[3] parent _1 $"), &MatchConfig::default(), |_| {}).unwrap_err(), refused);
+        assert_eq!(s.query_collect(expr!(s, "[3] parent _1 $"), &MatchConfig::default()).unwrap_err(), refused);
+        assert_eq!(s.query_cancellable(expr!(s, "[3] parent _1 $"), &CancellationToken::new(), |_| {}).unwrap_err(), refused);
+        // refused before the traversal, even where nothing is stored
+        assert_eq!(s.query_with_config(expr!(s, "[3] child _1 $"), &MatchConfig::default(), |_| {}).unwrap_err(), refused);
+    }
+
+    #[test]
+    fn query_cancelled_from_another_thread() {
+        let facts: String = (0..2000).map(|i| format!("(fact {i})\n")).collect();
+        let mut s = Space::new();
+        assert_eq!(2000, s.load_sexpr(facts.as_bytes(), expr!(s, "$"), expr!(s, "_1")).unwrap());
+
+        let token = CancellationToken::new();
+        let remote = token.clone();
+        let canceller = std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(20));
+            remote.cancel();
+        });
+        let mut seen = 0;
+        let result = s.query_cancellable(expr!(s, "[2] fact $"), &token, |_| {
+            seen += 1;
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        });
+        canceller.join().unwrap();
+
+        assert_eq!(result, Err(SpaceError::Cancelled));
+        assert!(seen < 2000);
+        assert_eq!(s.query_cancellable(expr!(s, "[2] fact $"), &CancellationToken::new(), |_| {}), Ok(2000));
+    }
+
+    #[test]
+    fn arity_histogram_sexprs0() {
+        let mut s = Space::new();
+        assert_eq!(16, s.load_sexpr(SEXPRS0.as_bytes(), expr!(s, "$"), expr!(s, "_1")).unwrap());
+        s.load_sexpr("(a b c)\n(x)\n".as_bytes(), expr!(s, "$"), expr!(s, "_1")).unwrap();
+        assert_eq!(s.arity_histogram(), std::collections::BTreeMap::from([(1, 1), (2, 16), (3, 1)]));
+    }
+
+    #[test]
+    fn query_read_quota() {
+        let mut s = Space::new();
+        assert_eq!(16, s.load_sexpr(SEXPRS0.as_bytes(), expr!(s, "$"), expr!(s, "_1")).unwrap());
+
+        let mut delivered = 0;
+        let result = s.query_with_quota(expr!(s, "[2] children [2] $ $"), 2, |_| delivered += 1);
+        assert_eq!(result, Err(SpaceError::ReadQuotaExceeded { quota: 2 }));
+        assert_eq!(delivered, 2);
+
+        assert_eq!(s.query_with_quota(expr!(s, "[2] children [2] $ $"), 3, |_| {}), Ok(3));
+    }
+
+    #[test]
+    fn null_pattern_is_rejected() {
+        let mut s = Space::new();
+        assert_eq!(16, s.load_sexpr(SEXPRS0.as_bytes(), expr!(s, "$"), expr!(s, "_1")).unwrap());
+        let null = Expr{ ptr: std::ptr::null_mut() };
+        let config = MatchConfig::default();
+        assert_eq!(s.query_with_config(null, &config, |_| {}), Err(SpaceError::InvalidPattern));
+        assert_eq!(s.transform_with_config(null, expr!(s, "_1"), &config), Err(SpaceError::InvalidPattern));
+        assert_eq!(s.transform_with_config(expr!(s, "$"), null, &config), Err(SpaceError::InvalidPattern));
+        assert_eq!(s.btm.val_count(), 16);
+    }
+
+    #[test]
+    fn match_config_limits() {
+        let mut s = Space::new();
+        s.load_sexpr("(f a b c)\n(g (h (i j)))\n".as_bytes(), expr!(s, "$"), expr!(s, "_1")).unwrap();
+
+        let config = MatchConfig { max_references: Some(2), max_depth: Some(2), ..MatchConfig::default() };
+        assert_eq!(s.query_with_config(expr!(s, "[4] f $ $ $"), &config, |_| {}), Err(SpaceError::MaxReferencesExceeded { max_references: 2 }));
+        assert_eq!(s.query_with_config(expr!(s, "[2] g [2] h [2] i $"), &config, |_| {}), Err(SpaceError::MaxDepthExceeded { max_depth: 2 }));
+        assert_eq!(s.transform_with_config(expr!(s, "[4] f $ $ $"), expr!(s, "[2] f3 _3"), &config), Err(SpaceError::MaxReferencesExceeded { max_references: 2 }));
+        assert_eq!(s.query_with_config(expr!(s, "[4] f a $ $"), &config, |_| {}), Ok(1));
+        assert_eq!(s.query_with_config(expr!(s, "[2] g [2] h $"), &config, |_| {}), Ok(1));
+    }
+
+    #[test]
+    fn query_collect_truncates() {
+        let mut s = Space::new();
+        s.load_sexpr("(edge a b)\n(edge b c)\n(edge c d)\n".as_bytes(), expr!(s, "$"), expr!(s, "_1")).unwrap();
+
+        let capped = s.query_collect(expr!(s, "[3] edge $ $"), &MatchConfig { max_results: Some(2), ..MatchConfig::default() }).unwrap();
+        assert_eq!(capped.results.len(), 2);
+        assert!(capped.truncated);
+
+        let exact = s.query_collect(expr!(s, "[3] edge $ $"), &MatchConfig { max_results: Some(3), ..MatchConfig::default() }).unwrap();
+        assert_eq!(exact.results.len(), 3);
+        assert!(!exact.truncated);
+        assert_eq!(s.query_collect(expr!(s, "[3] edge $ $"), &MatchConfig::default()).unwrap(), exact);
+    }
+
+    #[test]
+    fn join_across_spaces() {
+        let mut people = Space::new();
+        people.load_sexpr("(person alice 1)\n(person bob 2)\n".as_bytes(), expr!(people, "$"), expr!(people, "_1")).unwrap();
+        let mut payroll = Space::new();
+        payroll.load_sexpr("(salary 3 10)\n(salary 2 100)\n(salary 1 50)\n(salary 1 60)\n".as_bytes(), expr!(payroll, "$"), expr!(payroll, "_1")).unwrap();
+
+        let pattern_a = named_expr!(people, "[3] person $name $id");
+        let pattern_b = named_expr!(payroll, "[3] salary $id $amount");
+        let mut joined: Vec<_> = Space::join_across(&people, &payroll, &pattern_a, &pattern_b).iter()
+            .map(|(a, b)| (sexpr!(people, a.borrow()), sexpr!(payroll, b.borrow()))).collect();
+        joined.sort();
+        let mut expected = vec![
+            (sexpr!(people, expr!(people, "[3] person alice 1")), sexpr!(payroll, expr!(payroll, "[3] salary 1 50"))),
+            (sexpr!(people, expr!(people, "[3] person alice 1")), sexpr!(payroll, expr!(payroll, "[3] salary 1 60"))),
+            (sexpr!(people, expr!(people, "[3] person bob 2")), sexpr!(payroll, expr!(payroll, "[3] salary 2 100"))),
+        ];
+        expected.sort();
+        assert_eq!(joined, expected);
+    }
+
+    #[test]
+    fn query_named_captures() {
+        let mut s = Space::new();
+        assert_eq!(16, s.load_sexpr(SEXPRS0.as_bytes(), expr!(s, "$"), expr!(s, "_1")).unwrap());
+
+        let pattern = named_expr!(s, "[2] children [2] $index $value");
+        assert_eq!(pattern.names, vec!["index", "value"]);
+        let mut found = vec![];
+        s.query_named(&pattern, |captures| {
+            assert_eq!(captures.len(), 2);
+            found.push((sexpr!(s, captures["index"]), sexpr!(s, captures["value"])));
+        });
+        found.sort();
+        assert_eq!(found, vec![("0".to_string(), "Catherine".to_string()),
+                               ("1".to_string(), "Thomas".to_string()),
+                               ("2".to_string(), "Trevor".to_string())]);
+    }
+
+    #[test]
+    fn traverse_pattern_paths() {
+        let mut s = Space::new();
+        assert_eq!(16, s.load_sexpr(SEXPRS0.as_bytes(), expr!(s, "$"), expr!(s, "_1")).unwrap());
+
+        let mut paths = vec![];
+        let matches = s.traverse_pattern(expr!(s, "[2] children [2] $ $"), |refs, path| {
+            assert_eq!(refs.len(), 2);
+            paths.push(path.to_vec());
+        });
+        assert_eq!(matches, 3);
+
+        let mut found: Vec<_> = paths.iter_mut().map(|p| sexpr!(s, Expr{ ptr: p.as_mut_ptr() })).collect();
+        found.sort();
+        assert_eq!(found, vec!["(children (0 Catherine))", "(children (1 Thomas))", "(children (2 Trevor))"]);
+    }
+
     #[test]
     fn transform_simple() {
         let mut s = Space::new();
@@ -170,6 +1203,111 @@ mod tests {
         });
     }
 
+    #[test]
+    fn transform_counts_collisions() {
+        let mut s = Space::new();
+        s.load_sexpr("(edge a x)\n(edge b x)\n".as_bytes(), expr!(s, "$"), expr!(s, "_1")).unwrap();
+
+        let result = s.transform(expr!(s, "[3] edge $ $"), expr!(s, "[2] target _2"));
+        assert_eq!(result, TransformResult { written: 1, collisions: 1 });
+    }
+
+    #[test]
+    fn two_stage_pipeline() {
+        let mut s = Space::new();
+        s.load_sexpr("(edge a b)\n(edge b c)\n".as_bytes(), expr!(s, "$"), expr!(s, "_1")).unwrap();
+
+        let rejected = unsafe { expr!(s, "[2] source a").span().as_ref().unwrap() }.to_vec();
+        let results = Pipeline::new()
+            .transform(expr!(s, "[3] edge $ $"), expr!(s, "[3] rev _2 _1"))
+            .transform_where(expr!(s, "[3] rev $ $"), expr!(s, "[2] source _2"), move |e| unsafe { e.span().as_ref().unwrap() } != &rejected[..])
+            .run(&mut s).unwrap();
+        assert_eq!(results, vec![TransformResult { written: 2, collisions: 0 }, TransformResult { written: 1, collisions: 0 }]);
+
+        let mut out = Vec::new();
+        s.dump_all_sexpr(&mut out).unwrap();
+        assert_eq!(set_from_newlines(&String::from_utf8(out).unwrap()), set_from_newlines(
+            "(edge a b)\n(edge b c)\n(rev b a)\n(rev c b)\n(source b)\n"));
+    }
+
+    #[test]
+    fn checked_transform_expr() {
+        const _: () = assert!(!template_vars_bound("[2] children $", "[2] child_results _2"));
+        const _: () = assert!(template_vars_bound("[2] a $", "[3] b $ _2"));
+
+        let mut s = Space::new();
+        assert_eq!(16, s.load_sexpr(SEXPRS0.as_bytes(), expr!(s, "$"), expr!(s, "_1")).unwrap());
+        let (pattern, template) = transform_expr!(s, "[2] children [2] $ $" => "[2] child_results _2");
+        assert_eq!(s.transform(pattern, template).written, 3);
+    }
+
+    #[test]
+    fn transform_unbound_template_var() {
+        let mut s = Space::new();
+        assert_eq!(16, s.load_sexpr(SEXPRS0.as_bytes(), expr!(s, "$"), expr!(s, "_1"),).unwrap());
+
+        assert_eq!(s.try_transform(expr!(s, "[2] children [2] $ $"), expr!(s, "[3] child_results _2 _3")), Err(TransformError::UnboundTemplateVar(2)));
+        let refused = SpaceError::Template(TransformError::UnboundTemplateVar(2));
+        assert_eq!(s.transform_with_config(expr!(s, "[2] children [2] $ $"), expr!(s, "[3] child_results _2 _3"), &MatchConfig::default()).unwrap_err(), refused);
+        assert_eq!(s.transform_cancellable(expr!(s, "[2] children [2] $ $"), expr!(s, "[3] child_results _2 _3"), &CancellationToken::new()).unwrap_err(), refused);
+        let mut written = 0;
+        s.query(expr!(s, "[3] child_results $ $"), |_, _| written += 1);
+        assert_eq!(written, 0);
+
+        assert!(s.try_transform(expr!(s, "[2] children [2] $ $"), expr!(s, "[3] child_results _2 _1")).is_ok());
+        s.query(expr!(s, "[3] child_results $ $"), |_, _| written += 1);
+        assert_eq!(written, 3);
+    }
+
+    #[test]
+    fn transform_to_writer_leaves_space_alone() {
+        let mut s = Space::new();
+        s.load_sexpr("(axiom (= a b))\n(axiom (= (f x) c))\n".as_bytes(), expr!(s, "$"), expr!(s, "_1")).unwrap();
+
+        let mut out = Vec::new();
+        assert_eq!(2, s.transform_to_writer(expr!(s, "[2] axiom [3] = $ $"), expr!(s, "[2] flip [3] = _2 _1"), &mut out).unwrap());
+        assert_eq!(set_from_newlines(&String::from_utf8(out).unwrap()), set_from_newlines("(flip (= b a))\n(flip (= c (f x)))\n"));
+        assert_eq!(s.btm.val_count(), 2);
+    }
+
+    #[test]
+    fn transform_provenance() {
+        let mut s = Space::new();
+        assert_eq!(16, s.load_sexpr(SEXPRS0.as_bytes(), expr!(s, "$"), expr!(s, "_1"),).unwrap());
+
+        s.transform_with_provenance(expr!(s, "[2] children [2] $ $"), expr!(s, "[2] child_results _2"), expr!(s, "[3] derived_from $ $"));
+        let mut res = Vec::<u8>::new();
+        s.dump_sexpr(expr!(s, "[3] derived_from $ $"), expr!(s, "[3] derived_from _1 _2"), &mut res).unwrap();
+        assert_eq!(set_from_newlines(&String::from_utf8(res).unwrap()), set_from_newlines(
+            "(derived_from (child_results Catherine) (children (0 Catherine)))\n\
+             (derived_from (child_results Thomas) (children (1 Thomas)))\n\
+             (derived_from (child_results Trevor) (children (2 Trevor)))\n"));
+    }
+
+    #[test]
+    fn transform_provenance_of_large_facts() {
+        let mut s = Space::new();
+        let atoms: Vec<String> = (0..100).map(|i| format!("atom{i:04}")).collect();
+        let fact = format!("(big ({}))", atoms.join(" "));
+        assert_eq!(1, s.load_sexpr(format!("{fact}\n").as_bytes(), expr!(s, "$"), expr!(s, "_1")).unwrap());
+
+        let (touched, any_new) = s.transform_with_provenance(expr!(s, "[2] big $"), expr!(s, "[3] copy _1 _1"), expr!(s, "[3] derived_from $ $"));
+        assert_eq!(touched, 1);
+        assert!(any_new);
+        let copy = format!("(copy ({0}) ({0}))", atoms.join(" "));
+        let mut res = Vec::<u8>::new();
+        s.dump_all_sexpr(&mut res).unwrap();
+        assert_eq!(set_from_newlines(&String::from_utf8(res).unwrap()), set_from_newlines(&format!("{fact}\n{copy}\n(derived_from {copy} {fact})\n")));
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid provenance template")]
+    fn transform_provenance_unbound_reference() {
+        let mut s = Space::new();
+        s.load_sexpr("(val a)\n".as_bytes(), expr!(s, "$"), expr!(s, "_1")).unwrap();
+        s.transform_with_provenance(expr!(s, "[2] val $"), expr!(s, "[2] seen _1"), expr!(s, "[3] derived_from $ _2"));
+    }
+
     #[test]
     fn transform_multi() {
         let mut s = Space::new();
@@ -256,12 +1394,7 @@ mod tests {
 
         s.transform_multi_multi(&[expr!(s, "a")], &[expr!(s, "c")]);
 
-        let mut writer = Vec::new();
-        s.dump_sexpr(expr!(s, "$"), expr!(s, "_1"), &mut writer);
-
-        let out = unsafe {
-            core::mem::transmute::<_,String>(writer)
-        };
+        let out = s.dump_as_string(expr!(s, "$"), expr!(s, "_1")).unwrap();
 
         println!("{}", out);
 
@@ -269,6 +1402,40 @@ mod tests {
     }
 
 
+    #[test]
+    fn dump_as_string_rejects_invalid_utf8() {
+        let mut s = Space::new();
+        s.load_sexpr("(name Pam)\n".as_bytes(), expr!(s, "$"), expr!(s, "_1")).unwrap();
+        assert_eq!(s.dump_as_string(expr!(s, "$"), expr!(s, "_1")).unwrap(), "(name Pam)\n");
+
+        let sym = s.intern_symbol(&[b'P', 0xff, 0xfe]);
+        let mut path = vec![item_byte(Tag::SymbolSize(sym.len() as u8))];
+        path.extend_from_slice(&sym);
+        s.btm.insert(&path, ());
+        let err = s.dump_as_string(expr!(s, "$"), expr!(s, "_1")).unwrap_err();
+        assert!(err.as_bytes().windows(2).any(|w| w == [0xff, 0xfe]));
+    }
+
+    #[test]
+    fn transform_multi_dedup_matches() {
+        let mut s = Space::new();
+        s.load_sexpr("(val a)\n(val b)\n".as_bytes(), expr!(s, "$"), expr!(s, "_1")).unwrap();
+
+        // (a a) (a b) (b a) (b b); with dedup (b a) repeats the facts of (a b)
+        let (touched, any_new) = s.transform_multi_multi_with_config(&[expr!(s, "[2] val $"), expr!(s, "[2] val $")], &[expr!(s, "[2] seen _1")], &MatchConfig::default());
+        assert_eq!(touched, 4);
+        assert!(any_new);
+
+        let config = MatchConfig { dedup_matches: true, ..MatchConfig::default() };
+        let (touched, any_new) = s.transform_multi_multi_with_config(&[expr!(s, "[2] val $"), expr!(s, "[2] val $")], &[expr!(s, "[2] seen _1")], &config);
+        assert_eq!(touched, 3);
+        assert!(!any_new);
+
+        let mut writer = Vec::new();
+        s.dump_sexpr(expr!(s, "[2] seen $"), expr!(s, "_1"), &mut writer).unwrap();
+        assert_eq!(String::from_utf8(writer).unwrap(), "a\nb\n");
+    }
+
     #[test]
     fn transform_multi_multi_ignoring_second_template() {
         let mut s = Space::new();
@@ -281,12 +1448,7 @@ mod tests {
 
         s.transform_multi_multi(&[expr!(s, "[3] val $ $")], &[expr!(s, "_1"), expr!(s, "_2")]);
 
-        let mut writer = Vec::new();
-        s.dump_sexpr(expr!(s, "$"), expr!(s, "_1"), &mut writer);
-
-        let out = unsafe {
-            core::mem::transmute::<_,String>(writer)
-        };
+        let out = s.dump_as_string(expr!(s, "$"), expr!(s, "_1")).unwrap();
 
         println!("{}", out);
 
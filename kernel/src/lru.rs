@@ -0,0 +1,60 @@
+// Bounded map with least-recently-used eviction, backing the engines' compile and result caches
+
+use std::borrow::Borrow;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+
+/// A `HashMap` holding at most `capacity` entries; inserting into a full map evicts the entry that was
+/// least recently read or written
+#[derive(Debug, Clone)]
+pub(crate) struct LruMap<K, V> {
+    entries: HashMap<K, (V, u64)>,
+    /// Keys by the tick of their last use, oldest first
+    recency: BTreeMap<u64, K>,
+    clock: u64,
+    capacity: usize,
+}
+
+impl<K: Clone + Eq + Hash, V> LruMap<K, V> {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self { entries: HashMap::new(), recency: BTreeMap::new(), clock: 0, capacity }
+    }
+
+    /// The value at `key`, marking it as the most recently used
+    pub(crate) fn get<Q: ?Sized + Eq + Hash>(&mut self, key: &Q) -> Option<&V> where K: Borrow<Q> {
+        self.clock += 1;
+        let (value, last_used) = self.entries.get_mut(key)?;
+        let k = self.recency.remove(last_used).unwrap();
+        *last_used = self.clock;
+        self.recency.insert(self.clock, k);
+        Some(value)
+    }
+
+    /// Stores `value` at `key` as the most recently used entry; returns how many entries were evicted to make room
+    pub(crate) fn insert(&mut self, key: K, value: V) -> usize {
+        self.clock += 1;
+        if let Some((_, last_used)) = self.entries.remove(&key) {
+            self.recency.remove(&last_used);
+        }
+        let mut evicted = 0;
+        while !self.entries.is_empty() && self.entries.len() >= self.capacity {
+            let (_, oldest) = self.recency.pop_first().unwrap();
+            self.entries.remove(&oldest);
+            evicted += 1;
+        }
+        if self.capacity > 0 {
+            self.recency.insert(self.clock, key.clone());
+            self.entries.insert(key, (value, self.clock));
+        }
+        evicted
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.entries.clear();
+        self.recency.clear();
+    }
+}
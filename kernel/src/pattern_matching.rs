@@ -2,16 +2,25 @@
 // Powers S-expression/space pattern engines
 
 use std::collections::{HashMap, HashSet, BTreeMap};
-use crate::expr_query::{ExprStructure, ExprId};
+use crate::expr_query::ExprStructure;
+use crate::lru::LruMap;
 
 /// Enhanced unification engine for S-expressions
 pub struct UnificationEngine {
-    /// Cache for unification results
-    unification_cache: HashMap<(PatternId, ExprId), UnificationResult>,
+    /// Cache for unification results, keyed by the expression's 128-bit fingerprint
+    unification_cache: LruMap<(PatternId, ExprFingerprint), UnificationResult>,
     /// Compiled patterns for reuse
-    pattern_cache: HashMap<String, CompiledPattern>,
+    pattern_cache: LruMap<String, CompiledPattern>,
+    /// Ids the alternatives of a pattern are cached under, by the pattern's id and the alternative's index
+    alternative_ids: HashMap<(PatternId, usize), PatternId>,
     /// Configuration
     config: UnificationConfig,
+    /// Cache effectiveness counters, see `stats`
+    cache_hits: usize,
+    cache_misses: usize,
+    compile_hits: usize,
+    pattern_evictions: usize,
+    unification_evictions: usize,
 }
 
 /// Configuration for unification behavior
@@ -25,6 +34,8 @@ pub struct UnificationConfig {
     pub enable_caching: bool,
     /// Maximum variable scope
     pub max_variables: usize,
+    /// Most entries kept in each of the pattern and unification caches; the least recently used is evicted to make room
+    pub max_cache_entries: usize,
 }
 
 impl Default for UnificationConfig {
@@ -34,6 +45,7 @@ impl Default for UnificationConfig {
             occurs_check: true,
             enable_caching: true,
             max_variables: 1000,
+            max_cache_entries: 4096,
         }
     }
 }
@@ -41,7 +53,44 @@ impl Default for UnificationConfig {
 /// Unique identifier for patterns
 pub type PatternId = u64;
 
+/// Source of pattern ids, shared by all engines so a pattern compiled by one never collides with another's
+static NEXT_PATTERN_ID: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(1);
+
+fn fresh_pattern_id() -> PatternId {
+    NEXT_PATTERN_ID.fetch_add(1, std::sync::atomic::Ordering::Relaxed)
+}
+
+/// Structural fingerprint of an expression, identifying it in the unification cache without a copy of it
+pub type ExprFingerprint = u128;
+
+/// Feeds one traversal of a value to two differently seeded hashers, whose outputs make up an `ExprFingerprint`
+struct FingerprintHasher(std::collections::hash_map::DefaultHasher, std::collections::hash_map::DefaultHasher);
+
+impl std::hash::Hasher for FingerprintHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        self.0.write(bytes);
+        self.1.write(bytes);
+    }
+    
+    fn finish(&self) -> u64 {
+        self.0.finish()
+    }
+}
+
+/// The fingerprint `unify` caches `expr`'s results under; equal expressions have equal fingerprints, and
+/// distinct ones share one with negligible probability
+pub fn expr_fingerprint(expr: &ExprStructure) -> ExprFingerprint {
+    use std::hash::{Hash, Hasher};
+    let mut hasher = FingerprintHasher(Default::default(), Default::default());
+    hasher.1.write_u8(0x5a);
+    expr.hash(&mut hasher);
+    ((hasher.0.finish() as u128) << 64) | hasher.1.finish() as u128
+}
+
 /// Compiled pattern for efficient matching
+///
+/// Unification results are cached under `id`; the engine gives every pattern it builds (`compile_pattern`,
+/// `with_condition`) a distinct one, so a pattern edited afterwards must not be unified under its old id.
 #[derive(Debug, Clone)]
 pub struct CompiledPattern {
     pub id: PatternId,
@@ -137,10 +186,26 @@ pub enum Condition {
     Unifiable(Box<PatternStructure>),
     /// Expression must have specific properties
     Property(PropertyCheck),
+    /// Checked after the sub-pattern matches, against the bindings accumulated so far
+    BindingPredicate(fn(&VariableBindings) -> bool),
+}
+
+/// Predicates compare by address, which is only as reliable as `fn` pointer identity: the same function may
+/// have several addresses (e.g. across codegen units), so equal conditions can compare unequal
+impl PartialEq for Condition {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Condition::Predicate(a), Condition::Predicate(b)) => std::ptr::fn_addr_eq(*a, *b),
+            (Condition::Unifiable(a), Condition::Unifiable(b)) => a == b,
+            (Condition::Property(a), Condition::Property(b)) => a == b,
+            (Condition::BindingPredicate(a), Condition::BindingPredicate(b)) => std::ptr::fn_addr_eq(*a, *b),
+            _ => false,
+        }
+    }
 }
 
 /// Property checks
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum PropertyCheck {
     IsSymbol,
     IsVariable,
@@ -159,6 +224,8 @@ pub struct UnificationResult {
     pub bindings: VariableBindings,
     pub constraints_satisfied: bool,
     pub execution_time: std::time::Duration,
+    /// Why matching was aborted, when it failed on an engine limit rather than a mismatch
+    pub error: Option<UnificationError>,
 }
 
 /// Variable bindings from unification
@@ -182,6 +249,8 @@ pub struct MatchingContext {
     variables: HashMap<Variable, ExprStructure>,
     constraints: Vec<Constraint>,
     max_depth: usize,
+    max_variables: usize,
+    error: Option<UnificationError>,
 }
 
 impl UnificationEngine {
@@ -191,42 +260,74 @@ impl UnificationEngine {
     
     pub fn with_config(config: UnificationConfig) -> Self {
         Self {
-            unification_cache: HashMap::new(),
-            pattern_cache: HashMap::new(),
+            unification_cache: LruMap::new(config.max_cache_entries),
+            pattern_cache: LruMap::new(config.max_cache_entries),
+            alternative_ids: HashMap::new(),
             config,
+            cache_hits: 0,
+            cache_misses: 0,
+            compile_hits: 0,
+            pattern_evictions: 0,
+            unification_evictions: 0,
         }
     }
     
     /// Compile a pattern from string representation
     pub fn compile_pattern(&mut self, pattern_str: &str) -> Result<CompiledPattern, UnificationError> {
         if let Some(cached) = self.pattern_cache.get(pattern_str) {
+            self.compile_hits += 1;
             return Ok(cached.clone());
         }
         
         let mut parser = PatternParser::new(pattern_str);
-        let pattern = parser.parse()?;
+        let (structure, variables, constraints) = parser.parse()?;
+        // Unification results are cached per pattern id, so every compiled pattern needs its own
+        let pattern = CompiledPattern { id: fresh_pattern_id(), structure, variables, constraints };
         
         if self.config.enable_caching {
-            self.pattern_cache.insert(pattern_str.to_string(), pattern.clone());
+            self.pattern_evictions += self.pattern_cache.insert(pattern_str.to_string(), pattern.clone());
         }
         
         Ok(pattern)
     }
     
+    /// `pattern` restricted by `condition`, for conditions the pattern syntax cannot express
+    /// (such as `Condition::BindingPredicate`); the result is a new pattern with its own id
+    pub fn with_condition(&self, pattern: &CompiledPattern, condition: Condition) -> CompiledPattern {
+        CompiledPattern {
+            id: fresh_pattern_id(),
+            structure: PatternStructure::Conditional {
+                pattern: Box::new(pattern.structure.clone()),
+                condition,
+            },
+            variables: pattern.variables.clone(),
+            constraints: pattern.constraints.clone(),
+        }
+    }
+    
     /// Unify an expression with a pattern
     pub fn unify(&mut self, expr: &ExprStructure, pattern: &CompiledPattern) -> UnificationResult {
-        let start_time = std::time::Instant::now();
+        if !self.config.enable_caching {
+            return self.unify_uncached(expr, pattern);
+        }
         
-        // Check cache if enabled
-        if self.config.enable_caching {
-            // For simplicity, we'll create a dummy ExprId - in real implementation this would be proper
-            let expr_id = 0;
-            if let Some(cached) = self.unification_cache.get(&(pattern.id, expr_id)) {
-                return cached.clone();
-            }
+        let key = (pattern.id, expr_fingerprint(expr));
+        if let Some(cached) = self.unification_cache.get(&key) {
+            self.cache_hits += 1;
+            return cached.clone();
         }
+        self.cache_misses += 1;
         
-        let mut context = MatchingContext::new(self.config.max_depth);
+        let result = self.unify_uncached(expr, pattern);
+        self.unification_evictions += self.unification_cache.insert(key, result.clone());
+        result
+    }
+    
+    /// Unify without consulting the cache, for patterns derived from a compiled one that share its id
+    fn unify_uncached(&self, expr: &ExprStructure, pattern: &CompiledPattern) -> UnificationResult {
+        let start_time = std::time::Instant::now();
+        
+        let mut context = MatchingContext::new(self.config.max_depth, self.config.max_variables);
         let success = self.unify_recursive(expr, &pattern.structure, &mut context);
         
         let bindings = VariableBindings {
@@ -235,12 +336,14 @@ impl UnificationEngine {
         };
         
         let constraints_satisfied = bindings.constraints.iter().all(|c| c.satisfied);
+        let success = success && constraints_satisfied;
         
         let result = UnificationResult {
-            success: success && constraints_satisfied,
+            success,
             bindings,
             constraints_satisfied,
             execution_time: start_time.elapsed(),
+            error: context.error.filter(|_| !success),
         };
         
         result
@@ -260,28 +363,112 @@ impl UnificationEngine {
         matches
     }
     
+    /// Parallel `find_matches`, splitting `expressions` across the rayon thread pool
+    ///
+    /// Workers only read the engine, so the unification cache is neither consulted nor filled
+    /// and the cache counters are left untouched. Results are in expression order.
+    #[cfg(feature = "rayon")]
+    pub fn find_matches_parallel(&self, pattern: &CompiledPattern, expressions: &[ExprStructure]) -> Vec<(usize, UnificationResult)> {
+        use rayon::prelude::*;
+        
+        expressions.par_iter().enumerate()
+            .map(|(idx, expr)| (idx, self.unify_uncached(expr, pattern)))
+            .filter(|(_, result)| result.success)
+            .collect()
+    }
+    
     /// Pattern matching with multiple patterns
     pub fn multi_pattern_match(&mut self, patterns: &[CompiledPattern], expressions: &[ExprStructure]) -> MultiMatchResult {
+        self.multi_pattern_match_with_timeout(patterns, expressions, None)
+    }
+    
+    /// `multi_pattern_match` that gives up once `timeout` has elapsed, checked after each expression
+    ///
+    /// A timed-out result holds the matches found so far: the pattern being matched when time ran out
+    /// has the matches among the expressions already tried, and the patterns after it have no entry.
+    pub fn multi_pattern_match_with_timeout(&mut self, patterns: &[CompiledPattern], expressions: &[ExprStructure], timeout: Option<std::time::Duration>) -> MultiMatchResult {
+        let start_time = std::time::Instant::now();
         let mut results = HashMap::new();
+        let mut timed_out = false;
         
-        for (pattern_idx, pattern) in patterns.iter().enumerate() {
-            let matches = self.find_matches(pattern, expressions);
-            results.insert(pattern_idx, matches);
+        'patterns: for (pattern_idx, pattern) in patterns.iter().enumerate() {
+            let matches: &mut Vec<_> = results.entry(pattern_idx).or_default();
+            for (idx, expr) in expressions.iter().enumerate() {
+                let result = self.unify(expr, pattern);
+                if result.success {
+                    matches.push((idx, result));
+                }
+                if timeout.is_some_and(|t| start_time.elapsed() >= t) && (idx + 1 < expressions.len() || pattern_idx + 1 < patterns.len()) {
+                    timed_out = true;
+                    break 'patterns;
+                }
+            }
         }
         
         MultiMatchResult {
             pattern_matches: results,
             total_patterns: patterns.len(),
             total_expressions: expressions.len(),
+            timed_out,
         }
     }
     
+    /// Unify like `unify`, but on failure report the first mismatch found
+    pub fn explain(&mut self, expr: &ExprStructure, pattern: &CompiledPattern) -> Result<VariableBindings, UnificationFailureReason> {
+        let mut context = MatchingContext::new(self.config.max_depth, self.config.max_variables);
+        self.explain_recursive(expr, &pattern.structure, &mut context)?;
+        
+        let constraints = self.check_constraints(&pattern.constraints, &context.variables);
+        if let Some(violated) = constraints.iter().find(|c| !c.satisfied) {
+            return Err(UnificationFailureReason::ConstraintViolation {
+                constraint: violated.constraint.clone(),
+                reason: violated.reason.clone(),
+            });
+        }
+        
+        Ok(VariableBindings {
+            bindings: context.variables,
+            constraints,
+        })
+    }
+    
+    /// Build a matcher that indexes `patterns` by compound arity and head symbol, so each
+    /// expression is only unified against the patterns that could possibly match it
+    pub fn compile_matcher<'a>(&'a self, patterns: &[CompiledPattern]) -> PatternMatcher<'a> {
+        let mut matcher = PatternMatcher {
+            engine: self,
+            patterns: patterns.to_vec(),
+            by_head: HashMap::new(),
+            by_arity: HashMap::new(),
+            generic: Vec::new(),
+            unify_calls: std::cell::Cell::new(0),
+        };
+        
+        for (idx, pattern) in patterns.iter().enumerate() {
+            match &pattern.structure {
+                PatternStructure::Compound { arity, patterns } => match patterns.first() {
+                    Some(PatternStructure::Symbol(head)) => matcher.by_head.entry((*arity, head.clone())).or_default().push(idx),
+                    _ => matcher.by_arity.entry(*arity).or_default().push(idx),
+                },
+                _ => matcher.generic.push(idx),
+            }
+        }
+        
+        matcher
+    }
+    
     /// Enhanced unification with constraint propagation
+    ///
+    /// The match of `pattern` itself goes through the cache like `unify`; the additional constraints are
+    /// then checked against its bindings.
     pub fn unify_with_constraints(&mut self, expr: &ExprStructure, pattern: &CompiledPattern, additional_constraints: &[Constraint]) -> UnificationResult {
-        let mut enhanced_pattern = pattern.clone();
-        enhanced_pattern.constraints.extend_from_slice(additional_constraints);
-        
-        self.unify(expr, &enhanced_pattern)
+        let mut result = self.unify(expr, pattern);
+        let additional = self.check_constraints(additional_constraints, &result.bindings.bindings);
+        let additional_satisfied = additional.iter().all(|c| c.satisfied);
+        result.bindings.constraints.extend(additional);
+        result.constraints_satisfied &= additional_satisfied;
+        result.success &= additional_satisfied;
+        result
     }
     
     /// Generate all possible unifications
@@ -290,9 +477,11 @@ impl UnificationEngine {
         match &pattern.structure {
             PatternStructure::Alternative(alternatives) => {
                 let mut results = Vec::new();
-                for alt in alternatives {
+                for (idx, alt) in alternatives.iter().enumerate() {
+                    // each alternative is cached under an id of its own, assigned the first time it is tried
+                    let id = *self.alternative_ids.entry((pattern.id, idx)).or_insert_with(fresh_pattern_id);
                     let alt_pattern = CompiledPattern {
-                        id: pattern.id,
+                        id,
                         structure: alt.clone(),
                         variables: pattern.variables.clone(),
                         constraints: pattern.constraints.clone(),
@@ -314,6 +503,7 @@ impl UnificationEngine {
     pub fn clear_cache(&mut self) {
         self.unification_cache.clear();
         self.pattern_cache.clear();
+        self.alternative_ids.clear();
     }
     
     /// Get engine statistics
@@ -322,9 +512,23 @@ impl UnificationEngine {
             cached_patterns: self.pattern_cache.len(),
             cached_unifications: self.unification_cache.len(),
             cache_enabled: self.config.enable_caching,
+            cache_hits: self.cache_hits,
+            cache_misses: self.cache_misses,
+            compile_hits: self.compile_hits,
+            pattern_evictions: self.pattern_evictions,
+            unification_evictions: self.unification_evictions,
         }
     }
     
+    /// Zero the hit/miss and eviction counters reported by `stats`, leaving the caches themselves intact
+    pub fn reset_stats(&mut self) {
+        self.cache_hits = 0;
+        self.cache_misses = 0;
+        self.compile_hits = 0;
+        self.pattern_evictions = 0;
+        self.unification_evictions = 0;
+    }
+    
     // Private implementation methods
     
     fn unify_recursive(&self, expr: &ExprStructure, pattern: &PatternStructure, context: &mut MatchingContext) -> bool {
@@ -349,6 +553,13 @@ impl UnificationEngine {
                 c1.iter().zip(p2.iter()).all(|(child, pat)| self.unify_recursive(child, pat, context))
             },
             
+            (expr, PatternStructure::Conditional { pattern, condition: Condition::BindingPredicate(pred) }) => {
+                self.unify_recursive(expr, pattern, context) && pred(&VariableBindings {
+                    bindings: context.variables.clone(),
+                    constraints: Vec::new(),
+                })
+            },
+            
             (expr, PatternStructure::Conditional { pattern, condition }) => {
                 self.check_condition(expr, condition) && self.unify_recursive(expr, pattern, context)
             },
@@ -368,12 +579,71 @@ impl UnificationEngine {
         result
     }
     
+    fn explain_recursive(&self, expr: &ExprStructure, pattern: &PatternStructure, context: &mut MatchingContext) -> Result<(), UnificationFailureReason> {
+        match (expr, pattern) {
+            (_, PatternStructure::Wildcard) => Ok(()),
+            
+            (expr, PatternStructure::Variable(var)) => {
+                if self.bind_variable(expr, var, context) {
+                    Ok(())
+                } else if let Some(error) = context.error.take() {
+                    Err(UnificationFailureReason::Limit(error))
+                } else {
+                    Err(UnificationFailureReason::VariableMismatch { variable: var.clone(), found: expr.clone() })
+                }
+            },
+            
+            (ExprStructure::Symbol(found), PatternStructure::Symbol(expected)) => {
+                if found == expected {
+                    Ok(())
+                } else {
+                    Err(UnificationFailureReason::SymbolMismatch { expected: expected.clone(), found: found.clone() })
+                }
+            },
+            
+            (ExprStructure::Compound { arity: found, children },
+             PatternStructure::Compound { arity: expected, patterns }) => {
+                if found != expected || children.len() != patterns.len() {
+                    return Err(UnificationFailureReason::ArityMismatch { expected: *expected, found: *found });
+                }
+                if context.depth >= context.max_depth {
+                    return Err(UnificationFailureReason::Limit(UnificationError::RecursionLimit));
+                }
+                context.depth += 1;
+                let result = children.iter().zip(patterns.iter())
+                    .try_for_each(|(child, pat)| self.explain_recursive(child, pat, context));
+                context.depth -= 1;
+                result
+            },
+            
+            (ExprStructure::Symbol(_) | ExprStructure::Variable(_), PatternStructure::Compound { .. })
+            | (ExprStructure::Compound { .. } | ExprStructure::Variable(_), PatternStructure::Symbol(_)) => {
+                Err(UnificationFailureReason::ShapeMismatch { expected: pattern.clone(), found: expr.clone() })
+            },
+            
+            // Conditions, alternatives and sequences are only reported as a whole
+            _ => {
+                if self.unify_recursive(expr, pattern, context) {
+                    Ok(())
+                } else {
+                    Err(UnificationFailureReason::PatternMismatch { pattern: pattern.clone(), found: expr.clone() })
+                }
+            },
+        }
+    }
+    
     fn bind_variable(&self, expr: &ExprStructure, var: &Variable, context: &mut MatchingContext) -> bool {
         // Check if variable is already bound
         if let Some(existing) = context.variables.get(var) {
             // Must unify with existing binding
             self.expressions_equal(expr, existing)
         } else {
+            if context.variables.len() >= context.max_variables {
+                context.error.get_or_insert_with(|| UnificationError::ConstraintViolation(
+                    format!("more than {} variables bound", context.max_variables)));
+                return false;
+            }
+            
             // Check type compatibility
             if self.type_compatible(expr, &var.var_type) {
                 context.variables.insert(var.clone(), expr.clone());
@@ -410,6 +680,8 @@ impl UnificationEngine {
         match condition {
             Condition::Predicate(pred) => pred(expr),
             Condition::Property(prop) => self.check_property(expr, prop),
+            // Needs the bindings, so `unify_recursive` evaluates it after the sub-pattern
+            Condition::BindingPredicate(_) => true,
             Condition::Unifiable(pattern) => {
                 // Simplified unifiability check
                 match (expr, pattern.as_ref()) {
@@ -459,12 +731,7 @@ impl UnificationEngine {
     }
     
     fn calculate_depth(&self, expr: &ExprStructure) -> usize {
-        match expr {
-            ExprStructure::Symbol(_) | ExprStructure::Variable(_) => 1,
-            ExprStructure::Compound { children, .. } => {
-                1 + children.iter().map(|c| self.calculate_depth(c)).max().unwrap_or(0)
-            },
-        }
+        expr.depth()
     }
     
     fn match_sequence(&self, children: &[ExprStructure], patterns: &[PatternStructure], min_matches: usize, max_matches: Option<usize>, context: &mut MatchingContext) -> bool {
@@ -567,22 +834,19 @@ impl UnificationEngine {
     }
     
     fn calculate_size(&self, expr: &ExprStructure) -> usize {
-        match expr {
-            ExprStructure::Symbol(_) | ExprStructure::Variable(_) => 1,
-            ExprStructure::Compound { children, .. } => {
-                1 + children.iter().map(|c| self.calculate_size(c)).sum::<usize>()
-            },
-        }
+        expr.size()
     }
 }
 
 impl MatchingContext {
-    fn new(max_depth: usize) -> Self {
+    fn new(max_depth: usize, max_variables: usize) -> Self {
         Self {
             depth: 0,
             variables: HashMap::new(),
             constraints: Vec::new(),
             max_depth,
+            max_variables,
+            error: None,
         }
     }
 }
@@ -593,6 +857,52 @@ pub struct MultiMatchResult {
     pub pattern_matches: HashMap<usize, Vec<(usize, UnificationResult)>>,
     pub total_patterns: usize,
     pub total_expressions: usize,
+    /// Set when a timeout stopped matching before every pattern had seen every expression
+    pub timed_out: bool,
+}
+
+/// Discrimination index over a fixed set of patterns, see `UnificationEngine::compile_matcher`
+pub struct PatternMatcher<'a> {
+    engine: &'a UnificationEngine,
+    patterns: Vec<CompiledPattern>,
+    /// Compound patterns with a constant head, keyed by (arity, head)
+    by_head: HashMap<(usize, Vec<u8>), Vec<usize>>,
+    /// Compound patterns whose head is not a constant symbol
+    by_arity: HashMap<usize, Vec<usize>>,
+    /// Patterns that may match any expression shape
+    generic: Vec<usize>,
+    unify_calls: std::cell::Cell<usize>,
+}
+
+impl<'a> PatternMatcher<'a> {
+    /// Ids of the patterns matching `expr`, in the order the patterns were given
+    pub fn match_one(&self, expr: &ExprStructure) -> Vec<PatternId> {
+        let mut candidates: Vec<usize> = self.generic.clone();
+        if let ExprStructure::Compound { arity, children } = expr {
+            if let Some(ExprStructure::Symbol(head)) = children.first() {
+                if let Some(indices) = self.by_head.get(&(*arity, head.clone())) {
+                    candidates.extend_from_slice(indices);
+                }
+            }
+            if let Some(indices) = self.by_arity.get(arity) {
+                candidates.extend_from_slice(indices);
+            }
+        }
+        candidates.sort_unstable();
+        
+        candidates.into_iter()
+            .filter(|&idx| {
+                self.unify_calls.set(self.unify_calls.get() + 1);
+                self.engine.unify_uncached(expr, &self.patterns[idx]).success
+            })
+            .map(|idx| self.patterns[idx].id)
+            .collect()
+    }
+    
+    /// Number of unifications `match_one` has attempted so far
+    pub fn unify_calls(&self) -> usize {
+        self.unify_calls.get()
+    }
 }
 
 /// Statistics about the unification engine
@@ -601,6 +911,52 @@ pub struct UnificationStats {
     pub cached_patterns: usize,
     pub cached_unifications: usize,
     pub cache_enabled: bool,
+    /// `unify` calls answered from the unification cache
+    pub cache_hits: usize,
+    /// `unify` calls that had to run the matcher
+    pub cache_misses: usize,
+    /// `compile_pattern` calls answered from the pattern cache
+    pub compile_hits: usize,
+    /// Compiled patterns dropped to respect `max_cache_entries`
+    pub pattern_evictions: usize,
+    /// Unification results dropped to respect `max_cache_entries`
+    pub unification_evictions: usize,
+}
+
+/// First point of failure found by `UnificationEngine::explain`
+#[derive(Debug, Clone)]
+pub enum UnificationFailureReason {
+    /// Two constant symbols differ
+    SymbolMismatch { expected: Vec<u8>, found: Vec<u8> },
+    /// A compound has the wrong number of children
+    ArityMismatch { expected: usize, found: usize },
+    /// A symbol was found where a compound was expected, or vice versa
+    ShapeMismatch { expected: PatternStructure, found: ExprStructure },
+    /// A variable is of the wrong type or already bound to something else
+    VariableMismatch { variable: Variable, found: ExprStructure },
+    /// A conditional, alternative or sequence pattern did not match
+    PatternMismatch { pattern: PatternStructure, found: ExprStructure },
+    /// The structure matched but a constraint did not hold
+    ConstraintViolation { constraint: Constraint, reason: Option<String> },
+    /// An engine limit was hit
+    Limit(UnificationError),
+}
+
+impl std::fmt::Display for UnificationFailureReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::SymbolMismatch { expected, found } => write!(f, "Symbol mismatch: expected {}, found {}", String::from_utf8_lossy(expected), String::from_utf8_lossy(found)),
+            Self::ArityMismatch { expected, found } => write!(f, "Arity mismatch: expected {}, found {}", expected, found),
+            Self::ShapeMismatch { expected, found } => write!(f, "Shape mismatch: expected {:?}, found {:?}", expected, found),
+            Self::VariableMismatch { variable, found } => write!(f, "Variable {} cannot bind {:?}", variable.name, found),
+            Self::PatternMismatch { pattern, found } => write!(f, "Pattern {:?} does not match {:?}", pattern, found),
+            Self::ConstraintViolation { constraint, reason } => match reason {
+                Some(reason) => write!(f, "Constraint violation: {:?} ({})", constraint, reason),
+                None => write!(f, "Constraint violation: {:?}", constraint),
+            },
+            Self::Limit(error) => write!(f, "{}", error),
+        }
+    }
 }
 
 /// Errors in unification
@@ -628,10 +984,18 @@ impl std::fmt::Display for UnificationError {
 impl std::error::Error for UnificationError {}
 
 /// Simple pattern parser
+///
+/// Besides `?` (anonymous variable) and `*` (wildcard), named variables are written `$x`,
+/// optionally with a type annotation `$x:symbol` (`expression`, `symbol`, `compound`, `sequence`).
+/// A trailing `where` clause adds structural constraints on named variables, joined by `and`:
+/// `(add $x $y) where arity($x)==2 and depth($y)==1` (a symbol has depth 1).
 struct PatternParser {
     input: String,
     position: usize,
     next_var_id: u32,
+    /// Named variables in order of first appearance
+    variables: Vec<Variable>,
+    constraints: Vec<Constraint>,
 }
 
 impl PatternParser {
@@ -640,18 +1004,24 @@ impl PatternParser {
             input: input.to_string(),
             position: 0,
             next_var_id: 1,
+            variables: Vec::new(),
+            constraints: Vec::new(),
         }
     }
     
-    fn parse(&mut self) -> Result<CompiledPattern, UnificationError> {
+    /// The pattern's structure, named variables and `where` constraints
+    fn parse(&mut self) -> Result<(PatternStructure, Vec<Variable>, Vec<Constraint>), UnificationError> {
         let structure = self.parse_pattern()?;
         
-        Ok(CompiledPattern {
-            id: 1, // Simplified - would use proper ID generation
-            structure,
-            variables: Vec::new(), // Would collect during parsing
-            constraints: Vec::new(), // Would parse constraints
-        })
+        self.skip_whitespace();
+        if self.position < self.input.len() {
+            if self.parse_word() != "where" {
+                return Err(UnificationError::ParseError(format!("Unexpected input at position {}", self.position)));
+            }
+            self.parse_where_clause()?;
+        }
+        
+        Ok((structure, std::mem::take(&mut self.variables), std::mem::take(&mut self.constraints)))
     }
     
     fn parse_pattern(&mut self) -> Result<PatternStructure, UnificationError> {
@@ -678,6 +1048,9 @@ impl PatternParser {
                 self.next_var_id += 1;
                 Ok(PatternStructure::Variable(var))
             },
+            '$' => {
+                self.parse_named_variable()
+            },
             '(' => {
                 self.parse_compound_pattern()
             },
@@ -718,6 +1091,108 @@ impl PatternParser {
         })
     }
     
+    fn parse_named_variable(&mut self) -> Result<PatternStructure, UnificationError> {
+        let var = self.parse_variable_reference(true)?;
+        
+        if self.current_char() == ':' {
+            self.position += 1; // Skip :
+            let var_type = match self.parse_word().as_str() {
+                "expression" => VariableType::Expression,
+                "symbol" => VariableType::Symbol,
+                "compound" => VariableType::Compound,
+                "sequence" => VariableType::Sequence,
+                other => return Err(UnificationError::ParseError(format!("Unknown variable type '{}'", other))),
+            };
+            self.constraints.push(Constraint::Type(var.clone(), var_type));
+        }
+        
+        Ok(PatternStructure::Variable(var))
+    }
+    
+    /// Parse `$name`, introducing the variable on first sight if `introduce` is set
+    fn parse_variable_reference(&mut self, introduce: bool) -> Result<Variable, UnificationError> {
+        if self.current_char() != '$' {
+            return Err(UnificationError::ParseError(format!("Expected variable at position {}", self.position)));
+        }
+        self.position += 1; // Skip $
+        
+        let name = self.parse_word();
+        if name.is_empty() {
+            return Err(UnificationError::ParseError("Expected variable name after '$'".to_string()));
+        }
+        
+        if let Some(var) = self.variables.iter().find(|v| v.name == name) {
+            return Ok(var.clone());
+        }
+        if !introduce {
+            return Err(UnificationError::ParseError(format!("Unknown variable '${}'", name)));
+        }
+        
+        let var = Variable {
+            name,
+            id: self.next_var_id,
+            var_type: VariableType::Expression,
+        };
+        self.next_var_id += 1;
+        self.variables.push(var.clone());
+        Ok(var)
+    }
+    
+    fn parse_where_clause(&mut self) -> Result<(), UnificationError> {
+        loop {
+            self.skip_whitespace();
+            let property = self.parse_word();
+            self.skip_whitespace();
+            self.expect('(')?;
+            self.skip_whitespace();
+            let var = self.parse_variable_reference(false)?;
+            self.skip_whitespace();
+            self.expect(')')?;
+            self.skip_whitespace();
+            self.expect('=')?;
+            self.expect('=')?;
+            self.skip_whitespace();
+            let value = self.parse_word().parse::<usize>()
+                .map_err(|_| UnificationError::ParseError(format!("Expected number in '{}' constraint", property)))?;
+            
+            let structural = match property.as_str() {
+                "arity" => StructuralConstraint::Arity(value),
+                "depth" => StructuralConstraint::Depth(value),
+                other => return Err(UnificationError::ParseError(format!("Unknown constraint '{}'", other))),
+            };
+            self.constraints.push(Constraint::Structure(var, structural));
+            
+            self.skip_whitespace();
+            if self.position >= self.input.len() {
+                return Ok(());
+            }
+            if self.parse_word() != "and" {
+                return Err(UnificationError::ParseError(format!("Expected 'and' at position {}", self.position)));
+            }
+        }
+    }
+    
+    /// Consume a run of alphanumeric characters and underscores
+    fn parse_word(&mut self) -> String {
+        let start = self.position;
+        while self.position < self.input.len() {
+            let ch = self.current_char();
+            if !(ch.is_alphanumeric() || ch == '_') {
+                break;
+            }
+            self.position += 1;
+        }
+        self.input[start..self.position].to_string()
+    }
+    
+    fn expect(&mut self, expected: char) -> Result<(), UnificationError> {
+        if self.current_char() != expected {
+            return Err(UnificationError::ParseError(format!("Expected '{}' at position {}", expected, self.position)));
+        }
+        self.position += 1;
+        Ok(())
+    }
+    
     fn parse_string_pattern(&mut self) -> Result<PatternStructure, UnificationError> {
         self.position += 1; // Skip opening quote
         
@@ -818,6 +1293,33 @@ mod tests {
         assert!(!result2.success);
     }
     
+    #[test]
+    fn test_multi_pattern_match_timeout() {
+        let mut engine = UnificationEngine::new();
+        let pattern1 = engine.compile_pattern("(add ? ?)").unwrap();
+        let pattern2 = engine.compile_pattern("(sub ? ?)").unwrap();
+        let expressions: Vec<ExprStructure> = (0..10_000).map(|i| ExprStructure::Compound {
+            arity: 3,
+            children: vec![
+                ExprStructure::Symbol(b"add".to_vec()),
+                ExprStructure::Symbol(i.to_string().into_bytes()),
+                ExprStructure::Symbol(b"1".to_vec()),
+            ],
+        }).collect();
+        let patterns = [pattern1, pattern2];
+        
+        let partial = engine.multi_pattern_match_with_timeout(&patterns, &expressions, Some(std::time::Duration::from_nanos(1)));
+        assert!(partial.timed_out);
+        assert!(!partial.pattern_matches.is_empty());
+        assert!(partial.pattern_matches[&0].len() < expressions.len());
+        assert!(!partial.pattern_matches.contains_key(&1));
+        
+        let complete = engine.multi_pattern_match_with_timeout(&patterns, &expressions, Some(std::time::Duration::from_secs(3600)));
+        assert!(!complete.timed_out);
+        assert_eq!(complete.pattern_matches[&0].len(), expressions.len());
+        assert!(complete.pattern_matches[&1].is_empty());
+    }
+    
     #[test]
     fn test_multi_pattern_matching() {
         let mut engine = UnificationEngine::new();
@@ -863,4 +1365,366 @@ mod tests {
         let stats = engine.stats();
         assert_eq!(stats.cached_patterns, 1); // Should only cache once
     }
+    
+    #[test]
+    fn test_parse_type_annotation() {
+        let mut engine = UnificationEngine::new();
+        let pattern = engine.compile_pattern("(add $x:symbol $y)").unwrap();
+        assert_eq!(pattern.variables.len(), 2);
+        assert_eq!(pattern.constraints.len(), 1);
+        assert!(matches!(&pattern.constraints[0], Constraint::Type(var, VariableType::Symbol) if var.name == "x"));
+        
+        let symbol_arg = ExprStructure::Compound {
+            arity: 3,
+            children: vec![
+                ExprStructure::Symbol(b"add".to_vec()),
+                ExprStructure::Symbol(b"a".to_vec()),
+                ExprStructure::Symbol(b"b".to_vec()),
+            ],
+        };
+        let compound_arg = ExprStructure::Compound {
+            arity: 3,
+            children: vec![
+                ExprStructure::Symbol(b"add".to_vec()),
+                ExprStructure::Compound { arity: 1, children: vec![ExprStructure::Symbol(b"f".to_vec())] },
+                ExprStructure::Symbol(b"b".to_vec()),
+            ],
+        };
+        assert!(engine.unify(&symbol_arg, &pattern).success);
+        assert!(!engine.unify(&compound_arg, &pattern).success);
+        
+        assert!(engine.compile_pattern("(add $x:number)").is_err());
+    }
+    
+    #[test]
+    fn test_parse_where_arity() {
+        let mut engine = UnificationEngine::new();
+        let pattern = engine.compile_pattern("(add $x $y) where arity($x)==2").unwrap();
+        assert_eq!(pattern.constraints.len(), 1);
+        assert!(matches!(&pattern.constraints[0], Constraint::Structure(var, StructuralConstraint::Arity(2)) if var.name == "x"));
+        
+        let with_arg = |children: Vec<ExprStructure>| ExprStructure::Compound {
+            arity: 3,
+            children: vec![
+                ExprStructure::Symbol(b"add".to_vec()),
+                ExprStructure::Compound { arity: children.len(), children },
+                ExprStructure::Symbol(b"z".to_vec()),
+            ],
+        };
+        let pair = with_arg(vec![ExprStructure::Symbol(b"f".to_vec()), ExprStructure::Symbol(b"1".to_vec())]);
+        let triple = with_arg(vec![ExprStructure::Symbol(b"f".to_vec()), ExprStructure::Symbol(b"1".to_vec()), ExprStructure::Symbol(b"2".to_vec())]);
+        assert!(engine.unify(&pair, &pattern).success);
+        assert!(!engine.unify(&triple, &pattern).success);
+        
+        assert!(engine.compile_pattern("(add $x) where arity($z)==2").is_err());
+    }
+    
+    #[test]
+    fn test_parse_where_doc_example() {
+        let mut engine = UnificationEngine::new();
+        let pattern = engine.compile_pattern("(add $x $y) where arity($x)==2 and depth($y)==1").unwrap();
+        assert_eq!(pattern.constraints.len(), 2);
+        
+        let add = |y: ExprStructure| ExprStructure::Compound {
+            arity: 3,
+            children: vec![
+                ExprStructure::Symbol(b"add".to_vec()),
+                ExprStructure::Compound { arity: 2, children: vec![ExprStructure::Symbol(b"f".to_vec()), ExprStructure::Symbol(b"1".to_vec())] },
+                y,
+            ],
+        };
+        assert!(engine.unify(&add(ExprStructure::Symbol(b"z".to_vec())), &pattern).success);
+        let nested = ExprStructure::Compound { arity: 2, children: vec![ExprStructure::Symbol(b"g".to_vec()), ExprStructure::Symbol(b"z".to_vec())] };
+        assert!(!engine.unify(&add(nested), &pattern).success);
+    }
+    
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn test_find_matches_parallel() {
+        let mut engine = UnificationEngine::new();
+        let pattern = engine.compile_pattern("(add ? ?)").unwrap();
+        
+        let ops = [b"add".to_vec(), b"sub".to_vec(), b"mul".to_vec()];
+        let expressions: Vec<ExprStructure> = (0..10_000).map(|i| ExprStructure::Compound {
+            arity: 3,
+            children: vec![
+                ExprStructure::Symbol(ops[i % ops.len()].clone()),
+                ExprStructure::Symbol(i.to_string().into_bytes()),
+                ExprStructure::Symbol(b"1".to_vec()),
+            ],
+        }).collect();
+        
+        let parallel: Vec<usize> = engine.find_matches_parallel(&pattern, &expressions).into_iter().map(|(i, _)| i).collect();
+        let serial: Vec<usize> = engine.find_matches(&pattern, &expressions).into_iter().map(|(i, _)| i).collect();
+        assert_eq!(parallel.len(), 3334);
+        assert_eq!(parallel, serial);
+    }
+    
+    #[test]
+    fn test_binding_predicate() {
+        let mut engine = UnificationEngine::new();
+        let same = engine.compile_pattern("(same $x $y)").unwrap();
+        let pattern = engine.with_condition(&same, Condition::BindingPredicate(|bindings| {
+            let value = |name: &str| bindings.bindings.iter().find(|(v, _)| v.name == name).map(|(_, e)| e);
+            value("x").is_some() && value("x") == value("y")
+        }));
+        assert_ne!(pattern.id, same.id);
+        
+        let pair = |a: &[u8], b: &[u8]| ExprStructure::Compound {
+            arity: 3,
+            children: vec![
+                ExprStructure::Symbol(b"same".to_vec()),
+                ExprStructure::Symbol(a.to_vec()),
+                ExprStructure::Symbol(b.to_vec()),
+            ],
+        };
+        assert!(engine.unify(&pair(b"a", b"a"), &pattern).success);
+        assert!(!engine.unify(&pair(b"a", b"b"), &pattern).success);
+        // the unconditioned pattern has its own cache entries
+        assert!(engine.unify(&pair(b"a", b"b"), &same).success);
+    }
+    
+    #[test]
+    fn test_expr_fingerprint() {
+        let f = |x: &[u8]| ExprStructure::Compound { arity: 2, children: vec![ExprStructure::Symbol(b"f".to_vec()), ExprStructure::Symbol(x.to_vec())] };
+        assert_eq!(expr_fingerprint(&f(b"a")), expr_fingerprint(&f(b"a")));
+        assert_ne!(expr_fingerprint(&f(b"a")), expr_fingerprint(&f(b"b")));
+        assert_ne!(expr_fingerprint(&f(b"a")), expr_fingerprint(&ExprStructure::Symbol(b"a".to_vec())));
+    }
+    
+    #[test]
+    fn test_cache_separates_patterns() {
+        let mut engine = UnificationEngine::new();
+        let fa = engine.compile_pattern("(f a)").unwrap();
+        let fb = engine.compile_pattern("(f b)").unwrap();
+        assert_ne!(fa.id, fb.id);
+        
+        let expr = ExprStructure::Compound {
+            arity: 2,
+            children: vec![ExprStructure::Symbol(b"f".to_vec()), ExprStructure::Symbol(b"a".to_vec())],
+        };
+        for _ in 0..2 {
+            assert!(engine.unify(&expr, &fa).success);
+            assert!(!engine.unify(&expr, &fb).success);
+        }
+        let stats = engine.stats();
+        assert_eq!(stats.cache_misses, 2);
+        assert_eq!(stats.cache_hits, 2);
+    }
+    
+    #[test]
+    fn test_max_variables_limit() {
+        let mut engine = UnificationEngine::with_config(UnificationConfig {
+            max_variables: 2,
+            ..UnificationConfig::default()
+        });
+        let three = engine.compile_pattern("(f $a $b $c)").unwrap();
+        let two = engine.compile_pattern("(f $a $b 3)").unwrap();
+        
+        let expr = ExprStructure::Compound {
+            arity: 4,
+            children: vec![
+                ExprStructure::Symbol(b"f".to_vec()),
+                ExprStructure::Symbol(b"1".to_vec()),
+                ExprStructure::Symbol(b"2".to_vec()),
+                ExprStructure::Symbol(b"3".to_vec()),
+            ],
+        };
+        let result = engine.unify(&expr, &three);
+        assert!(!result.success);
+        assert!(matches!(result.error, Some(UnificationError::ConstraintViolation(_))));
+        
+        let result = engine.unify(&expr, &two);
+        assert!(result.success);
+        assert!(result.error.is_none());
+    }
+    
+    #[test]
+    fn test_compiled_matcher() {
+        let mut engine = UnificationEngine::new();
+        let ops: [&[u8]; 4] = [b"add", b"sub", b"mul", b"div"];
+        let patterns: Vec<CompiledPattern> = ops.iter()
+            .map(|op| engine.compile_pattern(&format!("({} ? ?)", std::str::from_utf8(op).unwrap())).unwrap())
+            .collect();
+        
+        let expressions: Vec<ExprStructure> = (0..1000).map(|i| ExprStructure::Compound {
+            arity: 3,
+            children: vec![
+                ExprStructure::Symbol(ops[i % ops.len()].to_vec()),
+                ExprStructure::Symbol(i.to_string().into_bytes()),
+                ExprStructure::Symbol(b"1".to_vec()),
+            ],
+        }).collect();
+        
+        let naive = engine.multi_pattern_match(&patterns, &expressions);
+        
+        let matcher = engine.compile_matcher(&patterns);
+        let mut counts: HashMap<PatternId, usize> = HashMap::new();
+        for expr in &expressions {
+            for id in matcher.match_one(expr) {
+                *counts.entry(id).or_default() += 1;
+            }
+        }
+        
+        for (idx, pattern) in patterns.iter().enumerate() {
+            assert_eq!(counts[&pattern.id], naive.pattern_matches[&idx].len());
+            assert_eq!(counts[&pattern.id], 250);
+        }
+        // one candidate per expression instead of one unification per pattern
+        assert_eq!(matcher.unify_calls(), expressions.len());
+        assert!(matcher.unify_calls() < patterns.len() * expressions.len());
+    }
+    
+    #[test]
+    fn test_explain_symbol_mismatch() {
+        let mut engine = UnificationEngine::new();
+        let pattern = engine.compile_pattern("(add $x 1)").unwrap();
+        let expr = ExprStructure::Compound {
+            arity: 3,
+            children: vec![
+                ExprStructure::Symbol(b"add".to_vec()),
+                ExprStructure::Symbol(b"a".to_vec()),
+                ExprStructure::Symbol(b"2".to_vec()),
+            ],
+        };
+        
+        match engine.explain(&expr, &pattern) {
+            Err(UnificationFailureReason::SymbolMismatch { expected, found }) => {
+                assert_eq!(expected, b"1".to_vec());
+                assert_eq!(found, b"2".to_vec());
+            },
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+    
+    #[test]
+    fn test_explain_arity_mismatch() {
+        let mut engine = UnificationEngine::new();
+        let pattern = engine.compile_pattern("(add ? (f ?))").unwrap();
+        let expr = ExprStructure::Compound {
+            arity: 3,
+            children: vec![
+                ExprStructure::Symbol(b"add".to_vec()),
+                ExprStructure::Symbol(b"a".to_vec()),
+                ExprStructure::Compound {
+                    arity: 3,
+                    children: vec![
+                        ExprStructure::Symbol(b"f".to_vec()),
+                        ExprStructure::Symbol(b"1".to_vec()),
+                        ExprStructure::Symbol(b"2".to_vec()),
+                    ],
+                },
+            ],
+        };
+        
+        assert!(matches!(engine.explain(&expr, &pattern), Err(UnificationFailureReason::ArityMismatch { expected: 2, found: 3 })));
+        
+        let good = engine.compile_pattern("(add ? (f ? ?))").unwrap();
+        assert!(engine.explain(&expr, &good).is_ok());
+    }
+    
+    #[test]
+    fn test_cache_hit_counters() {
+        let mut engine = UnificationEngine::new();
+        
+        let pattern = engine.compile_pattern("(add ? ?)").unwrap();
+        let _again = engine.compile_pattern("(add ? ?)").unwrap();
+        
+        let add = ExprStructure::Compound {
+            arity: 3,
+            children: vec![
+                ExprStructure::Symbol(b"add".to_vec()),
+                ExprStructure::Symbol(b"1".to_vec()),
+                ExprStructure::Symbol(b"2".to_vec()),
+            ],
+        };
+        let sub = ExprStructure::Compound {
+            arity: 3,
+            children: vec![
+                ExprStructure::Symbol(b"sub".to_vec()),
+                ExprStructure::Symbol(b"1".to_vec()),
+                ExprStructure::Symbol(b"2".to_vec()),
+            ],
+        };
+        let matches = engine.find_matches(&pattern, &[add.clone(), sub, add]);
+        assert_eq!(matches.len(), 2);
+        
+        let stats = engine.stats();
+        assert_eq!(stats.compile_hits, 1);
+        assert_eq!(stats.cache_misses, 2);
+        assert_eq!(stats.cache_hits, 1);
+        
+        engine.reset_stats();
+        let stats = engine.stats();
+        assert_eq!((stats.cache_hits, stats.cache_misses, stats.compile_hits), (0, 0, 0));
+        assert_eq!(stats.cached_unifications, 2);
+    }
+    
+    #[test]
+    fn test_constraint_and_alternative_paths_are_cached() {
+        let mut engine = UnificationEngine::new();
+        let pattern = engine.compile_pattern("(add $x $y)").unwrap();
+        let x = pattern.variables.iter().find(|v| v.name == "x").unwrap().clone();
+        let add = |x: &[u8]| ExprStructure::Compound {
+            arity: 3,
+            children: vec![ExprStructure::Symbol(b"add".to_vec()), ExprStructure::Symbol(x.to_vec()), ExprStructure::Symbol(b"2".to_vec())],
+        };
+        let x_is_one = [Constraint::Predicate(x, |e| *e == ExprStructure::Symbol(b"1".to_vec()))];
+        
+        assert!(engine.unify_with_constraints(&add(b"1"), &pattern, &x_is_one).success);
+        assert!(!engine.unify_with_constraints(&add(b"3"), &pattern, &x_is_one).success);
+        // the match of the pattern itself is shared with plain `unify`
+        assert!(engine.unify(&add(b"3"), &pattern).success);
+        let stats = engine.stats();
+        assert_eq!((stats.cache_misses, stats.cache_hits), (2, 1));
+        
+        let either = CompiledPattern {
+            structure: PatternStructure::Alternative(vec![
+                PatternStructure::Symbol(b"a".to_vec()),
+                PatternStructure::Wildcard,
+            ]),
+            ..engine.compile_pattern("a").unwrap()
+        };
+        engine.reset_stats();
+        assert_eq!(engine.generate_unifications(&ExprStructure::Symbol(b"a".to_vec()), &either).len(), 2);
+        assert_eq!(engine.generate_unifications(&ExprStructure::Symbol(b"a".to_vec()), &either).len(), 2);
+        let stats = engine.stats();
+        assert_eq!((stats.cache_misses, stats.cache_hits), (2, 2));
+        // only the wildcard alternative matches `b`
+        assert_eq!(engine.generate_unifications(&ExprStructure::Symbol(b"b".to_vec()), &either).len(), 1);
+    }
+    
+    #[test]
+    fn test_cache_eviction() {
+        let mut engine = UnificationEngine::with_config(UnificationConfig {
+            max_cache_entries: 2,
+            ..UnificationConfig::default()
+        });
+        engine.compile_pattern("(a ?)").unwrap();
+        engine.compile_pattern("(b ?)").unwrap();
+        engine.compile_pattern("(a ?)").unwrap();
+        // (b ?) is the least recently used and makes room for (c ?)
+        engine.compile_pattern("(c ?)").unwrap();
+        let stats = engine.stats();
+        assert_eq!((stats.cached_patterns, stats.pattern_evictions, stats.compile_hits), (2, 1, 1));
+        
+        engine.reset_stats();
+        engine.compile_pattern("(a ?)").unwrap();
+        engine.compile_pattern("(b ?)").unwrap();
+        let stats = engine.stats();
+        assert_eq!(stats.compile_hits, 1); // (b ?) had to be compiled again
+        assert_eq!(stats.pattern_evictions, 1);
+        
+        let pattern = engine.compile_pattern("(b ?)").unwrap();
+        let exprs: Vec<ExprStructure> = (0..3).map(|i| ExprStructure::Compound {
+            arity: 2,
+            children: vec![ExprStructure::Symbol(b"b".to_vec()), ExprStructure::Symbol(i.to_string().into_bytes())],
+        }).collect();
+        for e in exprs.iter() { engine.unify(e, &pattern); }
+        let stats = engine.stats();
+        assert_eq!((stats.cached_unifications, stats.unification_evictions), (2, 1));
+        engine.unify(&exprs[0], &pattern);
+        assert_eq!(engine.stats().cache_misses, 4);
+    }
+
 }
\ No newline at end of file
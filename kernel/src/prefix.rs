@@ -1,6 +1,7 @@
 use std::convert::AsRef;
 use std::ptr::{null, slice_from_raw_parts};
-use crate::stubs::{byte_item, Expr, item_byte, Tag};
+use crate::stubs::{byte_item, Expr, item_byte, OwnedExpr, Tag};
+use crate::stubs::pathmap::utils::find_prefix_overlap;
 use crate::stubs::Tag::{Arity, SymbolSize};
 
 
@@ -59,3 +60,17 @@ impl <'a> Prefix<'a> {
   }
 }
 
+/// The longest constant byte prefix shared by all `exprs`, e.g. for anchoring a single write zipper
+/// that spans several templates; a ground expression contributes its whole span
+pub fn longest_common_prefix(exprs: &[Expr]) -> OwnedExpr {
+  let mut common: Option<&[u8]> = None;
+  for e in exprs {
+    let p = unsafe { &*e.prefix().unwrap_or_else(|x| x) };
+    common = Some(match common {
+      None => p,
+      Some(c) => &c[..find_prefix_overlap(c, p)],
+    });
+    if common.is_some_and(|c| c.is_empty()) { break }
+  }
+  OwnedExpr::from(common.unwrap_or(&[]).to_vec())
+}
@@ -0,0 +1,205 @@
+//! Streaming tokenizer for the S-expression syntax read by `Space::load_sexpr`
+//!
+//! A standalone lexer for inspecting or rewriting S-expressions before they are loaded; the loader has its own
+//! parser and does not go through it. Tokens borrow from the source; `;` comments and whitespace are skipped.
+//! The two agree on well-formed sources, except that a `;` or `"` inside a bare atom ends the atom here while the
+//! loader keeps it as part of the symbol.
+
+use std::fmt;
+
+/// A lexical element of an S-expression
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind<'a> {
+    Open,
+    Close,
+    /// A bare symbol, or a quoted one including its quotes with escapes left as written (the loader stores the text
+    /// between the quotes, with each `\x` read as `x`)
+    Symbol(&'a str),
+    /// A `$name` variable, numbered by the order in which distinct names first appear in the current
+    /// top-level expression (a bare `$` has the empty name, so all of them in one expression are one variable)
+    Variable(u8),
+}
+
+/// A token and the byte offset in the source where it starts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Token<'a> {
+    pub kind: TokenKind<'a>,
+    pub offset: usize,
+}
+
+/// Why the source is not a sequence of well-formed S-expressions; offsets are in bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseError {
+    /// A `"` at `offset` is never closed
+    UnterminatedString { offset: usize },
+    /// A `)` at `offset` has no matching `(`
+    UnexpectedClose { offset: usize },
+    /// The source ends with `depth` expressions still open
+    UnexpectedEnd { depth: usize },
+    /// The variable at `offset` is one more than a top-level expression can introduce
+    TooManyVariables { offset: usize },
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::UnterminatedString { offset } => write!(f, "unterminated string starting at byte {offset}"),
+            ParseError::UnexpectedClose { offset } => write!(f, "unmatched ')' at byte {offset}"),
+            ParseError::UnexpectedEnd { depth } => write!(f, "input ends with {depth} unclosed expression(s)"),
+            ParseError::TooManyVariables { offset } => write!(f, "too many variables in one expression at byte {offset}"),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Tokenizes `src` lazily; after the first error the iterator is exhausted
+pub fn tokenize_sexpr(src: &str) -> SexprTokens<'_> {
+    SexprTokens { src, pos: 0, depth: 0, variables: Vec::new(), done: false }
+}
+
+/// The iterator returned by [`tokenize_sexpr`]
+#[derive(Debug, Clone)]
+pub struct SexprTokens<'a> {
+    src: &'a str,
+    pos: usize,
+    depth: usize,
+    /// Names of the variables introduced so far in the current top-level expression
+    variables: Vec<&'a str>,
+    done: bool,
+}
+
+impl<'a> SexprTokens<'a> {
+    fn fail(&mut self, error: ParseError) -> Option<Result<Token<'a>, ParseError>> {
+        self.done = true;
+        Some(Err(error))
+    }
+
+    fn skip_blanks(&mut self) {
+        let bytes = self.src.as_bytes();
+        while let Some(&c) = bytes.get(self.pos) {
+            if c == b';' {
+                while bytes.get(self.pos).is_some_and(|&c| c != b'\n') { self.pos += 1 }
+            } else if c.is_ascii_whitespace() {
+                self.pos += 1;
+            } else {
+                break
+            }
+        }
+    }
+
+    /// The end of the atom starting at `self.pos`
+    fn atom_end(&self) -> usize {
+        let bytes = self.src.as_bytes();
+        let mut end = self.pos;
+        while bytes.get(end).is_some_and(|&c| !c.is_ascii_whitespace() && !matches!(c, b'(' | b')' | b'"' | b';')) { end += 1 }
+        end
+    }
+}
+
+impl<'a> Iterator for SexprTokens<'a> {
+    type Item = Result<Token<'a>, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done { return None }
+        self.skip_blanks();
+        let bytes = self.src.as_bytes();
+        let offset = self.pos;
+        let Some(&c) = bytes.get(offset) else {
+            self.done = true;
+            return if self.depth > 0 { Some(Err(ParseError::UnexpectedEnd { depth: self.depth })) } else { None }
+        };
+
+        let kind = match c {
+            b'(' => {
+                self.depth += 1;
+                self.pos += 1;
+                TokenKind::Open
+            }
+            b')' => {
+                if self.depth == 0 { return self.fail(ParseError::UnexpectedClose { offset }) }
+                self.depth -= 1;
+                self.pos += 1;
+                if self.depth == 0 { self.variables.clear() }
+                TokenKind::Close
+            }
+            b'"' => {
+                let mut end = offset + 1;
+                let mut escaped = false;
+                loop {
+                    match bytes.get(end) {
+                        None => return self.fail(ParseError::UnterminatedString { offset }),
+                        Some(_) if escaped => escaped = false,
+                        Some(b'\\') => escaped = true,
+                        Some(b'"') => break,
+                        Some(_) => {}
+                    }
+                    end += 1;
+                }
+                self.pos = end + 1;
+                TokenKind::Symbol(&self.src[offset..self.pos])
+            }
+            _ => {
+                let end = self.atom_end();
+                self.pos = end;
+                let atom = &self.src[offset..end];
+                match atom.strip_prefix('$') {
+                    None => TokenKind::Symbol(atom),
+                    Some(name) => {
+                        let index = match self.variables.iter().position(|v| *v == name) {
+                            Some(i) => i,
+                            None => {
+                                self.variables.push(name);
+                                self.variables.len() - 1
+                            }
+                        };
+                        let Ok(index) = u8::try_from(index) else { return self.fail(ParseError::TooManyVariables { offset }) };
+                        if self.depth == 0 { self.variables.clear() }
+                        TokenKind::Variable(index)
+                    }
+                }
+            }
+        };
+        Some(Ok(Token { kind, offset }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(src: &str) -> Vec<Result<TokenKind<'_>, ParseError>> {
+        tokenize_sexpr(src).map(|t| t.map(|t| t.kind)).collect()
+    }
+
+    #[test]
+    fn test_tokenize_simple() {
+        let tokens: Vec<_> = tokenize_sexpr("(foo $x)").collect::<Result<_, _>>().unwrap();
+        assert_eq!(tokens, vec![
+            Token { kind: TokenKind::Open, offset: 0 },
+            Token { kind: TokenKind::Symbol("foo"), offset: 1 },
+            Token { kind: TokenKind::Variable(0), offset: 5 },
+            Token { kind: TokenKind::Close, offset: 7 },
+        ]);
+    }
+
+    #[test]
+    fn test_variables_strings_and_comments() {
+        use TokenKind::*;
+        assert_eq!(kinds("(f $x $ $y $x $) ; trailing\n(g $y)"), vec![
+            Ok(Open), Ok(Symbol("f")), Ok(Variable(0)), Ok(Variable(1)), Ok(Variable(2)), Ok(Variable(0)), Ok(Variable(1)), Ok(Close),
+            Ok(Open), Ok(Symbol("g")), Ok(Variable(0)), Ok(Close),
+        ]);
+        assert_eq!(kinds(r#"(city "New \"York\"")"#), vec![
+            Ok(Open), Ok(Symbol("city")), Ok(Symbol(r#""New \"York\"""#)), Ok(Close),
+        ]);
+    }
+
+    #[test]
+    fn test_tokenize_errors() {
+        use TokenKind::*;
+        assert_eq!(kinds("a)"), vec![Ok(Symbol("a")), Err(ParseError::UnexpectedClose { offset: 1 })]);
+        assert_eq!(kinds("((a)"), vec![Ok(Open), Ok(Open), Ok(Symbol("a")), Ok(Close), Err(ParseError::UnexpectedEnd { depth: 1 })]);
+        assert_eq!(kinds("(\"open"), vec![Ok(Open), Err(ParseError::UnterminatedString { offset: 1 })]);
+    }
+}
@@ -6,16 +6,810 @@ use std::fs::File;
 use std::mem::MaybeUninit;
 use std::ptr::{addr_of, null, null_mut, slice_from_raw_parts};
 use std::time::Instant;
-use crate::stubs::{AlgebraicStatus, BytesTrieMap, Expr, Tag, item_byte, byte_item, SharedMappingHandle, WriteZipper, ZipperMoving};
+use crate::stubs::{AlgebraicStatus, BytesTrieMap, Expr, OwnedExpr, Tag, item_byte, byte_item, SharedMappingHandle, SYM_LEN, WritePermit, WriteZipper, ZipperHead, ZipperMoving};
 use crate::json_parser::Transcriber;
 use crate::prefix::Prefix;
+use crate::stubs::pathmap::utils::find_prefix_overlap;
 use log::*;
 
 pub struct Space {
     pub btm: BytesTrieMap<()>,
-    pub sm: SharedMappingHandle
+    pub sm: SharedMappingHandle,
+    pub(crate) subscriptions: std::sync::Mutex<Subscriptions>,
 }
 
+/// A symbol as it is stored in the trie: an interned id, or the symbol bytes themselves when they are stored inline
+pub type OwnedSymbol = Vec<u8>;
+
+/// Maps the symbol ids of a dumped symbol table onto the ids they were given when it was loaded
+pub type SymbolRemap = BTreeMap<OwnedSymbol, OwnedSymbol>;
+
+/// The quoted form, with `"` and `\` escaped, of a symbol `load_sexpr` would otherwise read differently: one containing
+/// whitespace, parentheses or quotes, or starting like a variable or a comment. `""`, the spelling the empty string
+/// is stored under, is written as it is. Symbols are arbitrary bytes and are quoted byte for byte.
+fn quote_symbol(s: &[u8]) -> Option<Vec<u8>> {
+    if s == b"\"\"" { return None }
+    if !(s.starts_with(b"$") || s.starts_with(b";") || s.iter().any(|&b| b.is_ascii_whitespace() || b == b'(' || b == b')' || b == b'"')) { return None }
+    let mut q = Vec::with_capacity(s.len() + 2);
+    q.push(b'"');
+    for &b in s {
+        if b == b'"' || b == b'\\' { q.push(b'\\') }
+        q.push(b);
+    }
+    q.push(b'"');
+    Some(q)
+}
+
+/// The text a quoted atom stands for: the bytes between its quotes with each `\x` read as `x`. `None` for unquoted
+/// atoms, unterminated ones and `""` (the stored spelling of the empty string), which are taken as they are.
+fn unquote(atom: &[u8]) -> Option<Vec<u8>> {
+    if atom.len() < 3 || atom[0] != b'"' || atom[atom.len() - 1] != b'"' { return None }
+    let mut text = Vec::with_capacity(atom.len() - 2);
+    let mut escaped = false;
+    for &b in &atom[1..atom.len() - 1] {
+        if !escaped && b == b'\\' { escaped = true; continue }
+        escaped = false;
+        text.push(b);
+    }
+    // a trailing `\"` escapes the closing quote, so the atom was cut short by the end of the input
+    if escaped { None } else { Some(text) }
+}
+
+/// A pattern's matching program, see [`Space::compile_query`]
+#[derive(Clone, Debug)]
+pub struct CompiledQuery {
+    stack: Vec<u8>,
+    /// Extra stack the program's `{depth=N}` tokens may need
+    headroom: usize,
+}
+
+/// What a transform wrote: `written` counts outputs that were new to the space, `collisions` the outputs
+/// whose path already existed (and were collapsed into the existing fact)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TransformResult {
+    pub written: usize,
+    pub collisions: usize,
+}
+
+/// How [`Space::query_ordered_by`] compares the values captured by the sort variable
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CaptureOrder {
+    /// By the encoded bytes of the captured subexpression (trie order)
+    #[default]
+    Bytes,
+    /// By the value of captured symbols that read as numbers; anything else sorts after them, by bytes
+    Numeric,
+}
+
+/// The facts to add to and remove from one space to make it equal to another, see [`Space::diff`].
+/// Both lists hold encoded facts in trie order.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SpaceDiff {
+    pub added: Vec<Vec<u8>>,
+    pub removed: Vec<Vec<u8>>,
+}
+
+/// Facts grouped by the subexpression one of their variables captured, see [`Space::build_index`].
+/// Keys are stored in a trie, so a lookup costs the length of the key however many keys there are.
+pub struct SpaceIndex {
+    entries: BytesTrieMap<Vec<Vec<u8>>>,
+    keys: usize,
+}
+
+impl SpaceIndex {
+    /// The encoded facts whose captured key is `key`, in trie order
+    pub fn get(&self, key: Expr) -> &[Vec<u8>] {
+        self.entries.get(unsafe { key.span().as_ref().unwrap() }).map(|facts| &facts[..]).unwrap_or(&[])
+    }
+
+    /// The number of distinct keys
+    pub fn len(&self) -> usize {
+        self.keys
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys == 0
+    }
+}
+
+/// How [`Space::dump_all_sexpr_with_options`] writes the space
+#[derive(Clone, Debug, Default)]
+pub struct DumpOptions {
+    /// Write the facts in lexical order of their text rather than trie order, which under interning follows the
+    /// order symbols were first seen; makes dumps of equal spaces identical however they were loaded
+    pub sort_by_symbol: bool,
+}
+
+/// How [`Space::snapshot`] writes its file
+#[derive(Clone, Debug)]
+pub struct SnapshotOptions {
+    /// `fsync` the file (and, when `atomic`, its directory) before returning
+    pub durable: bool,
+    /// Write to a temporary file next to the target and rename it over the target once complete,
+    /// so a crash or error mid-write leaves any previous snapshot intact
+    pub atomic: bool,
+}
+
+impl Default for SnapshotOptions {
+    fn default() -> Self { Self { durable: true, atomic: true } }
+}
+
+/// First bytes of a snapshot written by [`Space::snapshot_with_metadata`]
+pub const SNAPSHOT_MAGIC: [u8; 8] = *b"MORKSNAP";
+/// Layout version written into new snapshot headers
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+/// What precedes the trie in a snapshot written by [`Space::snapshot_with_metadata`]: after [`SNAPSHOT_MAGIC`],
+/// a big-endian `u32` version, the `u64` offset of the symbol table (which follows the paths), and a `u64` count of
+/// metadata entries, each a `u64`-length-prefixed key and value
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct SnapshotHeader {
+    pub version: u32,
+    /// Byte offset from the start of the file at which the [`Space::dump_symbol_table`] output begins
+    pub symbol_table_offset: u64,
+    pub metadata: BTreeMap<String, String>,
+}
+
+impl SnapshotHeader {
+    fn write_to<W: Write>(&self, w: &mut W) -> std::io::Result<()> {
+        w.write_all(&SNAPSHOT_MAGIC)?;
+        w.write_all(&self.version.to_be_bytes())?;
+        w.write_all(&self.symbol_table_offset.to_be_bytes())?;
+        w.write_all(&(self.metadata.len() as u64).to_be_bytes())?;
+        for (k, v) in self.metadata.iter() {
+            w.write_all(&(k.len() as u64).to_be_bytes())?;
+            w.write_all(k.as_bytes())?;
+            w.write_all(&(v.len() as u64).to_be_bytes())?;
+            w.write_all(v.as_bytes())?;
+        }
+        Ok(())
+    }
+
+    fn read_from<R: Read>(r: &mut R) -> std::io::Result<Self> {
+        let invalid = |msg: &str| std::io::Error::new(std::io::ErrorKind::InvalidData, msg.to_string());
+        let mut magic = [0u8; 8];
+        r.read_exact(&mut magic)?;
+        if magic != SNAPSHOT_MAGIC { return Err(invalid("not a MORK snapshot")) }
+        let mut half = [0u8; 4];
+        r.read_exact(&mut half)?;
+        let version = u32::from_be_bytes(half);
+        if version > SNAPSHOT_VERSION { return Err(invalid("snapshot written by a newer version")) }
+        let mut word = [0u8; 8];
+        r.read_exact(&mut word)?;
+        let symbol_table_offset = u64::from_be_bytes(word);
+        r.read_exact(&mut word)?;
+        let count = u64::from_be_bytes(word);
+        let mut string = |r: &mut R| -> std::io::Result<String> {
+            r.read_exact(&mut word)?;
+            let mut bytes = vec![0u8; u64::from_be_bytes(word) as usize];
+            r.read_exact(&mut bytes)?;
+            String::from_utf8(bytes).map_err(|_| invalid("metadata is not UTF-8"))
+        };
+        let mut metadata = BTreeMap::new();
+        for _ in 0..count {
+            let k = string(r)?;
+            metadata.insert(k, string(r)?);
+        }
+        Ok(Self { version, symbol_table_offset, metadata })
+    }
+}
+
+/// Reads only the header of a snapshot written by [`Space::snapshot_with_metadata`], leaving the trie on disk
+pub fn read_snapshot_header<P: AsRef<std::path::Path>>(path: P) -> std::io::Result<SnapshotHeader> {
+    SnapshotHeader::read_from(&mut std::io::BufReader::new(File::open(path)?))
+}
+
+/// Creates `path` and fills it with `write`, as `options` asks; a failed atomic write removes its temporary file
+pub(crate) fn write_snapshot_file<T, F : FnOnce(&mut File) -> std::io::Result<T>>(path: &std::path::Path, options: &SnapshotOptions, write: F) -> std::io::Result<T> {
+    let target = if options.atomic {
+        let mut name = path.file_name().ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, "snapshot path has no file name"))?.to_owned();
+        name.push(".tmp");
+        path.with_file_name(name)
+    } else {
+        path.to_path_buf()
+    };
+    let written = File::create(&target).and_then(|mut file| {
+        let r = write(&mut file)?;
+        if options.durable { file.sync_all()?; }
+        Ok(r)
+    });
+    if !options.atomic { return written }
+    let r = match written {
+        Ok(r) => r,
+        Err(e) => { let _ = std::fs::remove_file(&target); return Err(e) }
+    };
+    std::fs::rename(&target, path)?;
+    #[cfg(unix)]
+    if options.durable {
+        // the rename is only durable once the directory entry is
+        let dir = path.parent().filter(|d| !d.as_os_str().is_empty()).unwrap_or(std::path::Path::new("."));
+        File::open(dir)?.sync_all()?;
+    }
+    Ok(r)
+}
+
+/// The size of a space's symbol table, see [`Space::symbol_table_stats`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SymbolTableStats {
+    /// Distinct interned symbols
+    pub count: usize,
+    /// Bytes held by the interned symbols and their ids, not counting the table's own bookkeeping
+    pub bytes: usize,
+}
+
+/// What [`Space::compact`] did
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CompactStats {
+    /// Value-less branches that were dropped
+    pub reclaimed: usize,
+    /// Facts in the compacted space
+    pub facts: usize,
+}
+
+/// Identifies a callback registered with [`Space::subscribe`]
+pub type SubscriptionId = u64;
+
+/// Insertion callbacks together with the (owned) encodings of the patterns they watch
+#[derive(Default)]
+pub(crate) struct Subscriptions {
+    next_id: SubscriptionId,
+    entries: Vec<(SubscriptionId, Vec<u8>, Box<dyn FnMut(Expr) + Send>)>,
+    /// The derived views among the subscriptions, whose queued outputs the loaders write
+    views: Vec<(SubscriptionId, std::sync::Arc<std::sync::Mutex<DerivedState>>)>,
+}
+
+/// How many rounds of derived outputs one loader writes, see [`Space::derived_view`]
+pub const MAX_DERIVED_PASSES: usize = 64;
+
+/// What a derived view shares with its subscription callback
+#[derive(Default)]
+pub(crate) struct DerivedState {
+    /// Instantiated templates not yet written to the space
+    pending: Vec<Vec<u8>>,
+    /// Facts the view added to the space (and removes with it); a loader writing one of them takes it over
+    written: std::collections::HashSet<Vec<u8>>,
+}
+
+/// The facts of a space as they were when [`Space::read_snapshot`] was called, with the space's read-only API.
+/// Its subscriptions and derived views stay with the live space.
+pub struct ReadSnapshot {
+    space: Space,
+}
+
+impl std::ops::Deref for ReadSnapshot {
+    type Target = Space;
+
+    fn deref(&self) -> &Space {
+        &self.space
+    }
+}
+
+/// A transform kept up to date with its source facts, see [`Space::derived_view`]
+pub struct DerivedView {
+    id: SubscriptionId,
+    state: std::sync::Arc<std::sync::Mutex<DerivedState>>,
+}
+
+impl DerivedView {
+    /// The number of facts the view has added to the space
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().written.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Length of the expression starting at `bytes[start]`
+pub(crate) fn subexpr_len(bytes: &[u8], start: usize) -> usize {
+    let mut i = start;
+    let mut pending = 1usize;
+    while pending > 0 {
+        pending -= 1;
+        match byte_item(bytes[i]) {
+            Tag::Arity(a) => { pending += a as usize; i += 1 }
+            Tag::SymbolSize(n) => { i += 1 + n as usize }
+            Tag::NewVar | Tag::VarRef(_) => { i += 1 }
+        }
+    }
+    i - start
+}
+
+/// Whether the ground `fact` is an instance of `pattern`, where `$` binds a whole subexpression and `_k` must repeat it
+fn pattern_matches_fact(pattern: &[u8], fact: &[u8]) -> bool {
+    let mut bound: Vec<&[u8]> = vec![];
+    let (mut pi, mut fi) = (0, 0);
+    while pi < pattern.len() {
+        if fi >= fact.len() { return false }
+        match byte_item(pattern[pi]) {
+            Tag::NewVar => {
+                let l = subexpr_len(fact, fi);
+                bound.push(&fact[fi..fi + l]);
+                fi += l;
+                pi += 1;
+            }
+            Tag::VarRef(k) => {
+                let l = subexpr_len(fact, fi);
+                if bound.get(k as usize) != Some(&&fact[fi..fi + l]) { return false }
+                fi += l;
+                pi += 1;
+            }
+            Tag::Arity(_) => {
+                if pattern[pi] != fact[fi] { return false }
+                pi += 1;
+                fi += 1;
+            }
+            Tag::SymbolSize(n) => {
+                let l = 1 + n as usize;
+                if fact.get(fi..fi + l) != Some(&pattern[pi..pi + l]) { return false }
+                pi += l;
+                fi += l;
+            }
+        }
+    }
+    fi == fact.len()
+}
+
+/// The length of the longest top-level expression in S-expression source, counting any comments inside it;
+/// one parse step never reads more of the source than that
+fn longest_sexpr(src: &[u8]) -> usize {
+    let (mut depth, mut in_string, mut escaped, mut in_comment) = (0i32, false, false, false);
+    let (mut start, mut longest) = (None, 0);
+    for (i, &c) in src.iter().enumerate() {
+        if in_string {
+            if escaped { escaped = false }
+            else if c == b'\\' { escaped = true }
+            else if c == b'"' { in_string = false }
+            continue
+        }
+        if in_comment {
+            if c == b'\n' { in_comment = false }
+            continue
+        }
+        if c == b';' { in_comment = true; continue }
+        if depth == 0 && c.is_ascii_whitespace() {
+            if let Some(s) = start.take() { longest = longest.max(i - s) }
+            continue
+        }
+        if start.is_none() { start = Some(i) }
+        match c {
+            b'"' => { in_string = true }
+            b'(' => { depth += 1 }
+            b')' => { depth = (depth - 1).max(0) }
+            _ => {}
+        }
+    }
+    if let Some(s) = start { longest = longest.max(src.len() - s) }
+    longest
+}
+
+/// A parse stack and a template buffer large enough for any expression of `src` and its instantiation of `template`.
+/// An atom takes at least one byte of source and encodes to at most `1 + SYM_LEN` bytes (an interned id) or one byte
+/// more than its text (inline), and a parenthesis to at most one, so an expression encodes to at most `1 + SYM_LEN`
+/// times its source; the instantiated template is the template with each `_n` replaced by at most the whole expression.
+pub(crate) fn load_buffers(src: &[u8], template: Expr) -> (Vec<u8>, Vec<u8>) {
+    let stack = (1 + SYM_LEN) * longest_sexpr(src) + 1;
+    let template = unsafe { template.span().as_ref().unwrap() };
+    let mut references = 0;
+    let mut i = 0;
+    while i < template.len() {
+        match byte_item(template[i]) {
+            Tag::SymbolSize(n) => { i += n as usize }
+            Tag::VarRef(_) => { references += 1 }
+            _ => {}
+        }
+        i += 1;
+    }
+    (vec![0u8; stack], vec![0u8; template.len() + references * stack])
+}
+
+/// An upper bound on the size of `template` instantiated with expressions of at most `substitution` bytes
+fn instance_len(template: Expr, substitution: usize) -> usize {
+    let template = unsafe { template.span().as_ref().unwrap() };
+    let mut variables = 0;
+    let mut i = 0;
+    while i < template.len() {
+        match byte_item(template[i]) {
+            Tag::SymbolSize(n) => { i += n as usize }
+            Tag::NewVar | Tag::VarRef(_) => { variables += 1 }
+            _ => {}
+        }
+        i += 1;
+    }
+    template.len() + variables * substitution
+}
+
+/// The offset of the first byte at or after `at` that is neither whitespace nor part of a `;` comment
+fn skip_blank(src: &[u8], mut at: usize) -> usize {
+    while at < src.len() {
+        if src[at].is_ascii_whitespace() { at += 1 }
+        else if src[at] == b';' { at = src[at..].iter().position(|&c| c == b'\n').map_or(src.len(), |k| at + k) }
+        else { break }
+    }
+    at
+}
+
+/// Splits S-expression source into at most `n` pieces of roughly equal size, cutting only after a newline that is
+/// outside any expression, string or comment; each piece comes with its byte offset in `src`
+fn sexpr_chunks(src: &[u8], n: usize) -> Vec<(usize, &[u8])> {
+    let step = src.len() / n + 1;
+    let mut cuts = vec![0];
+    let (mut depth, mut in_string, mut escaped, mut in_comment) = (0i32, false, false, false);
+    for (i, &c) in src.iter().enumerate() {
+        if in_string {
+            if escaped { escaped = false }
+            else if c == b'\\' { escaped = true }
+            else if c == b'"' { in_string = false }
+            continue
+        }
+        match c {
+            b'\n' => {
+                in_comment = false;
+                if depth == 0 && cuts.len() < n && i + 1 >= cuts[cuts.len() - 1] + step { cuts.push(i + 1) }
+            }
+            _ if in_comment => {}
+            b';' => { in_comment = true }
+            b'"' => { in_string = true }
+            b'(' => { depth += 1 }
+            b')' => { depth -= 1 }
+            _ => {}
+        }
+    }
+    cuts.push(src.len());
+    cuts.windows(2).filter(|w| w[0] < w[1]).map(|w| (w[0], &src[w[0]..w[1]])).collect()
+}
+
+/// The bytes of a symbol as stored in the trie: symbols of any length but an interned id's can only have been stored inline
+#[cfg(feature="interning")]
+pub(crate) fn symbol_bytes<'s>(sm: &'s SharedMappingHandle, s: &'s [u8]) -> &'s [u8] {
+    if s.len() != SYM_LEN { return s }
+    let symbol = i64::from_be_bytes(s.try_into().unwrap()).to_be_bytes();
+    sm.get_bytes(symbol).expect(format!("failed to look up {:?}", symbol).as_str())
+}
+
+/// Writes `e` as an S-expression, quoting symbols that `load_sexpr` would otherwise split
+#[cfg_attr(not(feature="interning"), allow(unused_variables))]
+pub(crate) fn serialize_fact<W : Write>(sm: &SharedMappingHandle, e: Expr, w: &mut W) {
+    let mut at = 0;
+    // write errors resurface when the caller writes the line break
+    let _ = write_subexpr(sm, unsafe { e.span().as_ref().unwrap() }, &mut at, w);
+}
+
+/// Writes the subexpression of `bytes` starting at `*at` and moves `at` past it
+#[cfg_attr(not(feature="interning"), allow(unused_variables))]
+fn write_subexpr<W : Write>(sm: &SharedMappingHandle, bytes: &[u8], at: &mut usize, w: &mut W) -> std::io::Result<()> {
+    let tag = byte_item(bytes[*at]);
+    *at += 1;
+    match tag {
+        Tag::NewVar => w.write_all(b"$"),
+        Tag::VarRef(r) => write!(w, "_{}", r as usize + 1),
+        Tag::SymbolSize(n) => {
+            let stored = &bytes[*at..*at + n as usize];
+            *at += n as usize;
+            #[cfg(feature="interning")]
+            let stored = symbol_bytes(sm, stored);
+            match quote_symbol(stored) {
+                None => w.write_all(stored),
+                Some(q) => w.write_all(&q[..]),
+            }
+        }
+        Tag::Arity(a) => {
+            w.write_all(b"(")?;
+            for k in 0..a {
+                if k > 0 { w.write_all(b" ")? }
+                write_subexpr(sm, bytes, at, w)?;
+            }
+            w.write_all(b")")
+        }
+    }
+}
+
+/// Writes `expr` as a frame that does not depend on `sm`: a big-endian `u32` payload length, then the expression's
+/// items with every symbol spelled out as a `u32` length and its text. Read it back with [`deserialize_binary`].
+#[cfg_attr(not(feature="interning"), allow(unused_variables))]
+pub fn serialize_binary<W : Write>(expr: Expr, sm: &SharedMappingHandle, w: &mut W) -> std::io::Result<()> {
+    let bytes = unsafe { expr.span().as_ref().unwrap() };
+    let mut payload = Vec::with_capacity(2*bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match byte_item(bytes[i]) {
+            Tag::SymbolSize(n) => {
+                let stored = &bytes[i + 1..i + 1 + n as usize];
+                #[cfg(feature="interning")]
+                let stored = symbol_bytes(sm, stored);
+                payload.push(bytes[i]);
+                payload.extend_from_slice(&(stored.len() as u32).to_be_bytes());
+                payload.extend_from_slice(stored);
+                i += 1 + n as usize;
+            }
+            _ => { payload.push(bytes[i]); i += 1 }
+        }
+    }
+    w.write_all(&(payload.len() as u32).to_be_bytes())?;
+    w.write_all(&payload[..])
+}
+
+/// Reads a frame written by [`serialize_binary`], interning its symbols into `sm`
+pub fn deserialize_binary<R : Read>(r: &mut R, sm: &SharedMappingHandle) -> std::io::Result<OwnedExpr> {
+    let invalid = || std::io::Error::new(std::io::ErrorKind::InvalidData, "malformed expression frame");
+    let mut word = [0u8; 4];
+    r.read_exact(&mut word)?;
+    let mut payload = vec![0u8; u32::from_be_bytes(word) as usize];
+    r.read_exact(&mut payload)?;
+
+    let mut pdp = ParDataParser::new(sm);
+    let mut out = Vec::with_capacity(payload.len());
+    let (mut i, mut pending) = (0, 1usize);
+    while pending > 0 {
+        let &item = payload.get(i).ok_or_else(invalid)?;
+        pending -= 1;
+        i += 1;
+        match byte_item(item) {
+            Tag::Arity(a) => { pending += a as usize; out.push(item) }
+            Tag::SymbolSize(_) => {
+                let len = u32::from_be_bytes(payload.get(i..i + 4).ok_or_else(invalid)?.try_into().unwrap()) as usize;
+                let text = payload.get(i + 4..i + 4 + len).ok_or_else(invalid)?;
+                let token = pdp.tokenizer(text);
+                out.push(item_byte(Tag::SymbolSize(token.len() as u8)));
+                out.extend_from_slice(token);
+                i += 4 + len;
+            }
+            Tag::NewVar | Tag::VarRef(_) => { out.push(item) }
+        }
+    }
+    if i != payload.len() { return Err(invalid()) }
+    Ok(OwnedExpr::from(out))
+}
+
+/// Errors raised when the space cannot hand out access to a region of the trie
+#[derive(Debug, Clone, PartialEq)]
+pub enum SpaceError {
+    /// The requested path overlaps a zipper that is already open
+    PathConflict,
+    /// The caller is not permitted to access the path
+    AccessDenied { path: Vec<u8> },
+    /// No zipper could be created for the request
+    ZipperUnavailable,
+    /// A diff adds a fact that is already present or removes one that is missing
+    DiffConflict { path: Vec<u8> },
+    /// A query matched more expressions than its read quota allows
+    ReadQuotaExceeded { quota: usize },
+    /// A pattern nests deeper than the configured `max_depth`
+    MaxDepthExceeded { max_depth: usize },
+    /// The patterns introduce more variables than the configured `max_references`
+    MaxReferencesExceeded { max_references: usize },
+    /// The traversal was cancelled through its `CancellationToken`
+    Cancelled,
+    /// No patterns were given, or a pattern or template is a null expression
+    InvalidPattern,
+    /// A loaded row has an empty field, which has no symbol encoding (fields and rows count from 0)
+    EmptySymbol { row: usize, column: usize },
+    /// An exclusive load cannot write the outputs of the space's derived views, which lie outside its path
+    DerivedViewsActive,
+    /// A pattern refers to a variable before introducing it
+    Pattern(PatternError),
+    /// A template refers to a variable the patterns do not bind
+    Template(TransformError),
+}
+
+impl std::fmt::Display for SpaceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::PathConflict => write!(f, "Path conflicts with an open zipper"),
+            Self::AccessDenied { path } => write!(f, "Access denied to path {}", serialize(path)),
+            Self::ZipperUnavailable => write!(f, "Zipper unavailable"),
+            Self::DiffConflict { path } => write!(f, "Diff does not apply at {}", serialize(path)),
+            Self::ReadQuotaExceeded { quota } => write!(f, "Read quota of {} matches exceeded", quota),
+            Self::MaxDepthExceeded { max_depth } => write!(f, "Pattern nests deeper than {}", max_depth),
+            Self::MaxReferencesExceeded { max_references } => write!(f, "Patterns introduce more than {} variables", max_references),
+            Self::Cancelled => write!(f, "Cancelled"),
+            Self::InvalidPattern => write!(f, "Missing or null pattern"),
+            Self::EmptySymbol { row, column } => write!(f, "Empty symbol in row {} column {}", row, column),
+            Self::DerivedViewsActive => write!(f, "Derived views need writes outside the exclusive path"),
+            Self::Pattern(e) => write!(f, "Invalid pattern: {}", e),
+            Self::Template(e) => write!(f, "Invalid template: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for SpaceError {}
+
+impl From<SpaceError> for String {
+    fn from(e: SpaceError) -> Self { e.to_string() }
+}
+
+/// Errors found when checking a pattern before it is turned into a matching program
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PatternError {
+    /// `_n` (0-based `n`) refers to a variable the pattern has not introduced yet
+    ForwardReference(u8),
+}
+
+impl std::fmt::Display for PatternError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::ForwardReference(n) => write!(f, "Reference _{} precedes the variable it refers to", *n as usize + 1),
+        }
+    }
+}
+
+impl std::error::Error for PatternError {}
+
+/// Writes `expr` to `out` with its variables renumbered in order of first appearance: the first occurrence of
+/// each variable becomes a `$` and later ones refer back to it, so alpha-equivalent expressions (including ones
+/// whose references were bound outside of them, like templates) get identical encodings
+pub fn canonicalize_variables(expr: Expr, out: &mut [u8]) -> Expr {
+    let mut ez = ExprZipper::new(expr);
+    let mut introduced = 0u8;
+    let mut canonical = BTreeMap::<u8, u8>::new();
+    let mut loc = 0;
+    loop {
+        let var = match ez.item() {
+            Ok(Tag::NewVar) => { introduced += 1; Some(introduced - 1) }
+            Ok(Tag::VarRef(r)) => { Some(r) }
+            Ok(Tag::Arity(a)) => { out[loc] = item_byte(Tag::Arity(a)); loc += 1; None }
+            Ok(Tag::SymbolSize(_)) => { unreachable!() }
+            Err(s) => {
+                out[loc] = item_byte(Tag::SymbolSize(s.len() as u8));
+                out[loc + 1..loc + 1 + s.len()].copy_from_slice(s);
+                loc += 1 + s.len();
+                None
+            }
+        };
+        if let Some(v) = var {
+            let next = canonical.len() as u8;
+            out[loc] = match canonical.get(&v) {
+                Some(&c) => item_byte(Tag::VarRef(c)),
+                None => { canonical.insert(v, next); item_byte(Tag::NewVar) }
+            };
+            loc += 1;
+        }
+        if !ez.next() { return Expr{ ptr: out.as_mut_ptr() } }
+    }
+}
+
+/// Errors found when checking a transform's templates against its patterns
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TransformError {
+    /// A template refers to `_n` (0-based `n`) but neither the patterns nor the template introduce that many variables
+    UnboundTemplateVar(u8),
+}
+
+impl std::fmt::Display for TransformError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnboundTemplateVar(n) => write!(f, "Template refers to _{} which the patterns do not bind", *n as usize + 1),
+        }
+    }
+}
+
+impl std::error::Error for TransformError {}
+
+/// Checks that the templates only refer to variables bound by the patterns (or introduced earlier in the
+/// template itself), since `substitute` indexes straight into the references of a match
+fn check_templates(patterns: &[Expr], templates: &[Expr]) -> Result<(), TransformError> {
+    let bound: usize = patterns.iter().map(|p| p.newvars()).sum();
+    for template in templates {
+        let mut ez = ExprZipper::new(*template);
+        let mut introduced = bound;
+        loop {
+            match ez.item() {
+                Ok(Tag::NewVar) => { introduced += 1; }
+                Ok(Tag::VarRef(r)) => { if r as usize >= introduced { return Err(TransformError::UnboundTemplateVar(r)) } }
+                _ => {}
+            }
+            if !ez.next() { break }
+        }
+    }
+    Ok(())
+}
+
+/// Checks that every `VarRef` in `e` refers to a variable introduced before it,
+/// since `REFER_RANGE` indexes straight into the captured references
+fn check_references(e: Expr) -> Result<(), PatternError> {
+    let mut ez = ExprZipper::new(e);
+    let mut introduced = 0usize;
+    loop {
+        match ez.item() {
+            Ok(Tag::NewVar) => { introduced += 1; }
+            Ok(Tag::VarRef(r)) => { if r as usize >= introduced { return Err(PatternError::ForwardReference(r)) } }
+            _ => {}
+        }
+        if !ez.next() { return Ok(()) }
+    }
+}
+
+/// Configuration for pattern matching over the space
+#[derive(Debug, Clone, Default)]
+pub struct MatchConfig {
+    /// Skip matches whose set of matched facts was already visited, so overlapping patterns hand the
+    /// same facts to the effect at most once
+    pub dedup_matches: bool,
+    /// Checked before every candidate; once cancelled the traversal is abandoned
+    pub cancel: Option<CancellationToken>,
+    /// The most matches handed to the effect; the traversal is abandoned when one more is found
+    pub read_quota: Option<usize>,
+    /// The deepest nesting of expressions a pattern may have; deeper patterns are refused before matching
+    pub max_depth: Option<usize>,
+    /// The most variables the patterns may introduce (each one holds a range while matching)
+    pub max_references: Option<usize>,
+    /// The most matches a collecting query keeps; finding one more stops it and marks the results truncated
+    pub max_results: Option<usize>,
+}
+
+/// The matches gathered by [`Space::query_collect`]; `truncated` is set when `max_results` cut the traversal short
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct QueryResults {
+    pub results: Vec<OwnedExpr>,
+    pub truncated: bool,
+}
+
+impl MatchConfig {
+    /// The error reported for a traversal this configuration abandoned
+    fn error(&self, interruption: Interruption) -> SpaceError {
+        match interruption {
+            Interruption::Cancelled => SpaceError::Cancelled,
+            Interruption::ReadQuotaExceeded => SpaceError::ReadQuotaExceeded { quota: self.read_quota.unwrap_or(0) },
+            Interruption::TooDeep => SpaceError::MaxDepthExceeded { max_depth: self.max_depth.unwrap_or(0) },
+            Interruption::TooManyReferences => SpaceError::MaxReferencesExceeded { max_references: self.max_references.unwrap_or(0) },
+            Interruption::InvalidPattern => SpaceError::InvalidPattern,
+            Interruption::Pattern(e) => SpaceError::Pattern(e),
+            Interruption::Template(e) => SpaceError::Template(e),
+        }
+    }
+}
+
+/// How deeply the expressions in `e` nest (0 for a symbol or variable)
+fn expr_depth(e: Expr) -> usize {
+    let mut ez = ExprZipper::new(e);
+    let mut remaining: Vec<u8> = vec![];
+    let mut depth = 0;
+    loop {
+        if let Some(r) = remaining.last_mut() { *r -= 1 }
+        if let Ok(Tag::Arity(a)) = ez.item() {
+            remaining.push(a);
+            depth = depth.max(remaining.len());
+        }
+        while remaining.last() == Some(&0) { remaining.pop(); }
+        if !ez.next() { return depth }
+    }
+}
+
+/// Why `query_multi_checked` abandoned a traversal
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Interruption {
+    Cancelled,
+    ReadQuotaExceeded,
+    TooDeep,
+    TooManyReferences,
+    InvalidPattern,
+    Pattern(PatternError),
+    Template(TransformError),
+}
+
+/// A flag another thread can raise to stop a running query, transform or calculus
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(std::sync::Arc<std::sync::atomic::AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, std::sync::atomic::Ordering::Release);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(std::sync::atomic::Ordering::Acquire)
+    }
+}
+
+/// Returned when a [`CancellationToken`] stopped an operation before it completed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cancelled;
+
 const SIZES: [u64; 4] = {
     let mut ret = [0u64; 4];
     let mut size = 1;
@@ -65,6 +859,45 @@ const BEGIN_RANGE: u8 = 12;
 const FINALIZE_RANGE: u8 = 13;
 const REFER_RANGE: u8 = 14;
 const RESERVED: u8 = 15;
+const ITER_DEPTH_AT_MOST: u8 = 16;
+const ITER_NESTED_AT_MOST: u8 = 17;
+const ITER_DEPTH_EXACTLY: u8 = 18;
+const ITER_NESTED_EXACTLY: u8 = 19;
+
+/// The stack a `{depth=N}` token can grow by per level: an operand and an opcode for each of up to 63 children
+const DEPTH_LEVEL_STACK: usize = 2 * 63;
+
+/// The `N` of a `{depth=N}` pattern token (`N` is at most 255), see [`Space::query_multi`]
+fn depth_token(symbol: &[u8]) -> Option<u8> {
+    let n = symbol.strip_prefix(b"{depth=")?.strip_suffix(b"}")?;
+    if n.is_empty() || n.len() > 3 || !n.iter().all(u8::is_ascii_digit) { return None }
+    std::str::from_utf8(n).ok()?.parse().ok()
+}
+
+/// `pattern` with its `{depth=N}` tokens replaced by the variables they capture, and the largest `N`
+fn without_depth_tokens(pattern: Expr) -> (Vec<u8>, usize) {
+    let bytes = unsafe { pattern.span().as_ref().unwrap() };
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut deepest = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        if let Tag::SymbolSize(n) = byte_item(bytes[i]) {
+            let symbol = &bytes[i + 1..i + 1 + n as usize];
+            match depth_token(symbol) {
+                Some(depth) => {
+                    out.push(item_byte(Tag::NewVar));
+                    deepest = deepest.max(depth as usize);
+                }
+                None => out.extend_from_slice(&bytes[i..i + 1 + n as usize]),
+            }
+            i += 1 + n as usize;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    (out, deepest)
+}
 
 fn label(l: u8) -> String {
     match l {
@@ -83,6 +916,10 @@ fn label(l: u8) -> String {
         BEGIN_RANGE => { "BEGIN_RANGE" }
         FINALIZE_RANGE => { "FINALIZE_RANGE" }
         REFER_RANGE => { "REFER_RANGE" }
+        ITER_DEPTH_AT_MOST => { "ITER_DEPTH_AT_MOST" }
+        ITER_NESTED_AT_MOST => { "ITER_NESTED_AT_MOST" }
+        ITER_DEPTH_EXACTLY => { "ITER_DEPTH_EXACTLY" }
+        ITER_NESTED_EXACTLY => { "ITER_NESTED_EXACTLY" }
         _ => { return l.to_string() }
     }.to_string()
 }
@@ -232,6 +1069,72 @@ fn referential_transition<Z : ZipperMoving + Zipper + ZipperAbsolutePath, F: FnM
         // last = last.offset(-1);
         last = last.offset(-1);
     };
+    (ITER_DEPTH_AT_MOST $recursive:expr) => {
+        let depth = *last; last = last.offset(-1);
+
+        unroll!(ITER_VARIABLES $recursive);
+
+        unroll!(ITER_SYMBOLS $recursive);
+
+        if depth > 0 {
+            last = last.offset(1); *last = depth;
+            last = last.offset(1); *last = ITER_NESTED_AT_MOST;
+            unroll!(ITER_ARITIES $recursive);
+            last = last.offset(-2);
+        }
+        last = last.offset(1); *last = depth;
+    };
+    (ITER_NESTED_AT_MOST $recursive:expr) => {
+        let arity = *last; last = last.offset(-1);
+        let depth = *last; last = last.offset(-1);
+        for _ in 0..arity {
+            last = last.offset(1); *last = depth - 1;
+            last = last.offset(1); *last = ITER_DEPTH_AT_MOST;
+        }
+        referential_transition(last, loc, references, introduced, f);
+        last = last.offset(-2 * arity as isize);
+        last = last.offset(1); *last = depth;
+        last = last.offset(1); *last = arity;
+    };
+    (ITER_DEPTH_EXACTLY $recursive:expr) => {
+        let depth = *last; last = last.offset(-1);
+
+        if depth == 0 {
+            unroll!(ITER_VARIABLES $recursive);
+
+            unroll!(ITER_SYMBOLS $recursive);
+        } else {
+            last = last.offset(1); *last = depth;
+            last = last.offset(1); *last = ITER_NESTED_EXACTLY;
+            unroll!(ITER_ARITIES $recursive);
+            last = last.offset(-2);
+        }
+        last = last.offset(1); *last = depth;
+    };
+    (ITER_NESTED_EXACTLY $recursive:expr) => {
+        let arity = *last; last = last.offset(-1);
+        let depth = *last; last = last.offset(-1);
+        if arity == 0 {
+            // `()` nests one level
+            if depth == 1 { referential_transition(last, loc, references, introduced, f); }
+        }
+        // split on the first child that nests `depth - 1` levels: the ones before it nest less, the ones after no more
+        for first in 0..arity {
+            if first > 0 && depth < 2 { break }
+            let base = last;
+            for child in (0..arity).rev() {
+                let (d, op) = if child < first { (depth - 2, ITER_DEPTH_AT_MOST) }
+                              else if child == first { (depth - 1, ITER_DEPTH_EXACTLY) }
+                              else { (depth - 1, ITER_DEPTH_AT_MOST) };
+                last = last.offset(1); *last = d;
+                last = last.offset(1); *last = op;
+            }
+            referential_transition(last, loc, references, introduced, f);
+            last = base;
+        }
+        last = last.offset(1); *last = depth;
+        last = last.offset(1); *last = arity;
+    };
     (ITER_SYMBOL $recursive:expr) => {
         let size = *last; last = last.offset(-1);
         let mut v = [0; 64];
@@ -347,6 +1250,10 @@ fn referential_transition<Z : ZipperMoving + Zipper + ZipperAbsolutePath, F: FnM
             BEGIN_RANGE => { unroll!(BEGIN_RANGE $recursive); }
             FINALIZE_RANGE => { unroll!(FINALIZE_RANGE $recursive); }
             REFER_RANGE => { unroll!(REFER_RANGE $recursive); }
+            ITER_DEPTH_AT_MOST => { unroll!(ITER_DEPTH_AT_MOST $recursive); }
+            ITER_NESTED_AT_MOST => { unroll!(ITER_NESTED_AT_MOST $recursive); }
+            ITER_DEPTH_EXACTLY => { unroll!(ITER_DEPTH_EXACTLY $recursive); }
+            ITER_NESTED_EXACTLY => { unroll!(ITER_NESTED_EXACTLY $recursive); }
             RESERVED => { unreachable!("reserved opcode"); }
             c => { unreachable!("invalid opcode {}", c); }
         }
@@ -409,10 +1316,18 @@ fn referential_bidirectional_matching_stack(ez: &mut ExprZipper) -> Vec<u8> {
                 v.push(r);
             }
             Ok(Tag::SymbolSize(_)) => { unreachable!() }
-            Err(s) => {
-                v.push(ITER_VAR_SYMBOL);
-                v.push(s.len() as u8);
-                v.extend(s);
+            Err(s) => match depth_token(s) {
+                Some(depth) => {
+                    v.push(BEGIN_RANGE);
+                    v.push(ITER_DEPTH_EXACTLY);
+                    v.push(depth);
+                    v.push(FINALIZE_RANGE);
+                }
+                None => {
+                    v.push(ITER_VAR_SYMBOL);
+                    v.push(s.len() as u8);
+                    v.extend(s);
+                }
             }
             Ok(Tag::Arity(a)) => {
                 v.push(ITER_VAR_ARITY);
@@ -492,11 +1407,13 @@ unsafe extern "C" {
 
 pub struct ParDataParser<'a> { count: u64,
     #[cfg(feature="interning")]
-    buf: [u8; 8],
+    buf: [u8; 16],
     #[cfg(not(feature="interning"))]
     buf: [u8; 64],
     #[cfg(not(feature="interning"))]
     truncated: u64,
+    /// Whether `{depth=N}` atoms are pattern tokens, to be kept as they are rather than interned
+    depth_tokens: bool,
     write_permit: WritePermit<'a> }
 
 impl <'a> Parser for ParDataParser<'a> {
@@ -504,9 +1421,14 @@ impl <'a> Parser for ParDataParser<'a> {
         self.count += 1;
         #[cfg(feature="interning")]
         {
+        if (s.len() < SYM_LEN && self.write_permit.inlines_small()) || (self.depth_tokens && depth_token(s).is_some()) {
+            // stored as-is; the size tag tells readers it is not an interned id (a depth token is longer than one)
+            self.buf[..s.len()].copy_from_slice(s);
+            return unsafe { std::mem::transmute(&self.buf[..s.len()]) };
+        }
         // FIXME hack until either the parser is rewritten or we can take a pointer of the symbol
-        self.buf = (self.write_permit.get_sym_or_insert(s) );
-        return unsafe { std::mem::transmute(&self.buf[..]) };
+        self.buf[..SYM_LEN].copy_from_slice(&self.write_permit.get_sym_or_insert(s));
+        return unsafe { std::mem::transmute(&self.buf[..SYM_LEN]) };
         }
         #[cfg(not(feature="interning"))]
         {
@@ -522,19 +1444,44 @@ impl <'a> Parser for ParDataParser<'a> {
     }
 }
 
+/// [`ParDataParser`] for S-expression sources, where a quoted atom stands for the text between its quotes
+pub(crate) struct SexprParser<'a>(ParDataParser<'a>);
+
+impl <'a> SexprParser<'a> {
+    pub(crate) fn new(handle: &'a SharedMappingHandle) -> Self {
+        Self(ParDataParser::new(handle))
+    }
+}
+
+impl <'a> Parser for SexprParser<'a> {
+    fn tokenizer<'r>(&mut self, s: &[u8]) -> &'r [u8] {
+        match unquote(s) {
+            Some(text) => self.0.tokenizer(&text),
+            None => self.0.tokenizer(s),
+        }
+    }
+}
+
 impl <'a> ParDataParser<'a> {
     pub fn new(handle: &'a SharedMappingHandle) -> Self {
         Self {
             count: 3,
             #[cfg(feature="interning")]
-            buf: (3u64).to_be_bytes(),
+            buf: [0; 16],
             #[cfg(not(feature="interning"))]
             buf: [0; 64],
             #[cfg(not(feature="interning"))]
             truncated: 0u64,
+            depth_tokens: false,
             write_permit: handle.try_aquire_permission().unwrap()
         }
     }
+
+    /// A parser for pattern sources, which keeps `{depth=N}` tokens readable by the query compiler; data atoms
+    /// spelled the same way are interned like any other symbol
+    pub fn for_patterns(handle: &'a SharedMappingHandle) -> Self {
+        Self { depth_tokens: true, ..Self::new(handle) }
+    }
 }
 
 pub struct SpaceTranscriber<'a, 'b, 'c> { count: usize, wz: &'c mut WriteZipperUntracked<'a, 'b, ()>, pdp: ParDataParser<'a> }
@@ -574,7 +1521,8 @@ impl <'a, 'b, 'c> crate::json_parser::Transcriber for SpaceTranscriber<'a, 'b, '
         if last { self.wz.ascend(1); }
     }
     #[inline(always)] fn write_empty_object(&mut self) -> () { self.write("{}"); self.count += 1; }
-    #[inline(always)] fn write_string(&mut self, s: &str) -> () { self.write(s); self.count += 1; }
+    // strings are stored unquoted, but there is no empty symbol, so "" keeps its quotes
+    #[inline(always)] fn write_string(&mut self, s: &str) -> () { self.write(if s.is_empty() { "\"\"" } else { s }); self.count += 1; }
     #[inline(always)] fn write_number(&mut self, negative: bool, mantissa: u64, exponent: i16) -> () {
         let mut s = String::new();
         if negative { s.push('-'); }
@@ -583,6 +1531,7 @@ impl <'a, 'b, 'c> crate::json_parser::Transcriber for SpaceTranscriber<'a, 'b, '
         self.write(s);
         self.count += 1;
     }
+    #[inline(always)] fn write_non_finite(&mut self, literal: &str) -> () { self.write(literal); self.count += 1; }
     #[inline(always)] fn write_true(&mut self) -> () { self.write("true"); self.count += 1; }
     #[inline(always)] fn write_false(&mut self) -> () { self.write("false"); self.count += 1; }
     #[inline(always)] fn write_null(&mut self) -> () { self.write("null"); self.count += 1; }
@@ -595,7 +1544,7 @@ macro_rules! prefix {
     ($space:ident, $s:literal) => {{
         let mut src = parse!($s);
         let q = Expr{ ptr: src.as_mut_ptr() };
-        let mut pdp = ParDataParser::new(&$space.sm);
+        let mut pdp = ParDataParser::for_patterns(&$space.sm);
         let mut buf = [0u8; 2048];
         let p = Expr{ ptr: buf.as_mut_ptr() };
         let used = q.substitute_symbols(&mut ExprZipper::new(p), |x| pdp.tokenizer(x));
@@ -612,21 +1561,307 @@ macro_rules! prefix {
 macro_rules! expr {
     ($space:ident, $s:literal) => {{
         // Simplified stub implementation
-        let src = crate::stubs::parse_expr!($s);
-        crate::stubs::Expr{ ptr: src.as_ptr() as *mut u8 }
+        let src = $crate::parse_expr!($s);
+        $crate::Expr{ ptr: src.as_ptr() as *mut u8 }
     }};
 }
 
-#[macro_export]
-macro_rules! sexpr {
-    ($space:ident, $e:expr) => {{
-        format!("sexpr: {:?}", $e)
-    }};
+/// Whether every `_n` in `template` refers to a variable introduced by a `$` in `pattern` (or earlier in
+/// `template`), counting on the space-separated source form `expr!` takes; usable in constants
+pub const fn template_vars_bound(pattern: &str, template: &str) -> bool {
+    let introduced = introduced_vars(pattern.as_bytes(), 0);
+    introduced >= 0 && introduced_vars(template.as_bytes(), introduced) >= 0
 }
 
-impl Space {
+/// The number of variables introduced once `src` has been read, or -1 if some `_n` refers past them
+const fn introduced_vars(src: &[u8], mut introduced: i64) -> i64 {
+    let mut i = 0;
+    while i < src.len() {
+        let start = i;
+        while i < src.len() && src[i] != b' ' { i += 1; }
+        if i > start {
+            if src[start] == b'$' {
+                introduced += 1;
+            } else if src[start] == b'_' && i > start + 1 {
+                let mut n = 0i64;
+                let mut j = start + 1;
+                while j < i && src[j].is_ascii_digit() { n = n*10 + (src[j] - b'0') as i64; j += 1; }
+                if j == i && n > introduced { return -1 }
+            }
+        }
+        i += 1;
+    }
+    introduced
+}
+
+/// `(expr!(space, pattern), expr!(space, template))`, refusing to compile when the template refers to a
+/// variable the pattern does not introduce
+///
+/// ```
+/// use mork::{transform_expr, space::Space};
+/// let s = Space::new();
+/// let (pattern, template) = transform_expr!(s, "[2] children $" => "[2] child_results _1");
+/// ```
+///
+/// The same transform with a template referring to `_2` is refused:
+///
+/// ```compile_fail
+/// use mork::{transform_expr, space::Space};
+/// let s = Space::new();
+/// // only one variable is introduced, so `_2` is unbound
+/// let (pattern, template) = transform_expr!(s, "[2] children $" => "[2] child_results _2");
+/// ```
+#[macro_export]
+macro_rules! transform_expr {
+    ($space:ident, $pattern:literal => $template:literal) => {{
+        const _: () = assert!($crate::space::template_vars_bound($pattern, $template),
+            concat!("template `", $template, "` refers to a variable that `", $pattern, "` does not introduce"));
+        ($crate::expr!($space, $pattern), $crate::expr!($space, $template))
+    }};
+}
+
+/// A pattern together with the names of its `$name` captures, in introduction order
+pub struct NamedPattern {
+    pub expr: Expr,
+    pub names: Vec<&'static str>,
+}
+
+/// Names of the new-variables in a pattern source, in introduction order (`""` for an anonymous `$`)
+pub fn capture_names(src: &'static str) -> Vec<&'static str> {
+    src.split(' ').filter_map(|token| token.strip_prefix('$')).collect()
+}
+
+#[macro_export]
+macro_rules! named_expr {
+    ($space:ident, $s:literal) => {{
+        $crate::space::NamedPattern { expr: $crate::expr!($space, $s), names: $crate::space::capture_names($s) }
+    }};
+}
+
+#[macro_export]
+macro_rules! sexpr {
+    ($space:ident, $e:expr) => {{
+        format!("sexpr: {:?}", $e)
+    }};
+}
+
+impl Space {
     pub fn new() -> Self {
-        Self { btm: BytesTrieMap::new(), sm: SharedMappingHandle::new() }
+        Self { btm: BytesTrieMap::new(), sm: SharedMappingHandle::new(), subscriptions: Default::default() }
+    }
+
+    /// A space whose interned symbol IDs are derived from the symbol bytes rather than load order,
+    /// so identical corpora produce identical tries (and backups) whatever order they were loaded in
+    pub fn with_stable_symbols() -> Self {
+        Self { btm: BytesTrieMap::new(), sm: SharedMappingHandle::new_stable(), subscriptions: Default::default() }
+    }
+
+    /// A space that stores symbols shorter than an interned id inline (as their raw size-tagged bytes)
+    /// and only interns the longer ones
+    pub fn with_inline_symbols() -> Self {
+        Self { btm: BytesTrieMap::new(), sm: SharedMappingHandle::new_inlining(), subscriptions: Default::default() }
+    }
+
+    /// The symbol that loading `bytes` would store in the trie, interning it if needed
+    pub fn intern_symbol(&self, bytes: &[u8]) -> OwnedSymbol {
+        #[cfg(feature="interning")]
+        {
+        if bytes.len() < SYM_LEN && self.sm.inlines_small() { return bytes.to_vec() }
+        self.sm.try_aquire_permission().unwrap().get_sym_or_insert(bytes).to_vec()
+        }
+        #[cfg(not(feature="interning"))]
+        bytes[..bytes.len().min(63)].to_vec()
+    }
+
+    /// The symbol that loading `bytes` would store in the trie, without interning it: `None` if it was never interned
+    fn lookup_symbol(&self, bytes: &[u8]) -> Option<OwnedSymbol> {
+        #[cfg(feature="interning")]
+        {
+        if bytes.len() < SYM_LEN && self.sm.inlines_small() { return Some(bytes.to_vec()) }
+        self.sm.get_sym(bytes).map(|sym| sym.to_vec())
+        }
+        #[cfg(not(feature="interning"))]
+        Some(bytes[..bytes.len().min(63)].to_vec())
+    }
+
+    /// The original bytes of a symbol found in the trie, or `None` if it was never interned
+    pub fn resolve_symbol(&self, id: &[u8]) -> Option<Vec<u8>> {
+        #[cfg(feature="interning")]
+        {
+        if id.len() < SYM_LEN { return self.sm.inlines_small().then(|| id.to_vec()) }
+        self.sm.get_bytes(id.try_into().ok()?).map(|x| x.to_vec())
+        }
+        #[cfg(not(feature="interning"))]
+        Some(id.to_vec())
+    }
+
+    /// What to add to and remove from `base` to turn it into this space.
+    /// Facts are compared by their encoding, so both spaces need the same symbol ids (e.g. `with_stable_symbols`).
+    pub fn diff(&self, base: &Space) -> SpaceDiff {
+        let mut diff = SpaceDiff::default();
+        let mut rz = self.btm.read_zipper();
+        while rz.to_next_val() {
+            if base.btm.get(rz.path()).is_none() { diff.added.push(rz.path().to_vec()) }
+        }
+        let mut rz = base.btm.read_zipper();
+        while rz.to_next_val() {
+            if self.btm.get(rz.path()).is_none() { diff.removed.push(rz.path().to_vec()) }
+        }
+        diff
+    }
+
+    /// Applies a diff made against this space's contents: either every added fact is inserted and every removed
+    /// one deleted, or, if some added fact is already present or some removed one is missing, nothing changes
+    pub fn apply_diff(&mut self, diff: &SpaceDiff) -> Result<(), SpaceError> {
+        if let Some(path) = diff.added.iter().find(|p| self.btm.get(&p[..]).is_some())
+            .or_else(|| diff.removed.iter().find(|p| self.btm.get(&p[..]).is_none())) {
+            return Err(SpaceError::DiffConflict { path: path.clone() })
+        }
+        for path in diff.removed.iter() {
+            self.btm.remove(&path[..]);
+        }
+        for path in diff.added.iter() {
+            self.btm.insert(&path[..], ());
+            self.notify_inserted(&path[..]);
+        }
+        self.apply_derived();
+        Ok(())
+    }
+
+    /// Calls `f` with every fact that contains `symbol` anywhere in its structure; returns the number of such facts.
+    /// Scans the whole trie, comparing the symbol items of each fact.
+    pub fn expressions_containing<F : FnMut(Expr) -> ()>(&self, symbol: &[u8], mut f: F) -> usize {
+        let Some(stored) = self.lookup_symbol(symbol) else { return 0 };
+        let mut found = 0;
+        let mut rz = self.btm.read_zipper();
+        while rz.to_next_val() {
+            let e = Expr{ ptr: rz.path().as_ptr().cast_mut() };
+            let mut ez = ExprZipper::new(e);
+            loop {
+                if ez.item() == Err(&stored[..]) {
+                    f(e);
+                    found += 1;
+                    break
+                }
+                if !ez.next() { break }
+            }
+        }
+        found
+    }
+
+    /// Every distinct symbol occurring in the space whose text starts with `prefix`, in the form it is stored in
+    /// the trie (see [`Space::resolve_symbol`] for its text); scans the whole trie
+    pub fn query_symbols(&self, prefix: &[u8]) -> Vec<OwnedSymbol> {
+        let mut found = std::collections::BTreeSet::<OwnedSymbol>::new();
+        let mut rz = self.btm.read_zipper();
+        while rz.to_next_val() {
+            let mut ez = ExprZipper::new(Expr{ ptr: rz.path().as_ptr().cast_mut() });
+            loop {
+                if let Err(s) = ez.item() {
+                    if !found.contains(s) && self.resolve_symbol(s).is_some_and(|text| text.starts_with(prefix)) {
+                        found.insert(s.to_vec());
+                    }
+                }
+                if !ez.next() { break }
+            }
+        }
+        found.into_iter().collect()
+    }
+
+    /// Calls `on_insert` with every fact matching `pattern` that a loader newly adds to the space.
+    /// The callback runs while the subscriptions are locked, so it must not (un)subscribe itself.
+    pub fn subscribe(&self, pattern: Expr, on_insert: impl FnMut(Expr) + Send + 'static) -> SubscriptionId {
+        let mut subscriptions = self.subscriptions.lock().unwrap();
+        let id = subscriptions.next_id;
+        subscriptions.next_id += 1;
+        let pattern = unsafe { pattern.span().as_ref().unwrap() }.to_vec();
+        subscriptions.entries.push((id, pattern, Box::new(on_insert)));
+        id
+    }
+
+    /// Removes a subscription; unknown ids are ignored
+    pub fn unsubscribe(&self, id: SubscriptionId) {
+        self.subscriptions.lock().unwrap().entries.retain(|(sid, _, _)| *sid != id);
+    }
+
+    fn notify_inserted(&self, fact: &[u8]) {
+        let mut subscriptions = self.subscriptions.lock().unwrap();
+        for (_, pattern, on_insert) in subscriptions.entries.iter_mut() {
+            if pattern_matches_fact(pattern, fact) {
+                on_insert(Expr{ ptr: fact.as_ptr().cast_mut() });
+            }
+        }
+    }
+
+    /// Called by loaders for a fact they write that was already present: if a derived view added it, the fact is
+    /// now loaded data as well and must outlive the view
+    fn notify_reloaded(&self, fact: &[u8]) {
+        for (_, state) in self.subscriptions.lock().unwrap().views.iter() {
+            state.lock().unwrap().written.remove(fact);
+        }
+    }
+
+    /// Writes `template` instantiated for every match of `source_pattern`, like `transform`, and keeps doing so for the
+    /// matching facts loaders add later: a loader writes the derived facts once its own are in, and
+    /// `load_sexpr_exclusive` refuses to run while views exist. Derived facts are announced to subscribers like loaded
+    /// ones, so views can build on each other (or on themselves, as `(s $x)` to `(s (s $x))` does); a loader writes at
+    /// most [`MAX_DERIVED_PASSES`] rounds of outputs, and whatever the last round derives waits for the next loader.
+    /// [`Space::remove_derived_view`] deletes what the view added, unless a loader has written the same fact since.
+    pub fn derived_view(&mut self, source_pattern: Expr, template: Expr) -> DerivedView {
+        let state = std::sync::Arc::new(std::sync::Mutex::new(DerivedState::default()));
+        let pattern = unsafe { source_pattern.span().as_ref().unwrap() }.to_vec();
+        let template = unsafe { template.span().as_ref().unwrap() }.to_vec();
+        let mut buffer = vec![0u8; 4096];
+        let mut derive = move |fact: Expr| {
+            let mut oz = ExprZipper::new(Expr{ ptr: buffer.as_mut_ptr() });
+            fact.transformData(Expr{ ptr: pattern.as_ptr().cast_mut() }, Expr{ ptr: template.as_ptr().cast_mut() }, &mut oz).ok()?;
+            Some(buffer[..oz.loc].to_vec())
+        };
+
+        let mut existing = vec![];
+        Self::query_multi(&self.btm, &[source_pattern], |_, loc| {
+            existing.push(unsafe { loc.span().as_ref().unwrap() }.to_vec());
+            Ok::<(), ()>(())
+        }).unwrap();
+        state.lock().unwrap().pending.extend(existing.iter().filter_map(|fact| derive(Expr{ ptr: fact.as_ptr().cast_mut() })));
+
+        let queue = state.clone();
+        let id = self.subscribe(source_pattern, move |fact| {
+            if let Some(derived) = derive(fact) { queue.lock().unwrap().pending.push(derived) }
+        });
+        self.subscriptions.lock().unwrap().views.push((id, state.clone()));
+        self.apply_derived();
+        DerivedView { id, state }
+    }
+
+    /// Stops `view` and deletes the facts it added, except those a loader has written since; returns how many were
+    /// still present
+    pub fn remove_derived_view(&mut self, view: DerivedView) -> usize {
+        self.unsubscribe(view.id);
+        self.subscriptions.lock().unwrap().views.retain(|(id, _)| *id != view.id);
+        let written = std::mem::take(&mut view.state.lock().unwrap().written);
+        written.iter().filter(|fact| self.btm.remove(&fact[..]).is_some()).count()
+    }
+
+    /// Writes the outputs derived views queued while a loader ran, until writing them derives nothing new or
+    /// [`MAX_DERIVED_PASSES`] rounds are written
+    fn apply_derived(&mut self) {
+        for _ in 0..MAX_DERIVED_PASSES {
+            let views: Vec<_> = self.subscriptions.lock().unwrap().views.iter().map(|(_, state)| state.clone()).collect();
+            let mut any_new = false;
+            for state in views {
+                let pending = std::mem::take(&mut state.lock().unwrap().pending);
+                for fact in pending {
+                    if self.btm.insert(&fact[..], ()).is_none() {
+                        // the callbacks may queue more outputs, so the view's state must not be locked here
+                        self.notify_inserted(&fact[..]);
+                        state.lock().unwrap().written.insert(fact);
+                        any_new = true;
+                    }
+                }
+            }
+            if !any_new { return }
+        }
     }
 
     /// Remy :I want to really discourage the use of this method, it needs to be exposed if we want to use the debugging macros `expr` and `sexpr` without giving acces directly to the field
@@ -639,6 +1874,11 @@ impl Space {
         println!("val count {}", self.btm.val_count());
     }
 
+    /// Opens a write zipper at `path` through `zh`, failing with `SpaceError::PathConflict` if another zipper overlaps it
+    pub fn exclusive_write_zipper<'a>(zh: &'a ZipperHead<()>, path: &[u8]) -> Result<WriteZipper<()>, SpaceError> {
+        zh.write_zipper_at_exclusive_path(path).map_err(|_| SpaceError::PathConflict)
+    }
+
     fn write_zipper_unchecked<'a>(&'a self) -> WriteZipperUntracked<'a, 'a, ()> {
         unsafe { (&self.btm as *const BytesTrieMap<()>).cast_mut().as_mut().unwrap().write_zipper() }
     }
@@ -690,12 +1930,18 @@ impl Space {
 
 
     pub fn load_csv(&mut self, r: &[u8], pattern: Expr, template: Expr, seperator: u8) -> Result<usize, String> {
+        self.load_csv_sized::<2048>(r, pattern, template, seperator)
+    }
+
+    /// `load_csv` with `N`-byte buffers for the encoded row and its transformed form, for rows (or templates)
+    /// that encode to more than the 2048 bytes `load_csv` sets aside
+    pub fn load_csv_sized<const N: usize>(&mut self, r: &[u8], pattern: Expr, template: Expr, seperator: u8) -> Result<usize, String> {
         let constant_template_prefix = unsafe { template.prefix().unwrap_or_else(|_| template.span()).as_ref().unwrap() };
         let mut wz = self.write_zipper_at_unchecked(constant_template_prefix);
-        let mut buf = [0u8; 2048];
+        let mut buf = [0u8; N];
 
         let mut i = 0usize;
-        let mut stack = [0u8; 2048];
+        let mut stack = [0u8; N];
         let mut pdp = ParDataParser::new(&self.sm);
         for sv in r.split(|&x| x == b'\n') {
             if sv.len() == 0 { continue }
@@ -710,6 +1956,7 @@ impl Space {
             ez.loc += num.len() + 1;
 
             for symbol in sv.split(|&x| x == seperator) {
+                if symbol.is_empty() { return Err(SpaceError::EmptySymbol { row: i, column: a }.into()) }
                 let internal = pdp.tokenizer(symbol);
                 ez.write_symbol(&internal[..]);
                 ez.loc += internal.len() + 1;
@@ -727,11 +1974,80 @@ impl Space {
             }
             let new_data = &buf[..oz.loc];
             wz.descend_to(&new_data[constant_template_prefix.len()..]);
-            wz.set_value(());
+            if wz.set_value(()).is_none() { self.notify_inserted(new_data) } else { self.notify_reloaded(new_data) }
             wz.reset();
             i += 1;
         }
 
+        drop(wz);
+        self.apply_derived();
+        Ok(i)
+    }
+
+    /// Loads every row of a Parquet file as `(<row> (<column> <value>) ...)`, the CSV row shape with column names as field labels
+    #[cfg(feature="parquet")]
+    pub fn load_parquet<P : AsRef<std::path::Path>>(&mut self, path: P, pattern: Expr, template: Expr) -> Result<usize, String> {
+        use parquet::file::reader::{FileReader, SerializedFileReader};
+        use parquet::record::Field;
+
+        let file = File::open(path).map_err(|e| e.to_string())?;
+        let reader = SerializedFileReader::new(file).map_err(|e| e.to_string())?;
+
+        let constant_template_prefix = unsafe { template.prefix().unwrap_or_else(|_| template.span()).as_ref().unwrap() };
+        let mut wz = self.write_zipper_at_unchecked(constant_template_prefix);
+        let mut buf = [0u8; 2048];
+
+        let mut i = 0usize;
+        let mut stack = [0u8; 2048];
+        let mut pdp = ParDataParser::new(&self.sm);
+        for g in 0..reader.num_row_groups() {
+            let row_group = reader.get_row_group(g).map_err(|e| e.to_string())?;
+            for row in row_group.get_row_iter(None).map_err(|e| e.to_string())? {
+                let row = row.map_err(|e| e.to_string())?;
+                let mut a = 0;
+                let e = Expr{ ptr: stack.as_mut_ptr() };
+                let mut ez = ExprZipper::new(e);
+                ez.loc += 1;
+                let num = pdp.tokenizer(i.to_string().as_bytes());
+                ez.write_symbol(num);
+                ez.loc += num.len() + 1;
+
+                for (name, field) in row.get_column_iter() {
+                    let value = match field {
+                        Field::Str(s) => s.clone(),
+                        Field::Null => "null".to_string(),
+                        other => other.to_string(),
+                    };
+                    ez.write_arity(2);
+                    ez.loc += 1;
+                    let label = pdp.tokenizer(name.as_bytes());
+                    ez.write_symbol(&label[..]);
+                    ez.loc += label.len() + 1;
+                    let internal = pdp.tokenizer(value.as_bytes());
+                    ez.write_symbol(&internal[..]);
+                    ez.loc += internal.len() + 1;
+                    a += 1;
+                }
+                let total = ez.loc;
+                ez.reset();
+                ez.write_arity(a + 1);
+
+                let data = &stack[..total];
+                let mut oz = ExprZipper::new(Expr{ ptr: buf.as_ptr().cast_mut() });
+                match (Expr{ ptr: data.as_ptr().cast_mut() }.transformData(pattern, template, &mut oz)) {
+                    Ok(()) => {}
+                    Err(e) => { continue }
+                }
+                let new_data = &buf[..oz.loc];
+                wz.descend_to(&new_data[constant_template_prefix.len()..]);
+                if wz.set_value(()).is_none() { self.notify_inserted(new_data) } else { self.notify_reloaded(new_data) }
+                wz.reset();
+                i += 1;
+            }
+        }
+
+        drop(wz);
+        self.apply_derived();
         Ok(i)
     }
 
@@ -867,9 +2183,9 @@ impl Space {
           // .unhandled_panic(tokio::runtime::UnhandledPanic::Ignore)
           .build()
           .unwrap();
-        let mut pdp = ParDataParser::new(&self.sm);
         let zh = self.btm.zipper_head();
-        let mut wz = zh.write_zipper_at_exclusive_path(&[]).unwrap();
+        let mut wz = Self::exclusive_write_zipper(&zh, &[])?;
+        let mut pdp = ParDataParser::new(&self.sm);
         let sa_symbol = pdp.tokenizer("NKV".as_bytes());
         let mut nodes = 0;
         let mut attributes = 0;
@@ -939,9 +2255,9 @@ impl Space {
           // .unhandled_panic(tokio::runtime::UnhandledPanic::Ignore)
           .build()
           .unwrap();
-        let mut pdp = ParDataParser::new(&self.sm);
         let zh = self.btm.zipper_head();
-        let mut wz = zh.write_zipper_at_exclusive_path(&[]).unwrap();
+        let mut wz = Self::exclusive_write_zipper(&zh, &[])?;
+        let mut pdp = ParDataParser::new(&self.sm);
         let sa_symbol = pdp.tokenizer("NL".as_bytes());
         let mut nodes = 0;
         let mut labels = 0;
@@ -987,11 +2303,10 @@ impl Space {
     pub fn load_sexpr(&mut self, r: &[u8], pattern: Expr, template: Expr) -> Result<usize, String> {
         let constant_template_prefix = unsafe { template.prefix().unwrap_or_else(|_| template.span()).as_ref().unwrap() };
         let mut wz = self.write_zipper_at_unchecked(constant_template_prefix);
-        let mut buffer = [0u8; 4096];
+        let (mut stack, mut buffer) = load_buffers(r, template);
         let mut it = Context::new(r);
         let mut i = 0;
-        let mut stack = [0u8; 2048];
-        let mut parser = ParDataParser::new(&self.sm);
+        let mut parser = SexprParser::new(&self.sm);
         loop {
             let mut ez = ExprZipper::new(Expr{ptr: stack.as_mut_ptr()});
             match parser.sexpr(&mut it, &mut ez) {
@@ -1004,43 +2319,264 @@ impl Space {
                     }
                     let new_data = &buffer[..oz.loc];
                     wz.descend_to(&new_data[constant_template_prefix.len()..]);
-                    wz.set_value(());
+                    if wz.set_value(()).is_none() { self.notify_inserted(new_data) } else { self.notify_reloaded(new_data) }
+                    wz.reset();
+                }
+                Err(ParserError::InputFinished) => { break }
+                Err(other) => { return Err(format!("parse error {:?} in expression {} (byte {})", other, i, it.loc)) }
+            }
+            i += 1;
+            it.variables.clear();
+        }
+        drop(wz);
+        self.apply_derived();
+        Ok(i)
+    }
+
+    /// `load_sexpr` through a write zipper taken from `zh` at the template's constant prefix, so the load can run
+    /// next to other zippers on disjoint paths. The exclusive path is claimed before the parser (and its write permit
+    /// on the symbol table) is set up, so a load into an occupied region fails with `PathConflict` without reading `r`.
+    /// Fails with `DerivedViewsActive` while the space has derived views, whose outputs the zipper cannot write.
+    pub fn load_sexpr_exclusive(&self, zh: &ZipperHead<()>, r: &[u8], pattern: Expr, template: Expr) -> Result<usize, String> {
+        if !self.subscriptions.lock().unwrap().views.is_empty() { return Err(SpaceError::DerivedViewsActive.into()) }
+        let constant_template_prefix = unsafe { template.prefix().unwrap_or_else(|_| template.span()).as_ref().unwrap() };
+        let mut wz = Self::exclusive_write_zipper(zh, constant_template_prefix)?;
+        let (mut stack, mut buffer) = load_buffers(r, template);
+        let mut it = Context::new(r);
+        let mut i = 0;
+        let mut parser = SexprParser::new(&self.sm);
+        loop {
+            let mut ez = ExprZipper::new(Expr{ptr: stack.as_mut_ptr()});
+            match parser.sexpr(&mut it, &mut ez) {
+                Ok(()) => {
+                    let data = &stack[..ez.loc];
+                    let mut oz = ExprZipper::new(Expr{ ptr: buffer.as_ptr().cast_mut() });
+                    if (Expr{ ptr: data.as_ptr().cast_mut() }.transformData(pattern, template, &mut oz)).is_err() { continue }
+                    let new_data = &buffer[..oz.loc];
+                    wz.descend_to(&new_data[constant_template_prefix.len()..]);
+                    if wz.set_value(()).is_none() { self.notify_inserted(new_data) }
                     wz.reset();
                 }
                 Err(ParserError::InputFinished) => { break }
-                Err(other) => { panic!("{:?}", other) }
+                Err(other) => { return Err(format!("parse error {:?} in expression {} (byte {})", other, i, it.loc)) }
+            }
+            i += 1;
+            it.variables.clear();
+        }
+        Ok(i)
+    }
+
+    /// `load_sexpr` that first collects and sorts the transformed expressions, then inserts them in order so the
+    /// write zipper only climbs back to the prefix shared with the previous expression instead of resetting.
+    /// Holds every transformed expression in memory at once; loads the same trie as `load_sexpr`.
+    pub fn load_sexpr_batched(&mut self, r: &[u8], pattern: Expr, template: Expr) -> Result<usize, String> {
+        let constant_template_prefix = unsafe { template.prefix().unwrap_or_else(|_| template.span()).as_ref().unwrap() };
+        let (mut stack, mut buffer) = load_buffers(r, template);
+        let mut it = Context::new(r);
+        let mut i = 0;
+        let mut parser = SexprParser::new(&self.sm);
+        let mut batch: Vec<Vec<u8>> = vec![];
+        loop {
+            let mut ez = ExprZipper::new(Expr{ptr: stack.as_mut_ptr()});
+            match parser.sexpr(&mut it, &mut ez) {
+                Ok(()) => {
+                    let data = &stack[..ez.loc];
+                    let mut oz = ExprZipper::new(Expr{ ptr: buffer.as_ptr().cast_mut() });
+                    if (Expr{ ptr: data.as_ptr().cast_mut() }.transformData(pattern, template, &mut oz)).is_ok() {
+                        batch.push(buffer[constant_template_prefix.len()..oz.loc].to_vec());
+                    }
+                }
+                Err(ParserError::InputFinished) => { break }
+                Err(other) => { return Err(format!("parse error {:?} in expression {} (byte {})", other, i, it.loc)) }
             }
             i += 1;
             it.variables.clear();
         }
+        drop(parser);
+        batch.sort_unstable();
+        batch.dedup();
+
+        let mut wz = self.write_zipper_at_unchecked(constant_template_prefix);
+        let mut fact = constant_template_prefix.to_vec();
+        let mut previous: &[u8] = &[];
+        for path in batch.iter() {
+            let shared = find_prefix_overlap(previous, path);
+            wz.ascend(previous.len() - shared);
+            wz.descend_to(&path[shared..]);
+            let new = wz.set_value(()).is_none();
+            fact.truncate(constant_template_prefix.len());
+            fact.extend_from_slice(path);
+            if new { self.notify_inserted(&fact[..]) } else { self.notify_reloaded(&fact[..]) }
+            previous = path;
+        }
+        drop(wz);
+        self.apply_derived();
         Ok(i)
     }
 
+    /// `load_sexpr` split over `threads` worker threads: the input is cut at top-level expression boundaries, every
+    /// worker parses its chunk into a private trie (interning through its own write permit on the shared symbol
+    /// table), and the chunks are merged into the space once all workers are done. Nothing is added if any chunk
+    /// fails to parse. Returns the number of expressions read.
+    pub fn load_sexpr_parallel(&mut self, r: &[u8], pattern: Expr, template: Expr, threads: usize) -> Result<usize, String> {
+        let constant_template_prefix = unsafe { template.prefix().unwrap_or_else(|_| template.span()).as_ref().unwrap() };
+        // `Expr` is a raw pointer, so hand the workers the underlying bytes instead
+        let pattern_bytes = unsafe { pattern.span().as_ref().unwrap() };
+        let template_bytes = unsafe { template.span().as_ref().unwrap() };
+        let sm = &self.sm;
+        let chunks = sexpr_chunks(r, threads.max(1));
+
+        let parts: Vec<Result<(usize, BytesTrieMap<()>), String>> = std::thread::scope(|scope| {
+            let workers: Vec<_> = chunks.iter().enumerate().map(|(c, &(offset, chunk))| scope.spawn(move || {
+                let pattern = Expr{ ptr: pattern_bytes.as_ptr().cast_mut() };
+                let template = Expr{ ptr: template_bytes.as_ptr().cast_mut() };
+                let mut part = BytesTrieMap::new();
+                let (mut stack, mut buffer) = load_buffers(chunk, template);
+                let mut it = Context::new(chunk);
+                let mut i = 0;
+                let mut parser = SexprParser::new(sm);
+                loop {
+                    let mut ez = ExprZipper::new(Expr{ptr: stack.as_mut_ptr()});
+                    match parser.sexpr(&mut it, &mut ez) {
+                        Ok(()) => {
+                            let data = &stack[..ez.loc];
+                            let mut oz = ExprZipper::new(Expr{ ptr: buffer.as_ptr().cast_mut() });
+                            if (Expr{ ptr: data.as_ptr().cast_mut() }.transformData(pattern, template, &mut oz)).is_ok() {
+                                part.insert(&buffer[constant_template_prefix.len()..oz.loc], ());
+                            }
+                        }
+                        Err(ParserError::InputFinished) => { break }
+                        Err(other) => { return Err(format!("parse error {:?} in chunk {} expression {} (byte {})", other, c, i, offset + it.loc)) }
+                    }
+                    i += 1;
+                    it.variables.clear();
+                }
+                Ok((i, part))
+            })).collect();
+            workers.into_iter().map(|w| w.join().unwrap_or_else(|_| Err("load worker panicked".to_string()))).collect()
+        });
+
+        let mut read = 0;
+        let mut merged = vec![];
+        for part in parts {
+            let (i, part) = part?;
+            read += i;
+            merged.push(part);
+        }
+
+        let mut wz = self.write_zipper_at_unchecked(constant_template_prefix);
+        let mut fact = constant_template_prefix.to_vec();
+        for part in merged.iter() {
+            let mut rz = part.read_zipper();
+            while rz.to_next_val() {
+                wz.descend_to(rz.path());
+                let new = wz.set_value(()).is_none();
+                fact.truncate(constant_template_prefix.len());
+                fact.extend_from_slice(rz.path());
+                if new { self.notify_inserted(&fact[..]) } else { self.notify_reloaded(&fact[..]) }
+                wz.ascend(rz.path().len());
+            }
+        }
+        drop(wz);
+        self.apply_derived();
+        Ok(read)
+    }
+
+    /// Loads the expressions of `r` one top-level expression at a time, calling `on_line` with the (0-based) line
+    /// each one starts on and whether it parsed, so a line holding several expressions gets a callback for each and
+    /// an expression spread over several lines one for its first line. After a parse error the rest of the line it
+    /// occurred on is skipped and loading goes on from the next one. Blank and comment-only lines are skipped without
+    /// a callback. Returns the number of facts added to the space.
+    pub fn load_sexpr_lines<F : FnMut(usize, Result<(), ParserError>)>(&mut self, r: &[u8], pattern: Expr, template: Expr, mut on_line: F) -> usize {
+        let constant_template_prefix = unsafe { template.prefix().unwrap_or_else(|_| template.span()).as_ref().unwrap() };
+        let mut wz = self.write_zipper_at_unchecked(constant_template_prefix);
+        let (mut stack, mut buffer) = load_buffers(r, template);
+        let mut it = Context::new(r);
+        let mut parser = SexprParser::new(&self.sm);
+        let mut added = 0;
+        // the line of `r[counted]`
+        let (mut line, mut counted) = (0, 0);
+        loop {
+            let start = skip_blank(r, it.loc);
+            line += r[counted..start].iter().filter(|&&c| c == b'\n').count();
+            counted = start;
+            let mut ez = ExprZipper::new(Expr{ptr: stack.as_mut_ptr()});
+            match parser.sexpr(&mut it, &mut ez) {
+                Ok(()) => {
+                    let data = &stack[..ez.loc];
+                    let mut oz = ExprZipper::new(Expr{ ptr: buffer.as_ptr().cast_mut() });
+                    if (Expr{ ptr: data.as_ptr().cast_mut() }.transformData(pattern, template, &mut oz)).is_ok() {
+                        let new_data = &buffer[..oz.loc];
+                        wz.descend_to(&new_data[constant_template_prefix.len()..]);
+                        if wz.set_value(()).is_none() { self.notify_inserted(new_data); added += 1; } else { self.notify_reloaded(new_data) }
+                        wz.reset();
+                    }
+                    on_line(line, Ok(()));
+                }
+                Err(ParserError::InputFinished) => { break }
+                Err(other) => {
+                    on_line(line, Err(other));
+                    let at = it.loc.min(r.len());
+                    it.loc = r[at..].iter().position(|&c| c == b'\n').map_or(r.len(), |k| at + k + 1);
+                }
+            }
+            it.variables.clear();
+        }
+        drop(wz);
+        self.apply_derived();
+        added
+    }
+
+    /// Loads every file with its own template (e.g. `[2] source_a _1`, tagging each fact with its origin).
+    /// A file that cannot be read or parsed does not stop the others; the results are in the order of `sources`.
+    pub fn load_sexpr_files<P : AsRef<std::path::Path>>(&mut self, sources: &[(P, Expr)]) -> Vec<Result<usize, String>> {
+        let mut all = [item_byte(Tag::NewVar)];
+        let pattern = Expr{ ptr: all.as_mut_ptr() };
+        sources.iter().map(|(path, template)| {
+            let src = std::fs::read(path).map_err(|e| format!("{}: {}", path.as_ref().display(), e))?;
+            self.load_sexpr(&src[..], pattern, *template)
+        }).collect()
+    }
+
     pub fn dump_all_sexpr<W : Write>(&self, w: &mut W) -> Result<usize, String> {
         let mut rz = self.btm.read_zipper();
         let mut i = 0usize;
         while rz.to_next_val() {
-            Expr{ ptr: rz.path().as_ptr().cast_mut() }.serialize(w, |s| {
-                #[cfg(feature="interning")]
-                {
-                    let symbol = i64::from_be_bytes(s.try_into().unwrap()).to_be_bytes();
-                    let mstr = self.sm.get_bytes(symbol).map(unsafe { |x| std::str::from_utf8_unchecked(x) });
-                    // println!("symbol {symbol:?}, bytes {mstr:?}");
-                    unsafe { std::mem::transmute(mstr.expect(format!("failed to look up {:?}", symbol).as_str())) }
-                }
-                #[cfg(not(feature="interning"))]
-                unsafe { std::mem::transmute(std::str::from_utf8(s).unwrap()) }
-            });
+            serialize_fact(&self.sm, Expr{ ptr: rz.path().as_ptr().cast_mut() }, w);
             w.write(&[b'\n']).map_err(|x| x.to_string())?;
             i += 1;
         }
         Ok(i)
     }
 
+    /// `dump_all_sexpr`, as `options` asks; sorting renders every fact in memory before writing any
+    pub fn dump_all_sexpr_with_options<W : Write>(&self, w: &mut W, options: &DumpOptions) -> Result<usize, String> {
+        if !options.sort_by_symbol { return self.dump_all_sexpr(w) }
+        let mut lines: Vec<Vec<u8>> = vec![];
+        let mut rz = self.btm.read_zipper();
+        while rz.to_next_val() {
+            let mut line = vec![];
+            serialize_fact(&self.sm, Expr{ ptr: rz.path().as_ptr().cast_mut() }, &mut line);
+            lines.push(line);
+        }
+        lines.sort_unstable();
+        for line in lines.iter() {
+            w.write_all(&line[..]).map_err(|x| x.to_string())?;
+            w.write(&[b'\n']).map_err(|x| x.to_string())?;
+        }
+        Ok(lines.len())
+    }
+
     pub fn dump_sexpr<W : Write>(&self, pattern: Expr, template: Expr, w: &mut W) -> Result<usize, String> {
+        self.dump_sexpr_sized::<4096, W>(pattern, template, w)
+    }
+
+    /// `dump_sexpr` instantiating the template in an `N`-byte buffer, for outputs that encode to more than the
+    /// 4096 bytes `dump_sexpr` sets aside
+    pub fn dump_sexpr_sized<const N: usize, W : Write>(&self, pattern: Expr, template: Expr, w: &mut W) -> Result<usize, String> {
         let constant_template_prefix = unsafe { template.prefix().unwrap_or_else(|_| template.span()).as_ref().unwrap() };
 
-        let mut buffer = [0u8; 4096];
+        let mut buffer = [0u8; N];
 
         Self::query_multi(&self.btm, &[pattern], |refs_bindings, loc| {
             let mut oz = ExprZipper::new(Expr { ptr: buffer.as_mut_ptr() });
@@ -1055,23 +2591,124 @@ impl Space {
             }
 
             // &buffer[constant_template_prefix.len()..oz.loc]
-            Expr{ ptr: buffer.as_ptr().cast_mut() }.serialize(w, |s| {
-                #[cfg(feature="interning")]
-                {
-                    let symbol = i64::from_be_bytes(s.try_into().unwrap()).to_be_bytes();
-                    let mstr = self.sm.get_bytes(symbol).map(unsafe { |x| std::str::from_utf8_unchecked(x) });
-                    // println!("symbol {symbol:?}, bytes {mstr:?}");
-                    unsafe { std::mem::transmute(mstr.expect(format!("failed to look up {:?}", symbol).as_str())) }
-                }
-                #[cfg(not(feature="interning"))]
-                unsafe { std::mem::transmute(std::str::from_utf8(s).unwrap()) }
-            });
+            serialize_fact(&self.sm, Expr{ ptr: buffer.as_ptr().cast_mut() }, w);
             w.write(&[b'\n']).map_err(|x| x.to_string())?;
 
             Ok(())
         })
     }
 
+    /// `dump_sexpr` into a string; symbols are arbitrary bytes, so this fails if the rendered facts are not UTF-8
+    pub fn dump_as_string(&self, pattern: Expr, template: Expr) -> Result<String, std::string::FromUtf8Error> {
+        let mut out = vec![];
+        self.dump_sexpr(pattern, template, &mut out).expect("writing to a Vec cannot fail");
+        String::from_utf8(out)
+    }
+
+    /// `transform` for read-only derivations: writes the instantiated `template` of every match of `pattern` to
+    /// `w`, one S-expression per line, instead of inserting it into the space; returns the number of matches
+    pub fn transform_to_writer<W : Write>(&self, pattern: Expr, template: Expr, w: &mut W) -> Result<usize, String> {
+        self.dump_sexpr(pattern, template, w)
+    }
+
+    /// Like `dump_sexpr`, but writes a uniform sample of at most `n` of the matches (reservoir sampling), so only
+    /// `n` instantiated templates are held in memory. The same `seed` over the same space picks the same sample.
+    pub fn sample_dump<W : Write>(&self, pattern: Expr, template: Expr, n: usize, seed: u64, w: &mut W) -> Result<usize, String> {
+        // splitmix64, good enough to pick reservoir slots and stable across platforms
+        let mut state = seed;
+        let mut next_random = move || {
+            state = state.wrapping_add(0x9e3779b97f4a7c15);
+            let mut z = state;
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+            z ^ (z >> 31)
+        };
+
+        let mut buffer = [0u8; 4096];
+        let mut reservoir: Vec<Vec<u8>> = Vec::with_capacity(n);
+        let mut seen = 0u64;
+        Self::query_multi(&self.btm, &[pattern], |refs_bindings, _loc| {
+            seen += 1;
+            let slot = if reservoir.len() < n { reservoir.len() } else { (next_random() % seen) as usize };
+            if slot >= n { return Ok(()) }
+
+            let mut oz = ExprZipper::new(Expr { ptr: buffer.as_mut_ptr() });
+            match refs_bindings {
+                Ok(refs) => {
+                    template.substitute(&refs.iter().map(|ee| ee.subsexpr()).collect::<Vec<_>>()[..], &mut oz);
+                }
+                Err((ref bindings, ti, ni, _)) => {
+                    mork_bytestring::apply(0, ni as u8, ti as u8, &mut ExprZipper::new(template), bindings, &mut oz, &mut BTreeMap::new(), &mut vec![], &mut vec![]);
+                }
+            }
+            let sampled = buffer[..oz.loc].to_vec();
+            if slot == reservoir.len() { reservoir.push(sampled) } else { reservoir[slot] = sampled }
+            Ok::<(), ()>(())
+        }).unwrap();
+
+        for sampled in reservoir.iter_mut() {
+            serialize_fact(&self.sm, Expr{ ptr: sampled.as_mut_ptr() }, w);
+            w.write(&[b'\n']).map_err(|x| x.to_string())?;
+        }
+        Ok(reservoir.len())
+    }
+
+    /// The inverse of [`Space::load_csv`]: writes one row per match whose instantiated `template` is a flat tuple
+    /// of symbols (the arity gives the column count), quoting fields that contain the delimiter, quotes or newlines.
+    /// Matches of any other shape are skipped; returns the number of rows written.
+    pub fn dump_as_csv<W : Write>(&self, w: &mut W, pattern: Expr, template: Expr, delimiter: u8) -> Result<usize, String> {
+        let mut buffer = [0u8; 4096];
+        let mut rows = 0usize;
+
+        Self::query_multi(&self.btm, &[pattern], |refs_bindings, loc| {
+            let mut oz = ExprZipper::new(Expr { ptr: buffer.as_mut_ptr() });
+
+            match refs_bindings {
+                Ok(refs) => {
+                    template.substitute(&refs.iter().map(|ee| ee.subsexpr()).collect::<Vec<_>>()[..], &mut oz);
+                }
+                Err((ref bindings, ti, ni, _)) => {
+                    mork_bytestring::apply(0, ni as u8, ti as u8, &mut ExprZipper::new(template), bindings, &mut oz, &mut BTreeMap::new(), &mut vec![], &mut vec![]);
+                }
+            }
+
+            let data = &buffer[..oz.loc];
+            let Tag::Arity(columns) = byte_item(data[0]) else { return Ok(()) };
+            let mut fields = Vec::with_capacity(columns as usize);
+            let mut i = 1;
+            while i < data.len() {
+                let Tag::SymbolSize(n) = byte_item(data[i]) else { return Ok(()) };
+                let symbol = &data[i + 1..i + 1 + n as usize];
+                #[cfg(feature="interning")]
+                fields.push(symbol_bytes(&self.sm, symbol));
+                #[cfg(not(feature="interning"))]
+                fields.push(symbol);
+                i += 1 + n as usize;
+            }
+
+            let mut row = Vec::<u8>::new();
+            for (k, field) in fields.into_iter().enumerate() {
+                if k > 0 { row.push(delimiter) }
+                if field.iter().any(|&b| b == delimiter || b == b'"' || b == b'\n' || b == b'\r') {
+                    row.push(b'"');
+                    for &b in field {
+                        if b == b'"' { row.push(b'"') }
+                        row.push(b);
+                    }
+                    row.push(b'"');
+                } else {
+                    row.extend_from_slice(field);
+                }
+            }
+            row.push(b'\n');
+            w.write_all(&row[..]).map_err(|x| x.to_string())?;
+            rows += 1;
+
+            Ok(())
+        })?;
+        Ok(rows)
+    }
+
     pub fn backup_symbols<out_dir_path : AsRef<std::path::Path>>(&self, path: out_dir_path) -> Result<(), std::io::Error>  {
         #[cfg(feature="interning")]
         {
@@ -1091,6 +2728,54 @@ impl Space {
         Ok(())
     }
 
+    /// How many symbols this space has interned and roughly how much memory they take; always empty when symbols
+    /// are stored inline (without the `interning` feature)
+    pub fn symbol_table_stats(&self) -> SymbolTableStats {
+        #[allow(unused_mut)]
+        let mut stats = SymbolTableStats::default();
+        #[cfg(feature="interning")]
+        self.sm.for_each_symbol(|_, bytes| {
+            stats.count += 1;
+            stats.bytes += SYM_LEN + bytes.len();
+        });
+        stats
+    }
+
+    /// Writes every interned symbol with its bytes to `w`, as a big-endian `u64` count followed by
+    /// `(id, u64 length, bytes)` entries, so the table can be shipped independently of the trie
+    pub fn dump_symbol_table<W: Write>(&self, w: &mut W) -> Result<(), std::io::Error> {
+        #[allow(unused_mut)]
+        let mut entries: Vec<([u8; SYM_LEN], Vec<u8>)> = vec![];
+        #[cfg(feature="interning")]
+        self.sm.for_each_symbol(|sym, bytes| entries.push((sym, bytes.to_vec())));
+        w.write_all(&(entries.len() as u64).to_be_bytes())?;
+        for (sym, bytes) in entries.iter() {
+            w.write_all(sym)?;
+            w.write_all(&(bytes.len() as u64).to_be_bytes())?;
+            w.write_all(&bytes[..])?;
+        }
+        Ok(())
+    }
+
+    /// Interns every symbol of a table written by [`Space::dump_symbol_table`] into this space's
+    /// symbol table, and returns how the ids in the dump map onto the ids in this space
+    pub fn load_symbol_table<R: Read>(&self, r: &mut R) -> Result<SymbolRemap, std::io::Error> {
+        let mut word = [0u8; 8];
+        r.read_exact(&mut word)?;
+        let count = u64::from_be_bytes(word);
+        let mut remap = SymbolRemap::new();
+        for _ in 0..count {
+            let mut sym = [0u8; SYM_LEN];
+            r.read_exact(&mut sym)?;
+            r.read_exact(&mut word)?;
+            let len = u64::from_be_bytes(word) as usize;
+            let mut bytes = vec![0u8; len];
+            r.read_exact(&mut bytes)?;
+            remap.insert(sym.to_vec(), self.intern_symbol(&bytes));
+        }
+        Ok(remap)
+    }
+
     pub fn backup<OutDirPath : AsRef<std::path::Path>>(&self, path: OutDirPath) -> Result<(), std::io::Error> {
         crate::stubs::pathmap::serialization::write_trie("neo4j triples", self.btm.read_zipper(),
                                            |v, b| crate::stubs::pathmap::serialization::ValueSlice::Read(&[]),
@@ -1116,28 +2801,103 @@ impl Space {
         Ok(())
     }
 
+    /// Streams the paths under `pattern`'s constant prefix to `w` in the compact path format.
+    /// Paths are written in full (prefix included), so `deserialize_paths` puts them back where they came from.
+    pub fn serialize_paths<W : Write>(&self, pattern: Expr, w: &mut W) -> Result<crate::stubs::pathmap::path_serialization::SerializationStats, std::io::Error> {
+        let prefix = unsafe { pattern.prefix().unwrap_or_else(|_| pattern.span()).as_ref().unwrap() };
+        let rz = self.btm.read_zipper_at_path(prefix);
+        let mut matched = BytesTrieMap::new();
+        if rz.path_exists() {
+            let zh = matched.zipper_head();
+            zh.write_zipper_at_exclusive_path(prefix).unwrap().graft(&rz);
+        }
+        crate::stubs::pathmap::path_serialization::serialize_paths_(matched.read_zipper(), w)
+    }
+
+    /// Inserts every path read from `r` (as written by `serialize_paths` or `backup_paths`) into the space
+    pub fn deserialize_paths<R : Read>(&mut self, r: &mut R) -> Result<crate::stubs::pathmap::path_serialization::DeserializationStats, std::io::Error> {
+        crate::stubs::pathmap::path_serialization::deserialize_paths_(self.btm.write_zipper(), r, ())
+    }
+
     pub fn backup_paths<OutDirPath: AsRef<std::path::Path>>(&self, path: OutDirPath) -> Result<crate::stubs::pathmap::path_serialization::SerializationStats, std::io::Error> {
         let mut file = File::create(path).unwrap();
         crate::stubs::pathmap::path_serialization::serialize_paths_(self.btm.read_zipper(), &mut file)
     }
 
+    /// `backup_paths` with control over durability: by default the snapshot is written to a temporary file,
+    /// fsynced and renamed over `path`, so an existing snapshot is only ever replaced by a complete one
+    pub fn snapshot<OutDirPath: AsRef<std::path::Path>>(&self, path: OutDirPath, options: &SnapshotOptions) -> Result<crate::stubs::pathmap::path_serialization::SerializationStats, std::io::Error> {
+        write_snapshot_file(path.as_ref(), options, |file| crate::stubs::pathmap::path_serialization::serialize_paths_(self.btm.read_zipper(), file))
+    }
+
+    /// `snapshot` preceded by a [`SnapshotHeader`] carrying `metadata` and followed by the symbol table, so tools can
+    /// inspect the file through [`read_snapshot_header`] without loading it
+    pub fn snapshot_with_metadata<OutDirPath: AsRef<std::path::Path>>(&self, path: OutDirPath, options: &SnapshotOptions, metadata: BTreeMap<String, String>) -> Result<SnapshotHeader, std::io::Error> {
+        use std::io::{Seek, SeekFrom};
+        let mut header = SnapshotHeader { version: SNAPSHOT_VERSION, symbol_table_offset: 0, metadata };
+        write_snapshot_file(path.as_ref(), options, |file| {
+            header.write_to(file)?;
+            crate::stubs::pathmap::path_serialization::serialize_paths_(self.btm.read_zipper(), file)?;
+            header.symbol_table_offset = file.stream_position()?;
+            self.dump_symbol_table(file)?;
+            file.seek(SeekFrom::Start(SNAPSHOT_MAGIC.len() as u64 + 4))?;
+            file.write_all(&header.symbol_table_offset.to_be_bytes())?;
+            Ok(())
+        })?;
+        Ok(header)
+    }
+
     pub fn restore_paths<OutDirPath : AsRef<std::path::Path>>(&mut self, path: OutDirPath) -> Result<crate::stubs::pathmap::path_serialization::DeserializationStats, std::io::Error> {
         let mut file = File::open(path).unwrap();
         crate::stubs::pathmap::path_serialization::deserialize_paths_(self.btm.write_zipper(), &mut file, ())
     }
 
-    pub fn query_multi<T, F : FnMut(Result<&[ExprEnv], (BTreeMap<(u8, u8), ExprEnv>, u8, u8, Vec<(u8, u8)>)>, Expr) -> Result<(), T>>(btm: &BytesTrieMap<()>, patterns: &[Expr], mut effect: F) -> Result<usize, T> {
-        let first_pattern_prefix = unsafe { patterns[0].prefix().unwrap_or_else(|x| patterns[0].span()).as_ref().unwrap() };
-        let mut rz = btm.read_zipper_at_path(first_pattern_prefix);
-        if !rz.path_exists() { return Ok(0); }
-        let mut first_temp_map = BytesTrieMap::new();
-        let mut first_zh = first_temp_map.zipper_head();
-        let mut virtual_path = vec![item_byte(Tag::Arity(patterns.len() as u8))];
-        let mut pattern_expr = virtual_path.clone();
+    /// Calls `effect` with every fact matching all of `patterns`. Besides `$` and `_n`, a pattern may contain
+    /// `{depth=N}` atoms: each captures like a `$`, but only matches an expression nesting exactly `N` levels deep
+    /// (a symbol is 0, `(a b)` 1, `(a (b c))` 2), and the traversal does not descend below that depth.
+    pub fn query_multi<T, F : FnMut(Result<&[ExprEnv], (BTreeMap<(u8, u8), ExprEnv>, u8, u8, Vec<(u8, u8)>)>, Expr) -> Result<(), T>>(btm: &BytesTrieMap<()>, patterns: &[Expr], effect: F) -> Result<usize, T> {
+        Self::query_multi_with_config(btm, patterns, &MatchConfig::default(), effect)
+    }
+
+    /// Panics if the patterns refer to a variable before introducing it, which only the checked queries report
+    pub fn query_multi_with_config<T, F : FnMut(Result<&[ExprEnv], (BTreeMap<(u8, u8), ExprEnv>, u8, u8, Vec<(u8, u8)>)>, Expr) -> Result<(), T>>(btm: &BytesTrieMap<()>, patterns: &[Expr], config: &MatchConfig, effect: F) -> Result<usize, T> {
+        match Self::query_multi_checked(btm, patterns, config, effect) {
+            (_, Some(Interruption::Pattern(e))) => panic!("invalid query pattern {:?}: {}", patterns, e),
+            (matches, _) => matches,
+        }
+    }
+
+    /// `query_multi_with_config`, also reporting whether the cancellation token or read quota of `config` cut the
+    /// traversal short, or whether the patterns were refused before it started
+    fn query_multi_checked<T, F : FnMut(Result<&[ExprEnv], (BTreeMap<(u8, u8), ExprEnv>, u8, u8, Vec<(u8, u8)>)>, Expr) -> Result<(), T>>(btm: &BytesTrieMap<()>, patterns: &[Expr], config: &MatchConfig, mut effect: F) -> (Result<usize, T>, Option<Interruption>) {
+        if patterns.is_empty() || patterns.iter().any(|p| p.ptr.is_null()) {
+            return (Ok(0), Some(Interruption::InvalidPattern))
+        }
+        // the program is built from the patterns as given, everything else sees the `{depth=N}` tokens as variables
+        let tokenized = patterns;
+        let (mut expanded, depths): (Vec<Vec<u8>>, Vec<usize>) = patterns.iter().map(|p| without_depth_tokens(*p)).unzip();
+        let patterns: Vec<Expr> = expanded.iter_mut().map(|p| Expr{ ptr: p.as_mut_ptr() }).collect();
+        let patterns = &patterns[..];
+        if config.max_depth.is_some_and(|max| patterns.iter().any(|p| expr_depth(*p) > max)) {
+            return (Ok(0), Some(Interruption::TooDeep))
+        }
+        if config.max_references.is_some_and(|max| patterns.iter().map(|p| p.newvars()).sum::<usize>() > max) {
+            return (Ok(0), Some(Interruption::TooManyReferences))
+        }
+        let mut pattern_expr = vec![item_byte(Tag::Arity(patterns.len() as u8))];
         for pattern in patterns.iter() {
             trace!(target: "query_multi", "pattern {:?}", pattern);
             pattern_expr.extend_from_slice(unsafe { pattern.span().as_ref().unwrap() })
         }
+        if let Err(e) = check_references(Expr { ptr: pattern_expr.as_mut_ptr() }) {
+            return (Ok(0), Some(Interruption::Pattern(e)))
+        }
+        let first_pattern_prefix = unsafe { patterns[0].prefix().unwrap_or_else(|x| patterns[0].span()).as_ref().unwrap() };
+        let mut rz = btm.read_zipper_at_path(first_pattern_prefix);
+        if !rz.path_exists() { return (Ok(0), None); }
+        let mut first_temp_map = BytesTrieMap::new();
+        let mut first_zh = first_temp_map.zipper_head();
+        let mut virtual_path = vec![pattern_expr[0]];
         virtual_path.extend_from_slice(first_pattern_prefix);
         first_zh.write_zipper_at_exclusive_path(&virtual_path[..]).unwrap().graft(&rz);
         drop(first_zh);
@@ -1150,7 +2910,7 @@ impl Space {
             let rz = btm.read_zipper_at_path(prefix);
             if !rz.path_exists() {
                 trace!("for p={:?} prefix {} not in map", p, serialize(prefix));
-                return Ok(0)
+                return (Ok(0), None)
             }
             zh.write_zipper_at_exclusive_path(prefix).unwrap().graft(&rz);
             drop(zh);
@@ -1168,15 +2928,17 @@ impl Space {
         let mut stack = vec![0; 1];
         stack[0] = ACTION;
 
-        for pattern in patterns.iter().rev() {
+        for pattern in tokenized.iter().rev() {
             let prefix = unsafe { pattern.prefix().unwrap_or_else(|x| pattern.span()).as_ref().unwrap() };
             stack.extend_from_slice(&referential_bidirectional_matching_stack(&mut ExprZipper::new(*pattern))[..]);
             // stack.extend_from_slice(&referential_bidirectional_matching_stack_traverse(*pattern, prefix.len())[..]);
         }
-        stack.reserve(4096);
+        stack.reserve(4096 + DEPTH_LEVEL_STACK * depths.iter().max().unwrap());
 
         let mut references: Vec<ExprEnv> = vec![];
         let mut candidate = 0;
+        let mut interruption = None;
+        let mut visited = std::collections::HashSet::<Vec<u8>>::new();
         thread_local! {
             static BREAK: std::cell::RefCell<[u64; 64]> = const { std::cell::RefCell::new([0; 64]) };
             static RET: std::cell::Cell<*mut u8> = const { std::cell::Cell::new(null_mut()) };
@@ -1191,6 +2953,19 @@ impl Space {
         BREAK.with_borrow_mut(|a| {
             if unsafe { setjmp(a) == 0 } {
                 referential_transition(stack.last_mut().unwrap(), &mut prz, &mut references, 0, &mut |refs, introduced, loc| {
+                    if config.dedup_matches {
+                        // the origin path is the virtual tuple of matched facts; key on the distinct facts themselves
+                        let mut facts = vec![];
+                        ExprEnv::new(1, Expr { ptr: loc.origin_path().as_ptr().cast_mut() }).args(&mut facts);
+                        let mut key: Vec<&[u8]> = facts.iter().map(|f| unsafe { &*f.subsexpr().span() }).collect();
+                        key.sort_unstable();
+                        key.dedup();
+                        if !visited.insert(key.concat()) { return }
+                    }
+                    if config.cancel.as_ref().is_some_and(|c| c.is_cancelled()) {
+                        unsafe { std::ptr::write_volatile(&mut interruption, Some(Interruption::Cancelled)); }
+                        unsafe { longjmp(a, 1) }
+                    }
                     let e = Expr { ptr: loc.origin_path().as_ptr().cast_mut() };
 
                     if true  { // introduced != 0
@@ -1222,6 +2997,10 @@ impl Space {
                                 };
                                 // println!("pre {:?} {:?} {}", (oi, ni), assignments, assignments.len());
 
+                                if config.read_quota.is_some_and(|q| unsafe { std::ptr::read_volatile(&candidate) } >= q) {
+                                    unsafe { std::ptr::write_volatile(&mut interruption, Some(Interruption::ReadQuotaExceeded)); }
+                                    unsafe { longjmp(a, 1) }
+                                }
                                 match effect(Err((bs, oi, ni, assignments)), e) {
                                     Ok(()) => {}
                                     Err(t) => {
@@ -1239,6 +3018,10 @@ impl Space {
                             }
                         }
                     } else {
+                        if config.read_quota.is_some_and(|q| unsafe { std::ptr::read_volatile(&candidate) } >= q) {
+                            unsafe { std::ptr::write_volatile(&mut interruption, Some(Interruption::ReadQuotaExceeded)); }
+                            unsafe { longjmp(a, 1) }
+                        }
                         match effect(Ok(refs), e) {
                             Ok(()) => {}
                             Err(t) => {
@@ -1253,7 +3036,7 @@ impl Space {
                 })
             }
         });
-        RET.with(|mptr| {
+        let result = RET.with(|mptr| {
             if mptr.get().is_null() { Ok(candidate) }
             else {
                 let tref = unsafe { mptr.get() };
@@ -1261,7 +3044,8 @@ impl Space {
                 unsafe { std::alloc::dealloc(tref, std::alloc::Layout::new::<T>()) };
                 Err(t)
             }
-        })
+        });
+        (result, unsafe { std::ptr::read_volatile(&interruption) })
     }
 
     pub fn prefix_subsumption(prefixes: &[&[u8]]) -> Vec<usize> {
@@ -1290,6 +3074,34 @@ impl Space {
     }
 
     pub fn transform_multi_multi(&mut self, patterns: &[Expr], templates: &[Expr]) -> (usize, bool) {
+        self.transform_multi_multi_with_config(patterns, templates, &MatchConfig::default())
+    }
+
+    /// Panics if the patterns or templates refer to a variable they do not bind, which only the checked
+    /// transforms report
+    pub fn transform_multi_multi_with_config(&mut self, patterns: &[Expr], templates: &[Expr], config: &MatchConfig) -> (usize, bool) {
+        let (touched, result, _) = self.transform_multi_multi_unchecked(patterns, templates, config);
+        (touched, result.written > 0)
+    }
+
+    /// `transform_multi_multi_checked` for callers with no error to report a malformed transform through
+    fn transform_multi_multi_unchecked(&mut self, patterns: &[Expr], templates: &[Expr], config: &MatchConfig) -> (usize, TransformResult, Option<Interruption>) {
+        match self.transform_multi_multi_checked(patterns, templates, config) {
+            (_, _, Some(Interruption::Pattern(e))) => panic!("invalid transform pattern {:?}: {}", patterns, e),
+            (_, _, Some(Interruption::Template(e))) => panic!("invalid transform {:?} -> {:?}: {}", patterns, templates, e),
+            checked => checked,
+        }
+    }
+
+    /// `transform_multi_multi_with_config`, returning the number of matches and what was written, and
+    /// whether `config` cut the transform short or the transform was refused before it started
+    fn transform_multi_multi_checked(&mut self, patterns: &[Expr], templates: &[Expr], config: &MatchConfig) -> (usize, TransformResult, Option<Interruption>) {
+        if patterns.is_empty() || patterns.iter().chain(templates).any(|e| e.ptr.is_null()) {
+            return (0, TransformResult::default(), Some(Interruption::InvalidPattern))
+        }
+        if let Err(e) = check_templates(patterns, templates) {
+            return (0, TransformResult::default(), Some(Interruption::Template(e)))
+        }
         let mut buffer = [0u8; 512];
         let mut template_prefixes = vec![unsafe { MaybeUninit::zeroed().assume_init() }; templates.len()];
         let mut subsumption = Self::prefix_subsumption(&template_prefixes[..]);
@@ -1311,8 +3123,8 @@ impl Space {
         trace!(target: "transform", "prefixes {:?}", template_prefixes);
         trace!(target: "transform", "subsumption {:?}", subsumption);
 
-        let mut any_new = false;
-        let touched = Self::query_multi(&read_copy, patterns, |refs_bindings, loc| {
+        let mut result = TransformResult::default();
+        let (touched, interruption) = Self::query_multi_checked(&read_copy, patterns, config, |refs_bindings, loc| {
             // trace!(target: "transform", "pattern {}", serialize(unsafe { template.span().as_ref().unwrap()}));
             trace!(target: "transform", "data {}", serialize(unsafe { loc.span().as_ref().unwrap()}));
 
@@ -1341,16 +3153,16 @@ impl Space {
                 wz.descend_to(&buffer[template_prefixes[subsumption[i]].len()..oz.loc]);
                 // println!("wz path {} {}", serialize(template_prefixes[subsumption[i]]), serialize(wz.path()));
                 // println!("insert path {}", serialize(&buffer[..oz.loc]));
-                any_new |= wz.set_value(()).is_none();
+                if wz.set_value(()).is_none() { result.written += 1 } else { result.collisions += 1 }
                 wz.reset();
                 // THIS DOES WORK v
                 // any_new |= unsafe { ((&self.btm) as *const BytesTrieMap<()>).cast_mut().as_mut().unwrap() }.insert(&buffer[..oz.loc], ()).is_none();
                 
             }
             Ok::<(), ()>(())
-        }).unwrap();
+        });
         drop(template_prefixes);
-        (touched, any_new)
+        (touched.unwrap(), result, interruption)
     }
 
     pub fn transform_multi_multi_(&mut self, patterns: &[Expr], templates: &[Expr], add: Expr) -> (usize, bool) {
@@ -1420,18 +3232,376 @@ impl Space {
     }
 
 
-    pub fn transform_multi(&mut self, patterns: &[Expr], template: Expr) -> (usize, bool) {
-        self.transform_multi_multi(patterns, &[template])
+    pub fn transform_multi(&mut self, patterns: &[Expr], template: Expr) -> TransformResult {
+        self.transform_multi_multi_unchecked(patterns, &[template], &MatchConfig::default()).1
+    }
+
+    pub fn transform(&mut self, pattern: Expr, template: Expr) -> TransformResult {
+        self.transform_multi(&[pattern], template)
+    }
+
+    /// `transform` that checks the template against the pattern first, failing instead of writing corrupt
+    /// facts when the template refers to a variable the pattern does not bind
+    pub fn try_transform(&mut self, pattern: Expr, template: Expr) -> Result<TransformResult, TransformError> {
+        check_templates(&[pattern], &[template])?;
+        Ok(self.transform(pattern, template))
+    }
+
+    /// Calls `f` with every match of `pattern` until `cancel` is raised; returns the number of matches, or
+    /// `SpaceError::Cancelled` if the traversal was abandoned (and fails like [`Space::query_with_config`] otherwise)
+    pub fn query_cancellable<F : FnMut(Expr) -> ()>(&self, pattern: Expr, cancel: &CancellationToken, f: F) -> Result<usize, SpaceError> {
+        self.query_with_config(pattern, &MatchConfig { cancel: Some(cancel.clone()), ..MatchConfig::default() }, f)
+    }
+
+    /// Calls `f` with the matches of `pattern`, failing with `ReadQuotaExceeded` as soon as a match beyond the
+    /// first `quota` is found; returns the number of matches otherwise
+    pub fn query_with_quota<F : FnMut(Expr) -> ()>(&self, pattern: Expr, quota: usize, f: F) -> Result<usize, SpaceError> {
+        self.query_with_config(pattern, &MatchConfig { read_quota: Some(quota), ..MatchConfig::default() }, f)
+    }
+
+    /// Calls `f` with the matches of `pattern` under `config`, failing if one of its limits is hit or it is
+    /// cancelled; returns the number of matches otherwise
+    pub fn query_with_config<F : FnMut(Expr) -> ()>(&self, pattern: Expr, config: &MatchConfig, mut f: F) -> Result<usize, SpaceError> {
+        match Self::query_multi_checked(&self.btm, &[pattern], config, |_, loc| { f(loc); Ok::<(), ()>(()) }) {
+            (matches, None) => Ok(matches.unwrap()),
+            (_, Some(interruption)) => Err(config.error(interruption)),
+        }
+    }
+
+    /// Collects the matches of `pattern` under `config`; past `config.max_results` matches the traversal stops and
+    /// the results are marked truncated instead of growing further. Fails like [`Space::query_with_config`].
+    pub fn query_collect(&self, pattern: Expr, config: &MatchConfig) -> Result<QueryResults, SpaceError> {
+        let mut collected = QueryResults::default();
+        let (_, interruption) = Self::query_multi_checked(&self.btm, &[pattern], config, |_, loc| {
+            if config.max_results.is_some_and(|max| collected.results.len() >= max) {
+                collected.truncated = true;
+                return Err(())
+            }
+            collected.results.push(OwnedExpr::from(unsafe { loc.span().as_ref().unwrap() }.to_vec()));
+            Ok(())
+        });
+        match interruption {
+            None => Ok(collected),
+            Some(interruption) => Err(config.error(interruption)),
+        }
     }
 
-    pub fn transform(&mut self, pattern: Expr, template: Expr) -> (usize, bool) {
-        self.transform_multi_multi(&[pattern], &[template])
+    /// Calls `f` with every fact under the constant `root` whose remainder matches `pattern`, i.e. the matches of
+    /// `root` followed by `pattern`; variables are numbered from the start of `pattern`, so `root` must not introduce
+    /// any. Returns the number of matches.
+    pub fn query_under<F : FnMut(Expr) -> ()>(&self, root: &Prefix, pattern: Expr, mut f: F) -> usize {
+        let mut anchored = root.path().to_vec();
+        anchored.extend_from_slice(unsafe { pattern.span().as_ref().unwrap() });
+        Self::query_multi(&self.btm, &[Expr{ ptr: anchored.as_mut_ptr() }], |_, loc| { f(loc); Ok::<(), ()>(()) }).unwrap()
+    }
+
+    /// The bytes that can follow `prefix` in the trie, in ascending order: the next step a browser can take from
+    /// there without listing the facts below. Empty when nothing is stored under `prefix`.
+    pub fn children_at(&self, prefix: &Prefix) -> Vec<u8> {
+        self.btm.read_zipper_at_path(prefix.path()).child_mask().iter().collect()
+    }
+
+    /// A consistent view for a sequence of queries: the trie is cloned by sharing its nodes, so this is cheap, and
+    /// later writes to the space copy the nodes they change instead of altering the snapshot's
+    pub fn read_snapshot(&self) -> ReadSnapshot {
+        ReadSnapshot { space: Space { btm: self.btm.clone(), sm: self.sm.clone(), subscriptions: Default::default() } }
+    }
+
+    /// Runs the query for `pattern` on a worker thread, sending every matched fact through the returned channel as
+    /// it is found. At most `bound` matches wait in the channel, so the traversal keeps pace with the consumer; dropping
+    /// the receiver stops it. The worker queries a snapshot of the trie taken by this call.
+    pub fn query_channel(&self, pattern: Expr, bound: usize) -> std::sync::mpsc::Receiver<OwnedExpr> {
+        let (tx, rx) = std::sync::mpsc::sync_channel(bound);
+        let btm = self.btm.clone();
+        let mut pattern = unsafe { pattern.span().as_ref().unwrap() }.to_vec();
+        std::thread::spawn(move || {
+            let _ = Self::query_multi(&btm, &[Expr{ ptr: pattern.as_mut_ptr() }], |_, loc| {
+                tx.send(OwnedExpr::from(unsafe { loc.span().as_ref().unwrap() }.to_vec())).map_err(|_| ())
+            });
+        });
+        rx
+    }
+
+    /// `transform` under `config`, failing if one of its limits is hit or it is cancelled;
+    /// facts written before that stay in the space
+    pub fn transform_with_config(&mut self, pattern: Expr, template: Expr, config: &MatchConfig) -> Result<TransformResult, SpaceError> {
+        match self.transform_multi_multi_checked(&[pattern], &[template], config) {
+            (_, result, None) => Ok(result),
+            (_, _, Some(interruption)) => Err(config.error(interruption)),
+        }
+    }
+
+    /// `transform` that stops deriving facts once `cancel` is raised, failing with `SpaceError::Cancelled`
+    /// (and like [`Space::transform_with_config`] otherwise); facts already written stay in the space
+    pub fn transform_cancellable(&mut self, pattern: Expr, template: Expr, cancel: &CancellationToken) -> Result<(usize, bool), SpaceError> {
+        let config = MatchConfig { cancel: Some(cancel.clone()), ..MatchConfig::default() };
+        match self.transform_multi_multi_checked(&[pattern], &[template], &config) {
+            (touched, result, None) => Ok((touched, result.written > 0)),
+            (_, _, Some(interruption)) => Err(config.error(interruption)),
+        }
+    }
+
+    /// The number of branches of the trie that lead to no fact (left behind by removals); each is counted once at
+    /// its top. Visits every node and counts the values below it, so it is meant for maintenance, not hot paths.
+    pub fn dangling_paths(&self) -> usize {
+        let mut rz = self.btm.read_zipper();
+        let mut dangling = 0;
+        // the child bytes still to visit at each level of the current path
+        let mut stack: Vec<Vec<u8>> = vec![rz.child_mask().iter().collect()];
+        while let Some(children) = stack.last_mut() {
+            match children.pop() {
+                None => {
+                    stack.pop();
+                    if !stack.is_empty() { rz.ascend(1); }
+                }
+                Some(b) => {
+                    rz.descend_to([b]);
+                    if rz.val_count() == 0 {
+                        dangling += 1;
+                        rz.ascend(1);
+                    } else {
+                        stack.push(rz.child_mask().iter().collect());
+                    }
+                }
+            }
+        }
+        dangling
+    }
+
+    /// Rebuilds the trie from its facts if it holds branches that lead to no fact, reclaiming their nodes
+    pub fn compact(&mut self) -> CompactStats {
+        let reclaimed = self.dangling_paths();
+        let facts = self.btm.val_count();
+        if reclaimed > 0 {
+            let mut compacted = BytesTrieMap::new();
+            let mut rz = self.btm.read_zipper();
+            while rz.to_next_val() {
+                compacted.insert(rz.path(), ());
+            }
+            drop(rz);
+            self.btm = compacted;
+        }
+        CompactStats { reclaimed, facts }
+    }
+
+    /// How many expressions there are of each top-level arity (symbols and variables at the root are not counted)
+    pub fn arity_histogram(&self) -> BTreeMap<u8, usize> {
+        let mut rz = self.btm.read_zipper();
+        let mut histogram = BTreeMap::new();
+        let m = rz.child_mask().and(&ByteMask(ARITIES));
+        let mut it = m.iter();
+        while let Some(b) = it.next() {
+            if let Tag::Arity(a) = byte_item(b) {
+                if rz.descend_to([b]) {
+                    histogram.insert(a, rz.val_count());
+                }
+                rz.ascend(1);
+            }
+        }
+        histogram
+    }
+
+    /// Calls `f` with at most `limit` matches of `pattern`, abandoning the traversal as soon as the cap is reached;
+    /// returns the number of matches delivered
+    pub fn query_limited<F : FnMut(Expr) -> ()>(&self, pattern: Expr, limit: usize, mut f: F) -> usize {
+        if limit == 0 { return 0 }
+        let mut delivered = 0usize;
+        let _ = Self::query_multi(&self.btm, &[pattern], |_, loc| {
+            f(loc);
+            delivered += 1;
+            if delivered == limit { Err(()) } else { Ok(()) }
+        });
+        delivered
+    }
+
+    /// Like [`Space::transform`], but for every derived fact also writes `provenance_template` with its first
+    /// variable replaced by the derived fact and its second by the source fact it was derived from, e.g.
+    /// `[3] derived_from $ $`; `_1` and `_2` after those repeat the derived and source fact.
+    ///
+    /// Panics if either template refers to a variable that is not bound, or `provenance_template` introduces
+    /// more than the two variables.
+    pub fn transform_with_provenance(&mut self, pattern: Expr, template: Expr, provenance_template: Expr) -> (usize, bool) {
+        if let Err(e) = check_templates(&[pattern], &[template]) { panic!("invalid transform {:?} -> {:?}: {}", pattern, template, e) }
+        if let Err(e) = check_templates(&[], &[provenance_template]) { panic!("invalid provenance template {:?}: {}", provenance_template, e) }
+        assert!(provenance_template.newvars() <= 2, "provenance template {:?} introduces more than the derived and source fact", provenance_template);
+        let pattern_len = unsafe { pattern.span().as_ref().unwrap() }.len();
+        let mut buffer = vec![];
+        let mut provenance_buffer = vec![];
+        let read_copy = self.btm.clone();
+        let mut wz = self.write_zipper_unchecked();
+
+        let mut any_new = false;
+        let touched = Self::query_multi(&read_copy, &[pattern], |refs_bindings, loc| {
+            // variables are bound to parts of the source fact or of the pattern
+            let source_len = unsafe { loc.span().as_ref().unwrap() }.len();
+            buffer.resize(buffer.len().max(instance_len(template, source_len.max(pattern_len))), 0);
+            let mut oz = ExprZipper::new(Expr { ptr: buffer.as_mut_ptr() });
+            match refs_bindings {
+                Ok(refs) => {
+                    template.substitute(&refs.iter().map(|ee| ee.subsexpr()).collect::<Vec<_>>()[..], &mut oz);
+                }
+                Err((ref bindings, ti, ni, _)) => {
+                    mork_bytestring::apply(1, ni as u8, ti as u8, &mut ExprZipper::new(template), bindings, &mut oz, &mut BTreeMap::new(), &mut vec![], &mut vec![]);
+                }
+            }
+            wz.descend_to(&buffer[..oz.loc]);
+            any_new |= wz.set_value(()).is_none();
+            wz.reset();
+
+            provenance_buffer.resize(provenance_buffer.len().max(instance_len(provenance_template, source_len.max(oz.loc))), 0);
+            let mut pz = ExprZipper::new(Expr { ptr: provenance_buffer.as_mut_ptr() });
+            provenance_template.substitute(&[Expr { ptr: buffer.as_mut_ptr() }, loc], &mut pz);
+            wz.descend_to(&provenance_buffer[..pz.loc]);
+            wz.set_value(());
+            wz.reset();
+            Ok::<(), ()>(())
+        }).unwrap();
+        (touched, any_new)
     }
 
     pub fn query<F : FnMut(&[ExprEnv], Expr) -> ()>(&mut self, pattern: Expr, mut effect: F) {
         Self::query_multi(&self.btm, &[pattern], |refs, e| { effect(refs.unwrap(), e); Ok::<(), ()>(()) } ).unwrap();
     }
 
+    /// Streams every match of `pattern` to `visit` as the captured references and the absolute path of
+    /// the matched expression, skipping unification and templates; returns the number of matches.
+    ///
+    /// Both slices are borrowed from the traversal's buffers and are only valid for the duration of the
+    /// call, so copy them out (e.g. `path.to_vec()`) to retain them.
+    ///
+    /// Panics if the pattern refers to a variable before introducing it, see [`Space::compile_query`].
+    pub fn traverse_pattern<F : FnMut(&[Expr], &[u8]) -> ()>(&self, pattern: Expr, visit: F) -> usize {
+        let compiled = self.compile_query(pattern).unwrap_or_else(|e| panic!("invalid pattern {:?}: {}", pattern, e));
+        self.traverse_stack(&compiled, visit)
+    }
+
+    /// The facts matching `pattern`, sorted by what its `var_slot`-th (0-based) `$` captured; facts with equal
+    /// captures keep their traversal order.
+    ///
+    /// Panics if the pattern refers to a variable before introducing it, see [`Space::compile_query`].
+    pub fn query_ordered_by(&self, pattern: Expr, var_slot: u8, order: CaptureOrder) -> Vec<OwnedExpr> {
+        let mut matches: Vec<(Vec<u8>, Vec<u8>)> = vec![];
+        self.traverse_pattern(pattern, |refs, path| {
+            let key = refs.get(var_slot as usize).map(|e| unsafe { e.span().as_ref().unwrap() }.to_vec()).unwrap_or_default();
+            matches.push((key, path.to_vec()));
+        });
+        match order {
+            CaptureOrder::Bytes => matches.sort_by(|(a, _), (b, _)| a.cmp(b)),
+            CaptureOrder::Numeric => matches.sort_by(|(a, _), (b, _)| {
+                match (self.numeric_value(a), self.numeric_value(b)) {
+                    (Some(x), Some(y)) => x.total_cmp(&y),
+                    (Some(_), None) => std::cmp::Ordering::Less,
+                    (None, Some(_)) => std::cmp::Ordering::Greater,
+                    (None, None) => a.cmp(b),
+                }
+            }),
+        }
+        matches.into_iter().map(|(_, path)| OwnedExpr::from(path)).collect()
+    }
+
+    /// Indexes the facts matching `pattern` by what its `key_slot`-th (0-based) `$` captured, so joins on that field
+    /// can look facts up instead of matching again. The index is a copy: later changes to the space don't show in it.
+    ///
+    /// Panics if the pattern refers to a variable before introducing it, see [`Space::compile_query`].
+    pub fn build_index(&self, pattern: Expr, key_slot: u8) -> SpaceIndex {
+        let mut grouped: BTreeMap<Vec<u8>, Vec<Vec<u8>>> = BTreeMap::new();
+        self.traverse_pattern(pattern, |refs, path| {
+            if let Some(key) = refs.get(key_slot as usize) {
+                grouped.entry(unsafe { key.span().as_ref().unwrap() }.to_vec()).or_default().push(path.to_vec());
+            }
+        });
+        let mut entries = BytesTrieMap::new();
+        let keys = grouped.len();
+        for (key, facts) in grouped {
+            entries.insert(&key[..], facts);
+        }
+        SpaceIndex { entries, keys }
+    }
+
+    /// The number a captured symbol spells, if it is a symbol and reads as one
+    fn numeric_value(&self, captured: &[u8]) -> Option<f64> {
+        let Tag::SymbolSize(n) = byte_item(*captured.first()?) else { return None };
+        let bytes = &captured[1..1 + n as usize];
+        #[cfg(feature="interning")]
+        let bytes = symbol_bytes(&self.sm, bytes);
+        std::str::from_utf8(bytes).ok()?.parse().ok()
+    }
+
+    /// Builds the matching program for `pattern` once, so [`Space::run_query`] can reuse it across calls and spaces.
+    /// Fails if a `_n` in the pattern comes before the `$` it refers to.
+    pub fn compile_query(&self, pattern: Expr) -> Result<CompiledQuery, PatternError> {
+        let (mut expanded, deepest) = without_depth_tokens(pattern);
+        check_references(Expr{ ptr: expanded.as_mut_ptr() })?;
+        let mut stack = vec![ACTION];
+        stack.extend_from_slice(&referential_bidirectional_matching_stack(&mut ExprZipper::new(pattern))[..]);
+        Ok(CompiledQuery { stack, headroom: DEPTH_LEVEL_STACK * deepest })
+    }
+
+    /// Runs a compiled query, calling `effect` with the captured references and the matched expression;
+    /// returns the number of matches
+    pub fn run_query<F : FnMut(&[Expr], Expr) -> ()>(&self, compiled: &CompiledQuery, mut effect: F) -> usize {
+        self.traverse_stack(compiled, |refs, path| effect(refs, Expr{ ptr: path.as_ptr().cast_mut() }))
+    }
+
+    fn traverse_stack<F : FnMut(&[Expr], &[u8]) -> ()>(&self, compiled: &CompiledQuery, mut visit: F) -> usize {
+        let mut rz = self.btm.read_zipper();
+        // the transition rewrites the stack as it goes, so every run works on its own copy
+        let mut stack = Vec::with_capacity(compiled.stack.len() + 4096 + compiled.headroom);
+        stack.extend_from_slice(&compiled.stack[..]);
+
+        let mut references: Vec<ExprEnv> = vec![];
+        let mut captured: Vec<Expr> = vec![];
+        let mut matches = 0;
+        referential_transition(stack.last_mut().unwrap(), &mut rz, &mut references, 0, &mut |refs, _introduced, loc| {
+            captured.clear();
+            captured.extend(refs.iter().map(|ee| ee.subsexpr()));
+            visit(&captured[..], loc.origin_path());
+            matches += 1;
+        });
+        matches
+    }
+
+    /// Like `query`, but hands the effect a map from capture name to the bound subexpression
+    pub fn query_named<F : FnMut(&BTreeMap<&str, Expr>) -> ()>(&mut self, pattern: &NamedPattern, mut effect: F) {
+        let mut captures = BTreeMap::new();
+        self.query(pattern.expr, |refs, _e| {
+            captures.clear();
+            for (name, ee) in pattern.names.iter().zip(refs.iter()) {
+                if !name.is_empty() { captures.insert(*name, ee.subsexpr()); }
+            }
+            effect(&captures);
+        });
+    }
+
+    /// Joins the matches of `pattern_a` in `a` with those of `pattern_b` in `b` on the captures both patterns name
+    /// alike, returning every agreeing pair of facts, each in its own space's encoding. The spaces intern symbols
+    /// independently, so captures are compared by their S-expression text; with no shared names every pair joins.
+    pub fn join_across(a: &Self, b: &Self, pattern_a: &NamedPattern, pattern_b: &NamedPattern) -> Vec<(OwnedExpr, OwnedExpr)> {
+        let shared: Vec<&str> = pattern_a.names.iter().filter(|name| !name.is_empty() && pattern_b.names.contains(name)).copied().collect();
+        let keyed = |space: &Self, pattern: &NamedPattern| {
+            let mut facts = vec![];
+            Self::query_multi(&space.btm, &[pattern.expr], |refs, loc| {
+                let refs = refs.unwrap();
+                let key = shared.iter().map(|name| {
+                    let mut text = vec![];
+                    serialize_fact(&space.sm, refs[pattern.names.iter().position(|n| n == name).unwrap()].subsexpr(), &mut text);
+                    text
+                }).collect::<Vec<_>>();
+                facts.push((key, OwnedExpr::from(unsafe { loc.span().as_ref().unwrap() }.to_vec())));
+                Ok::<(), ()>(())
+            }).unwrap();
+            facts
+        };
+
+        let mut b_by_key: BTreeMap<Vec<Vec<u8>>, Vec<OwnedExpr>> = BTreeMap::new();
+        for (key, fact) in keyed(b, pattern_b) { b_by_key.entry(key).or_default().push(fact) }
+        let mut joined = vec![];
+        for (key, fact) in keyed(a, pattern_a) {
+            for other in b_by_key.get(&key).into_iter().flatten() { joined.push((fact.clone(), other.clone())) }
+        }
+        joined
+    }
+
     // (exec <loc> (, <src1> <src2> <srcn>)
     //             (, <dst1> <dst2> <dstm>))
     pub fn interpret(&mut self, rt: Expr) {
@@ -1495,7 +3665,7 @@ impl Space {
         assert!(rtz.next_child());
         let mut res = rtz.subexpr();
 
-        self.transform_multi(&dsts[..], res).1
+        self.transform_multi(&dsts[..], res).written > 0
     }
 
     pub fn datalog(&mut self, statements: &[Expr]) {
@@ -1530,13 +3700,19 @@ impl Space {
     //     }
     // }
 
-    pub fn metta_calculus(&mut self, mut steps: usize) {
+    pub fn metta_calculus(&mut self, steps: usize) {
+        let _ = self.metta_calculus_cancellable(steps, &CancellationToken::new());
+    }
+
+    /// `metta_calculus` that checks `cancel` before each step, returning the number of steps taken
+    pub fn metta_calculus_cancellable(&mut self, mut steps: usize, cancel: &CancellationToken) -> Result<usize, Cancelled> {
         // MC CMD "TEXEC THREAD0"
         let mut done = 0;
         let prefix_e = expr!(self, "[4] exec $ $ $");
         let prefix = unsafe { prefix_e.prefix().unwrap().as_ref().unwrap() };
 
         while {
+            if cancel.is_cancelled() { return Err(Cancelled) }
             let mut rz = self.btm.read_zipper_at_borrowed_path(prefix);
             if rz.to_next_val() {
                 // cannot be here `rz` conflicts potentially with zippers(rz.path())
@@ -1550,6 +3726,7 @@ impl Space {
                 false
             }
         } { done += 1 }
+        Ok(done)
     }
 
     // pub fn prefix_forks(&self, e: Expr) -> (Vec<u8>, Vec<Expr>) {
@@ -1625,3 +3802,70 @@ impl Space {
         process::exit(0);
     }
 }
+
+/// One step of a [`Pipeline`]
+struct PipelineStage {
+    pattern: Expr,
+    template: Expr,
+    guard: Option<Box<dyn Fn(Expr) -> bool>>,
+}
+
+/// A sequence of transforms run one after the other over a space, each stage matching against the facts the
+/// previous stages wrote:
+/// `Pipeline::new().transform(p1, t1).transform_where(p2, t2, guard).run(&mut space)`
+#[derive(Default)]
+pub struct Pipeline {
+    stages: Vec<PipelineStage>,
+}
+
+impl Pipeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a stage writing `template` for every match of `pattern`
+    pub fn transform(mut self, pattern: Expr, template: Expr) -> Self {
+        self.stages.push(PipelineStage { pattern, template, guard: None });
+        self
+    }
+
+    /// Adds a stage like `transform` that only writes the derived facts `guard` accepts
+    pub fn transform_where<G : Fn(Expr) -> bool + 'static>(mut self, pattern: Expr, template: Expr, guard: G) -> Self {
+        self.stages.push(PipelineStage { pattern, template, guard: Some(Box::new(guard)) });
+        self
+    }
+
+    /// Checks every stage's template, then runs the stages in order; returns what each stage wrote.
+    /// Nothing is written if a template refers to a variable its pattern does not bind.
+    pub fn run(&self, space: &mut Space) -> Result<Vec<TransformResult>, TransformError> {
+        for stage in self.stages.iter() {
+            check_templates(&[stage.pattern], &[stage.template])?;
+        }
+        let mut buffer = vec![0u8; 4096];
+        let mut results = Vec::with_capacity(self.stages.len());
+        for stage in self.stages.iter() {
+            let read_copy = space.btm.clone();
+            let mut wz = space.write_zipper_unchecked();
+            let mut result = TransformResult::default();
+            Space::query_multi(&read_copy, &[stage.pattern], |refs_bindings, _loc| {
+                let mut oz = ExprZipper::new(Expr { ptr: buffer.as_mut_ptr() });
+                match refs_bindings {
+                    Ok(refs) => {
+                        stage.template.substitute(&refs.iter().map(|ee| ee.subsexpr()).collect::<Vec<_>>()[..], &mut oz);
+                    }
+                    Err((ref bindings, ti, ni, _)) => {
+                        mork_bytestring::apply(1, ni as u8, ti as u8, &mut ExprZipper::new(stage.template), bindings, &mut oz, &mut BTreeMap::new(), &mut vec![], &mut vec![]);
+                    }
+                }
+                if stage.guard.as_ref().is_some_and(|guard| !guard(Expr { ptr: buffer.as_mut_ptr() })) { return Ok(()) }
+                wz.descend_to(&buffer[..oz.loc]);
+                if wz.set_value(()).is_none() { result.written += 1 } else { result.collisions += 1 }
+                wz.reset();
+                Ok::<(), ()>(())
+            }).unwrap();
+            drop(wz);
+            results.push(result);
+        }
+        Ok(results)
+    }
+}
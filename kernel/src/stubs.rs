@@ -76,19 +76,37 @@ impl<T> ReadZipper<T> {
     }
 }
 
+/// Paths held by the outstanding exclusive zippers of a `ZipperHead`
+type Claims = std::sync::Arc<std::sync::Mutex<Vec<Vec<u8>>>>;
+
 #[derive(Debug, Clone)]
 pub struct ZipperHead<T> {
+    claimed: Claims,
     _phantom: std::marker::PhantomData<T>,
 }
 
 impl<T> ZipperHead<T> {
     pub fn new() -> Self {
         Self {
+            claimed: Claims::default(),
             _phantom: std::marker::PhantomData,
         }
     }
+    
+    /// Like pathmap, refuses a path that is a prefix of, or prefixed by, the path of a live exclusive zipper
+    pub fn write_zipper_at_exclusive_path(&self, path: &[u8]) -> Result<WriteZipper<T>, Conflict> {
+        let mut claimed = self.claimed.lock().unwrap();
+        if claimed.iter().any(|held| held.starts_with(path) || path.starts_with(held)) {
+            return Err(Conflict);
+        }
+        claimed.push(path.to_vec());
+        Ok(WriteZipper { claim: Some((self.claimed.clone(), path.to_vec())), _phantom: std::marker::PhantomData })
+    }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Conflict;
+
 // Additional functions and types needed
 pub fn serialize(_data: &[u8]) -> String {
     format!("serialized:{:?}", _data)
@@ -109,6 +127,9 @@ impl Context {
 #[derive(Debug)]
 pub enum ParserError {
     InputFinished,
+    UnexpectedEOF,
+    UnexpectedRightBracket,
+    TooManyArguments,
 }
 
 // ExprZipper stub
@@ -285,18 +306,18 @@ pub mod pathmap {
         pub struct SerializationStats;
         pub struct DeserializationStats;
         
-        pub fn serialize_paths_<T>(_zipper: ReadZipper<T>, _file: &mut std::fs::File) -> Result<SerializationStats, std::io::Error> {
+        pub fn serialize_paths_<T, W: std::io::Write>(_zipper: ReadZipper<T>, _target: &mut W) -> Result<SerializationStats, std::io::Error> {
             Ok(SerializationStats)
         }
         
-        pub fn deserialize_paths_<T>(_zipper: WriteZipper<T>, _file: &mut std::fs::File, _default: T) -> Result<DeserializationStats, std::io::Error> {
+        pub fn deserialize_paths_<T, R: std::io::Read>(_zipper: WriteZipper<T>, _source: &mut R, _default: T) -> Result<DeserializationStats, std::io::Error> {
             Ok(DeserializationStats)
         }
     }
     
     pub mod utils {
-        pub fn find_prefix_overlap(_a: &[u8], _b: &[u8]) -> usize {
-            0
+        pub fn find_prefix_overlap(a: &[u8], b: &[u8]) -> usize {
+            a.iter().zip(b).take_while(|(x, y)| x == y).count()
         }
     }
 }
@@ -333,6 +354,33 @@ pub fn byte_item(b: u8) -> Tag {
     else { panic!("reserved {}", b) }
 }
 
+/// Renders the tagged encoding of `expr` one item per line, e.g. `Arity(2)`, `SymbolSize(3) "abc"`, `NewVar`
+pub fn debug_bytes(expr: Expr) -> String {
+    let mut out = String::new();
+    let mut i = 0;
+    let mut pending = 1usize;
+    while pending > 0 {
+        pending -= 1;
+        let tag = byte_item(unsafe { *expr.ptr.add(i) });
+        i += 1;
+        if !out.is_empty() { out.push('\n'); }
+        match tag {
+            Tag::Arity(a) => {
+                pending += a as usize;
+                out.push_str(&format!("Arity({})", a));
+            }
+            Tag::SymbolSize(n) => {
+                let symbol = unsafe { std::slice::from_raw_parts(expr.ptr.add(i), n as usize) };
+                i += n as usize;
+                out.push_str(&format!("SymbolSize({}) \"{}\"", n, symbol.escape_ascii()));
+            }
+            Tag::NewVar => out.push_str("NewVar"),
+            Tag::VarRef(r) => out.push_str(&format!("VarRef({})", r)),
+        }
+    }
+    out
+}
+
 #[derive(Clone, Copy)]
 #[repr(transparent)]
 pub struct Expr {
@@ -351,6 +399,28 @@ impl Expr {
     }
 }
 
+/// An expression that owns its encoding
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OwnedExpr {
+    pub buf: Vec<u8>,
+}
+
+impl OwnedExpr {
+    pub fn borrow(&self) -> Expr {
+        Expr { ptr: self.buf.as_ptr() as *mut u8 }
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        &self.buf[..]
+    }
+}
+
+impl From<Vec<u8>> for OwnedExpr {
+    fn from(buf: Vec<u8>) -> Self {
+        Self { buf }
+    }
+}
+
 unsafe impl Send for Expr {}
 unsafe impl Sync for Expr {}
 
@@ -378,19 +448,38 @@ pub enum AlgebraicStatus {
     None,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct WriteZipper<T> {
+    /// The zipper head claim released when an exclusive zipper is dropped
+    claim: Option<(Claims, Vec<u8>)>,
     _phantom: std::marker::PhantomData<T>,
 }
 
 impl<T> WriteZipper<T> {
     pub fn new() -> Self {
         Self {
+            claim: None,
             _phantom: std::marker::PhantomData,
         }
     }
 }
 
+impl<T> Clone for WriteZipper<T> {
+    /// Clones do not hold the original's claim
+    fn clone(&self) -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Drop for WriteZipper<T> {
+    fn drop(&mut self) {
+        if let Some((claimed, path)) = self.claim.take() {
+            let mut claimed = claimed.lock().unwrap();
+            if let Some(i) = claimed.iter().position(|held| *held == path) { claimed.swap_remove(i); }
+        }
+    }
+}
+
 impl<T> ZipperMoving for WriteZipper<T> {
     fn join_into(&mut self, _other: &mut Self) -> AlgebraicStatus {
         AlgebraicStatus::Element
@@ -398,10 +487,109 @@ impl<T> ZipperMoving for WriteZipper<T> {
 }
 
 // Shared mapping stub for bucket_map
-pub struct SharedMappingHandle;
+pub const SYM_LEN: usize = 8;
+
+pub type Symbol = [u8; SYM_LEN];
+
+#[derive(Default)]
+struct SymbolTables {
+    to_symbol: BTreeMap<Vec<u8>, Symbol>,
+    /// Boxed so the bytes handed out by `get_bytes` stay put while the tables grow
+    to_bytes: BTreeMap<Symbol, Box<[u8]>>,
+    next_symbol: u64,
+}
+
+#[derive(Default)]
+struct SharedMapping {
+    stable: bool,
+    inline_small: bool,
+    tables: std::sync::RwLock<SymbolTables>,
+}
+
+/// Reference counted handle to a symbol mapping, mirroring bucket_map's
+#[derive(Clone, Default)]
+pub struct SharedMappingHandle(std::sync::Arc<SharedMapping>);
+
+/// Permission to intern new symbols into a mapping
+pub struct WritePermit<'a>(&'a SharedMappingHandle);
+
+impl<'a> std::ops::Deref for WritePermit<'a> {
+    type Target = SharedMappingHandle;
+    fn deref(&self) -> &SharedMappingHandle {
+        self.0
+    }
+}
+
+impl<'a> WritePermit<'a> {
+    pub fn get_sym_or_insert(&self, bytes: &[u8]) -> Symbol {
+        let mut tables = self.0.0.tables.write().unwrap();
+        if let Some(sym) = tables.to_symbol.get(bytes) { return *sym }
+        let sym = if self.0.0.stable {
+            // probe past collisions (and the all-zero symbol, which is reserved)
+            let mut candidate = stable_symbol(bytes);
+            while u64::from_be_bytes(candidate) == 0 || tables.to_bytes.contains_key(&candidate) {
+                candidate = u64::from_be_bytes(candidate).wrapping_add(1).to_be_bytes();
+            }
+            candidate
+        } else {
+            tables.next_symbol += 1;
+            tables.next_symbol.to_be_bytes()
+        };
+        tables.to_symbol.insert(bytes.to_vec(), sym);
+        tables.to_bytes.insert(sym, bytes.into());
+        sym
+    }
+}
+
+/// The symbol a stable mapping first tries for `bytes`: a 64-bit FNV-1a hash of them
+fn stable_symbol(bytes: &[u8]) -> Symbol {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for b in bytes {
+        hash = (hash ^ *b as u64).wrapping_mul(0x100000001b3);
+    }
+    hash.to_be_bytes()
+}
 
 impl SharedMappingHandle {
     pub fn new() -> Self {
-        Self
+        Self::default()
+    }
+
+    /// Symbols are derived from a hash of their bytes rather than insertion order
+    pub fn new_stable() -> Self {
+        Self(std::sync::Arc::new(SharedMapping { stable: true, ..Default::default() }))
     }
-}
\ No newline at end of file
+
+    /// Users of the mapping store symbols shorter than a [`Symbol`] inline instead of interning them
+    pub fn new_inlining() -> Self {
+        Self(std::sync::Arc::new(SharedMapping { inline_small: true, ..Default::default() }))
+    }
+
+    pub fn is_stable(&self) -> bool {
+        self.0.stable
+    }
+
+    pub fn inlines_small(&self) -> bool {
+        self.0.inline_small
+    }
+
+    pub fn try_aquire_permission(&self) -> Result<WritePermit<'_>, ()> {
+        Ok(WritePermit(self))
+    }
+
+    pub fn get_sym(&self, bytes: &[u8]) -> Option<Symbol> {
+        self.0.tables.read().unwrap().to_symbol.get(bytes).copied()
+    }
+
+    pub fn get_bytes(&self, sym: Symbol) -> Option<&[u8]> {
+        let tables = self.0.tables.read().unwrap();
+        // entries are never removed and their boxes never move, so they live as long as the mapping
+        tables.to_bytes.get(&sym).map(|b| unsafe { &*(&**b as *const [u8]) })
+    }
+
+    pub fn for_each_symbol(&self, mut f: impl FnMut(Symbol, &[u8])) {
+        for (sym, bytes) in self.0.tables.read().unwrap().to_bytes.iter() {
+            f(*sym, bytes)
+        }
+    }
+}
@@ -1,9 +1,10 @@
 // Triemap Derivation Implementation
 // Generalizes triemap structure over algebraic data types
 
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fmt::Debug;
 use std::hash::Hash;
+use std::rc::Rc;
 
 /// Generic triemap trait that can be derived for algebraic data types
 pub trait TrieMap<K, V> {
@@ -21,28 +22,208 @@ pub trait TrieMap<K, V> {
     fn difference(&self, other: &Self) -> Self where V: Clone;
 }
 
+/// How a [`BytesTrieMap`] node stores its children
+///
+/// Traversals that promise lexicographic order (`iter`, `range`, `fuzzy_get`, `debug_tree`)
+/// keep it on every backend; only the cost of a step differs.
+pub trait NodeBackend: Sized {
+    type Children<T>: Children<T>;
+}
+
+/// A node's children, keyed by the next byte of the key
+pub trait Children<T> {
+    fn empty() -> Self;
+    fn get(&self, byte: u8) -> Option<&T>;
+    fn get_mut(&mut self, byte: u8) -> Option<&mut T>;
+    fn get_or_insert_with(&mut self, byte: u8, f: impl FnOnce() -> T) -> &mut T;
+    fn remove(&mut self, byte: u8) -> Option<T>;
+    fn is_empty(&self) -> bool;
+    /// The children in no particular order
+    fn iter<'a>(&'a self) -> impl Iterator<Item = (u8, &'a T)> where T: 'a;
+    fn iter_mut<'a>(&'a mut self) -> impl Iterator<Item = (u8, &'a mut T)> where T: 'a;
+    /// The children in ascending byte order
+    fn sorted<'a>(&'a self) -> impl DoubleEndedIterator<Item = (u8, &'a T)> where T: 'a;
+    fn retain(&mut self, f: impl FnMut(&mut T) -> bool);
+    fn clone_children(&self) -> Self where T: Clone;
+
+    fn insert(&mut self, byte: u8, child: T) {
+        let mut child = Some(child);
+        let slot = self.get_or_insert_with(byte, || child.take().unwrap());
+        if let Some(child) = child {
+            *slot = child;
+        }
+    }
+}
+
+/// Children in a `BTreeMap`: ordered, O(log n) per step (the default)
+#[derive(Debug, Clone, Copy)]
+pub struct Ordered;
+
+/// Children in a 256-slot array allocated with the first child: O(1) per step, 256 slots per inner node
+#[derive(Debug, Clone, Copy)]
+pub struct Dense;
+
+/// Children in a `HashMap`: O(1) expected per step, ordered traversals sort the children of each node they visit
+#[derive(Debug, Clone, Copy)]
+pub struct Hashed;
+
+impl NodeBackend for Ordered {
+    type Children<T> = BTreeMap<u8, T>;
+}
+
+impl NodeBackend for Dense {
+    type Children<T> = DenseChildren<T>;
+}
+
+impl NodeBackend for Hashed {
+    type Children<T> = HashMap<u8, T>;
+}
+
+impl<T> Children<T> for BTreeMap<u8, T> {
+    fn empty() -> Self { BTreeMap::new() }
+    fn get(&self, byte: u8) -> Option<&T> { BTreeMap::get(self, &byte) }
+    fn get_mut(&mut self, byte: u8) -> Option<&mut T> { BTreeMap::get_mut(self, &byte) }
+    fn get_or_insert_with(&mut self, byte: u8, f: impl FnOnce() -> T) -> &mut T { self.entry(byte).or_insert_with(f) }
+    fn remove(&mut self, byte: u8) -> Option<T> { BTreeMap::remove(self, &byte) }
+    fn is_empty(&self) -> bool { BTreeMap::is_empty(self) }
+    fn iter<'a>(&'a self) -> impl Iterator<Item = (u8, &'a T)> where T: 'a { BTreeMap::iter(self).map(|(&b, c)| (b, c)) }
+    fn iter_mut<'a>(&'a mut self) -> impl Iterator<Item = (u8, &'a mut T)> where T: 'a { BTreeMap::iter_mut(self).map(|(&b, c)| (b, c)) }
+    fn sorted<'a>(&'a self) -> impl DoubleEndedIterator<Item = (u8, &'a T)> where T: 'a { BTreeMap::iter(self).map(|(&b, c)| (b, c)) }
+    fn retain(&mut self, mut f: impl FnMut(&mut T) -> bool) { BTreeMap::retain(self, |_, c| f(c)) }
+    fn clone_children(&self) -> Self where T: Clone { self.clone() }
+}
+
+impl<T> Children<T> for HashMap<u8, T> {
+    fn empty() -> Self { HashMap::new() }
+    fn get(&self, byte: u8) -> Option<&T> { HashMap::get(self, &byte) }
+    fn get_mut(&mut self, byte: u8) -> Option<&mut T> { HashMap::get_mut(self, &byte) }
+    fn get_or_insert_with(&mut self, byte: u8, f: impl FnOnce() -> T) -> &mut T { self.entry(byte).or_insert_with(f) }
+    fn remove(&mut self, byte: u8) -> Option<T> { HashMap::remove(self, &byte) }
+    fn is_empty(&self) -> bool { HashMap::is_empty(self) }
+    fn iter<'a>(&'a self) -> impl Iterator<Item = (u8, &'a T)> where T: 'a { HashMap::iter(self).map(|(&b, c)| (b, c)) }
+    fn iter_mut<'a>(&'a mut self) -> impl Iterator<Item = (u8, &'a mut T)> where T: 'a { HashMap::iter_mut(self).map(|(&b, c)| (b, c)) }
+    fn sorted<'a>(&'a self) -> impl DoubleEndedIterator<Item = (u8, &'a T)> where T: 'a {
+        let mut children: Vec<_> = Children::iter(self).collect();
+        children.sort_unstable_by_key(|&(b, _)| b);
+        children.into_iter()
+    }
+    fn retain(&mut self, mut f: impl FnMut(&mut T) -> bool) { HashMap::retain(self, |_, c| f(c)) }
+    fn clone_children(&self) -> Self where T: Clone { self.clone() }
+}
+
+/// The children of a [`Dense`] node, indexed by byte; leaves don't allocate any slots
+#[derive(Debug)]
+pub struct DenseChildren<T> {
+    slots: Vec<Option<T>>,
+    len: usize,
+}
+
+impl<T> Children<T> for DenseChildren<T> {
+    fn empty() -> Self { Self { slots: Vec::new(), len: 0 } }
+    fn get(&self, byte: u8) -> Option<&T> { self.slots.get(byte as usize)?.as_ref() }
+    fn get_mut(&mut self, byte: u8) -> Option<&mut T> { self.slots.get_mut(byte as usize)?.as_mut() }
+    fn get_or_insert_with(&mut self, byte: u8, f: impl FnOnce() -> T) -> &mut T {
+        if self.slots.is_empty() {
+            self.slots.resize_with(256, || None);
+        }
+        let slot = &mut self.slots[byte as usize];
+        if slot.is_none() {
+            self.len += 1;
+        }
+        slot.get_or_insert_with(f)
+    }
+    fn remove(&mut self, byte: u8) -> Option<T> {
+        let removed = self.slots.get_mut(byte as usize)?.take();
+        if removed.is_some() {
+            self.len -= 1;
+        }
+        removed
+    }
+    fn is_empty(&self) -> bool { self.len == 0 }
+    fn iter<'a>(&'a self) -> impl Iterator<Item = (u8, &'a T)> where T: 'a { self.sorted() }
+    fn iter_mut<'a>(&'a mut self) -> impl Iterator<Item = (u8, &'a mut T)> where T: 'a {
+        self.slots.iter_mut().enumerate().filter_map(|(b, c)| Some((b as u8, c.as_mut()?)))
+    }
+    fn sorted<'a>(&'a self) -> impl DoubleEndedIterator<Item = (u8, &'a T)> where T: 'a {
+        self.slots.iter().enumerate().filter_map(|(b, c)| Some((b as u8, c.as_ref()?)))
+    }
+    fn retain(&mut self, mut f: impl FnMut(&mut T) -> bool) {
+        for slot in self.slots.iter_mut() {
+            if slot.as_mut().is_some_and(|c| !f(c)) {
+                *slot = None;
+                self.len -= 1;
+            }
+        }
+    }
+    fn clone_children(&self) -> Self where T: Clone { Self { slots: self.slots.clone(), len: self.len } }
+}
+
 /// Bytes-based triemap implementation
-#[derive(Debug, Clone)]
-pub struct BytesTrieMap<V> {
-    root: TrieNode<V>,
+///
+/// `B` picks how each node stores its children, see [`NodeBackend`].
+pub struct BytesTrieMap<V, B: NodeBackend = Ordered> {
+    root: TrieNode<V, B>,
 }
 
-#[derive(Debug, Clone)]
-struct TrieNode<V> {
+struct TrieNode<V, B: NodeBackend> {
     value: Option<V>,
-    children: BTreeMap<u8, TrieNode<V>>,
+    children: B::Children<TrieNode<V, B>>,
 }
 
-impl<V> TrieNode<V> {
+impl<V, B: NodeBackend> TrieNode<V, B> {
     fn new() -> Self {
         Self {
             value: None,
-            children: BTreeMap::new(),
+            children: Children::empty(),
+        }
+    }
+}
+
+impl<V: Clone, B: NodeBackend> Clone for TrieNode<V, B> {
+    fn clone(&self) -> Self {
+        Self {
+            value: self.value.clone(),
+            children: self.children.clone_children(),
         }
     }
 }
 
-impl<V> TrieMap<&[u8], V> for BytesTrieMap<V> {
+impl<V: Debug, B: NodeBackend> Debug for TrieNode<V, B> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        struct Sorted<'a, V, B: NodeBackend>(&'a B::Children<TrieNode<V, B>>);
+        impl<V: Debug, B: NodeBackend> Debug for Sorted<'_, V, B> {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                f.debug_map().entries(self.0.sorted()).finish()
+            }
+        }
+        f.debug_struct("TrieNode")
+            .field("value", &self.value)
+            .field("children", &Sorted::<V, B>(&self.children))
+            .finish()
+    }
+}
+
+impl<V: Clone, B: NodeBackend> Clone for BytesTrieMap<V, B> {
+    fn clone(&self) -> Self {
+        Self { root: self.root.clone() }
+    }
+}
+
+impl<V: Debug, B: NodeBackend> Debug for BytesTrieMap<V, B> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BytesTrieMap").field("root", &self.root).finish()
+    }
+}
+
+impl<V> BytesTrieMap<V> {
+    /// An empty map with the default [`Ordered`] backend; spell out the backend
+    /// (`BytesTrieMap::<V, Dense>::new()`) to pick another
+    pub fn new() -> Self {
+        <Self as TrieMap<&[u8], V>>::new()
+    }
+}
+
+impl<V, B: NodeBackend> TrieMap<&[u8], V> for BytesTrieMap<V, B> {
     fn new() -> Self {
         Self {
             root: TrieNode::new(),
@@ -52,7 +233,7 @@ impl<V> TrieMap<&[u8], V> for BytesTrieMap<V> {
     fn insert(&mut self, key: &[u8], value: V) -> Option<V> {
         let mut node = &mut self.root;
         for &byte in key {
-            node = node.children.entry(byte).or_insert_with(TrieNode::new);
+            node = node.children.get_or_insert_with(byte, TrieNode::new);
         }
         node.value.replace(value)
     }
@@ -60,7 +241,7 @@ impl<V> TrieMap<&[u8], V> for BytesTrieMap<V> {
     fn get(&self, key: &&[u8]) -> Option<&V> {
         let mut node = &self.root;
         for &byte in *key {
-            node = node.children.get(&byte)?;
+            node = node.children.get(byte)?;
         }
         node.value.as_ref()
     }
@@ -83,16 +264,12 @@ impl<V> TrieMap<&[u8], V> for BytesTrieMap<V> {
     
     /// Union operation: combines two triemaps
     fn union(&self, other: &Self) -> Self where V: Clone {
-        let mut result = self.clone();
-        result.union_with(other);
-        result
+        self.clone().into_union(other)
     }
     
     /// Intersection operation: keeps only common keys
     fn intersection(&self, other: &Self) -> Self where V: Clone {
-        let mut result = Self::new();
-        self.intersection_recursive(&self.root, &other.root, &mut result.root, &[]);
-        result
+        self.intersection_with(other, |left, _| left.clone())
     }
     
     /// Difference operation: removes keys present in other
@@ -103,7 +280,7 @@ impl<V> TrieMap<&[u8], V> for BytesTrieMap<V> {
     }
 }
 
-impl<V> BytesTrieMap<V> {
+impl<V, B: NodeBackend> BytesTrieMap<V, B> {
     /// Insert with owned key
     pub fn insert_owned(&mut self, key: Vec<u8>, value: V) -> Option<V> {
         self.insert(&key, value)
@@ -114,18 +291,18 @@ impl<V> BytesTrieMap<V> {
         self.get(&key.as_slice())
     }
     
-    fn remove_recursive(&mut self, node: &mut TrieNode<V>, key: &&[u8], depth: usize) -> Option<V> {
+    fn remove_recursive(&mut self, node: &mut TrieNode<V, B>, key: &&[u8], depth: usize) -> Option<V> {
         if depth == key.len() {
             return node.value.take();
         }
         
         let byte = key[depth];
-        if let Some(child) = node.children.get_mut(&byte) {
+        if let Some(child) = node.children.get_mut(byte) {
             let result = self.remove_recursive(child, key, depth + 1);
             
             // Clean up empty nodes
             if child.value.is_none() && child.children.is_empty() {
-                node.children.remove(&byte);
+                node.children.remove(byte);
             }
             
             result
@@ -134,101 +311,321 @@ impl<V> BytesTrieMap<V> {
         }
     }
     
-    fn count_values(&self, node: &TrieNode<V>) -> usize {
+    /// Whether every key in `keys` is present
+    pub fn contains_all(&self, keys: &[&[u8]]) -> bool {
+        self.get_many(keys).iter().all(Option::is_some)
+    }
+    
+    /// Look up several keys at once, returning results aligned with `keys`
+    ///
+    /// Keys are visited in sorted order so that each descent resumes from the
+    /// deepest node shared with the previous key instead of restarting at the root.
+    pub fn get_many<'a>(&'a self, keys: &[&[u8]]) -> Vec<Option<&'a V>> {
+        let mut order: Vec<usize> = (0..keys.len()).collect();
+        order.sort_by_key(|&i| keys[i]);
+        
+        let mut results = vec![None; keys.len()];
+        // path[d] is the node reached after the first d bytes of `previous`
+        let mut path: Vec<&TrieNode<V, B>> = vec![&self.root];
+        let mut previous: &[u8] = &[];
+        for i in order {
+            let key = keys[i];
+            let shared = previous.iter().zip(key.iter()).take_while(|(a, b)| a == b).count().min(path.len() - 1);
+            path.truncate(shared + 1);
+            for byte in &key[shared..] {
+                match path.last().unwrap().children.get(*byte) {
+                    Some(child) => path.push(child),
+                    None => break,
+                }
+            }
+            previous = &key[..path.len() - 1];
+            if path.len() == key.len() + 1 {
+                results[i] = path.last().unwrap().value.as_ref();
+            }
+        }
+        results
+    }
+    
+    fn count_values(&self, node: &TrieNode<V, B>) -> usize {
         let mut count = if node.value.is_some() { 1 } else { 0 };
-        for child in node.children.values() {
+        for (_, child) in node.children.iter() {
             count += self.count_values(child);
         }
         count
     }
     
+    /// Consuming union: merges `other` into `self` in place, left value wins on conflict
+    ///
+    /// Unlike `union`, which clones all of `self` up front, this only allocates the
+    /// nodes that `other` contributes, so peak memory is roughly `|other|` instead of `|self| + |other|`.
+    pub fn into_union(mut self, other: &Self) -> Self where V: Clone {
+        self.union_with(other);
+        self
+    }
+    
     fn union_with(&mut self, other: &Self) where V: Clone {
-        self.union_recursive(&mut self.root, &other.root);
+        Self::union_recursive(&mut self.root, &other.root);
     }
     
-    fn union_recursive(&mut self, node: &mut TrieNode<V>, other_node: &TrieNode<V>) where V: Clone {
+    fn union_recursive(node: &mut TrieNode<V, B>, other_node: &TrieNode<V, B>) where V: Clone {
         // If other node has a value and current doesn't, take it
         if node.value.is_none() && other_node.value.is_some() {
             node.value = other_node.value.clone();
         }
         
         // Recursively union children
-        for (&byte, other_child) in &other_node.children {
-            let child = node.children.entry(byte).or_insert_with(TrieNode::new);
-            self.union_recursive(child, other_child);
+        for (byte, other_child) in other_node.children.iter() {
+            let child = node.children.get_or_insert_with(byte, TrieNode::new);
+            Self::union_recursive(child, other_child);
         }
     }
     
-    fn intersection_recursive(&self, node1: &TrieNode<V>, node2: &TrieNode<V>, result: &mut TrieNode<V>, _path: &[u8]) where V: Clone {
+    /// Union of every map in `maps`, merged in order into a single accumulator; the first map holding a key
+    /// gives its value
+    pub fn union_all(maps: &[&Self]) -> Self where V: Clone {
+        let mut result = Self { root: TrieNode::new() };
+        for map in maps {
+            result.union_with(map);
+        }
+        result
+    }
+    
+    /// Intersection of every map in `maps`, keeping the values of the smallest one
+    ///
+    /// Starts from the smallest map so every step works on as little as possible, and
+    /// stops as soon as the accumulator is empty instead of visiting the remaining maps.
+    pub fn intersection_all(maps: &[&Self]) -> Self where V: Clone {
+        Self::intersection_all_counted(maps).0
+    }
+    
+    /// `intersection_all`, also returning how many maps were folded in
+    fn intersection_all_counted(maps: &[&Self]) -> (Self, usize) where V: Clone {
+        let Some(smallest) = (0..maps.len()).min_by_key(|&i| maps[i].len()) else {
+            return (Self { root: TrieNode::new() }, 0)
+        };
+        let mut result = maps[smallest].clone();
+        let mut folded = 1;
+        for (i, map) in maps.iter().enumerate() {
+            if i == smallest { continue }
+            if result.root.value.is_none() && result.root.children.is_empty() { break }
+            result = result.intersection(map);
+            folded += 1;
+        }
+        (result, folded)
+    }
+    
+    /// Intersection that keeps the value from `other` at shared keys
+    pub fn intersection_right(&self, other: &Self) -> Self where V: Clone {
+        self.intersection_with(other, |_, right| right.clone())
+    }
+    
+    /// Intersection that combines the left and right values at each shared key with `f`
+    pub fn intersection_with<F: Fn(&V, &V) -> V>(&self, other: &Self, f: F) -> Self {
+        let mut result = Self { root: TrieNode::new() };
+        Self::intersection_recursive(&self.root, &other.root, &mut result.root, &f);
+        result
+    }
+    
+    fn intersection_recursive<F: Fn(&V, &V) -> V>(node1: &TrieNode<V, B>, node2: &TrieNode<V, B>, result: &mut TrieNode<V, B>, f: &F) {
         // Include value only if both nodes have it
-        if node1.value.is_some() && node2.value.is_some() {
-            result.value = node1.value.clone();
+        if let (Some(left), Some(right)) = (&node1.value, &node2.value) {
+            result.value = Some(f(left, right));
         }
         
-        // Recursively intersect children
-        for (&byte, child1) in &node1.children {
-            if let Some(child2) = node2.children.get(&byte) {
-                let result_child = result.children.entry(byte).or_insert_with(TrieNode::new);
-                self.intersection_recursive(child1, child2, result_child, _path);
+        // Recursively intersect children, dropping branches that end up holding no values
+        for (byte, child1) in node1.children.iter() {
+            if let Some(child2) = node2.children.get(byte) {
+                let mut result_child = TrieNode::new();
+                Self::intersection_recursive(child1, child2, &mut result_child, f);
+                if result_child.value.is_some() || !result_child.children.is_empty() {
+                    result.children.insert(byte, result_child);
+                }
             }
         }
     }
     
+    /// Difference that also reports which keys of `self` were removed because they appear in `other`
+    ///
+    /// The removed keys are returned in lexicographic order.
+    pub fn difference_report(&self, other: &Self) -> (Self, Vec<Vec<u8>>) where V: Clone {
+        let mut result = self.clone();
+        let mut removed = Vec::new();
+        Self::difference_recursive(&mut result.root, &other.root, &mut Vec::new(), &mut removed);
+        (result, removed)
+    }
+    
     fn difference_with(&mut self, other: &Self) where V: Clone {
-        self.difference_recursive(&mut self.root, &other.root);
+        Self::difference_recursive(&mut self.root, &other.root, &mut Vec::new(), &mut Vec::new());
     }
     
-    fn difference_recursive(&mut self, node: &mut TrieNode<V>, other_node: &TrieNode<V>) where V: Clone {
+    fn difference_recursive(node: &mut TrieNode<V, B>, other_node: &TrieNode<V, B>, path: &mut Vec<u8>, removed: &mut Vec<Vec<u8>>) where V: Clone {
         // Remove value if it exists in other
-        if other_node.value.is_some() {
-            node.value = None;
+        if other_node.value.is_some() && node.value.take().is_some() {
+            removed.push(path.clone());
         }
         
         // Recursively difference children
-        for (&byte, child) in &mut node.children {
-            if let Some(other_child) = other_node.children.get(&byte) {
-                self.difference_recursive(child, other_child);
+        for (byte, child) in node.children.iter_mut() {
+            if let Some(other_child) = other_node.children.get(byte) {
+                path.push(byte);
+                Self::difference_recursive(child, other_child, path, removed);
+                path.pop();
             }
         }
         
         // Clean up empty children
-        node.children.retain(|_, child| child.value.is_some() || !child.children.is_empty());
+        node.children.retain(|child| child.value.is_some() || !child.children.is_empty());
     }
     
     /// Iterator over all key-value pairs
-    pub fn iter(&self) -> TrieMapIterator<V> {
+    pub fn iter(&self) -> TrieMapIterator<V, B> {
         TrieMapIterator::new(&self.root)
     }
+    
+    /// All entries within `max_edits` Levenshtein edits of `key`, in lexicographic key order
+    ///
+    /// Walks the trie carrying one row of the edit-distance table per node, and abandons
+    /// a branch as soon as every cell in its row exceeds `max_edits`.
+    pub fn fuzzy_get(&self, key: &[u8], max_edits: usize) -> Vec<(Vec<u8>, &V)> {
+        let mut results = Vec::new();
+        let first_row: Vec<usize> = (0..=key.len()).collect();
+        Self::fuzzy_recursive(&self.root, key, max_edits, &first_row, &mut Vec::new(), &mut results);
+        results
+    }
+    
+    fn fuzzy_recursive<'a>(node: &'a TrieNode<V, B>, key: &[u8], max_edits: usize, row: &[usize], path: &mut Vec<u8>, results: &mut Vec<(Vec<u8>, &'a V)>) {
+        if let Some(value) = &node.value {
+            if row[key.len()] <= max_edits {
+                results.push((path.clone(), value));
+            }
+        }
+        
+        for (byte, child) in node.children.sorted() {
+            let mut next = Vec::with_capacity(row.len());
+            next.push(row[0] + 1);
+            for i in 1..row.len() {
+                let substitution = row[i - 1] + (key[i - 1] != byte) as usize;
+                next.push(substitution.min(row[i] + 1).min(next[i - 1] + 1));
+            }
+            if next.iter().min().is_some_and(|&d| d <= max_edits) {
+                path.push(byte);
+                Self::fuzzy_recursive(child, key, max_edits, &next, path, results);
+                path.pop();
+            }
+        }
+    }
+    
+    /// Iterator over the entries with keys in `[lo, hi)`, in lexicographic order
+    ///
+    /// Empty when `lo >= hi`.
+    pub fn range<'a>(&'a self, lo: &[u8], hi: &[u8]) -> TrieMapIterator<'a, V, B> {
+        TrieMapIterator::seek(&self.root, lo, Some(hi))
+    }
+    
+    /// Iterator over the entries with keys `>= lo`, in lexicographic order
+    pub fn range_from<'a>(&'a self, lo: &[u8]) -> TrieMapIterator<'a, V, B> {
+        TrieMapIterator::seek(&self.root, lo, None)
+    }
+    
+    /// Render the trie as an indented tree for debugging
+    ///
+    /// Each line is one edge byte, shown as ASCII when printable and as hex otherwise.
+    /// Nodes holding a value are marked with a trailing `*`.
+    pub fn debug_tree(&self) -> String {
+        let mut out = String::new();
+        out.push('.');
+        if self.root.value.is_some() {
+            out.push_str(" *");
+        }
+        out.push('\n');
+        Self::debug_tree_recursive(&self.root, 1, &mut out);
+        out
+    }
+    
+    fn debug_tree_recursive(node: &TrieNode<V, B>, depth: usize, out: &mut String) {
+        for (byte, child) in node.children.sorted() {
+            for _ in 0..depth {
+                out.push_str("  ");
+            }
+            if byte.is_ascii_graphic() {
+                out.push('\'');
+                out.push(byte as char);
+                out.push('\'');
+            } else {
+                out.push_str(&format!("0x{:02x}", byte));
+            }
+            if child.value.is_some() {
+                out.push_str(" *");
+            }
+            out.push('\n');
+            Self::debug_tree_recursive(child, depth + 1, out);
+        }
+    }
 }
 
 /// Iterator for TrieMap
-pub struct TrieMapIterator<'a, V> {
-    stack: Vec<(Vec<u8>, &'a TrieNode<V>)>,
+///
+/// Keys come out in lexicographic byte order: a node's own key is yielded before any key that extends it
+/// (`app` before `apple`), and children are visited by ascending byte.
+pub struct TrieMapIterator<'a, V, B: NodeBackend = Ordered> {
+    stack: Vec<(Vec<u8>, &'a TrieNode<V, B>)>,
+    /// Exclusive upper bound on yielded keys
+    hi: Option<Vec<u8>>,
 }
 
-impl<'a, V> TrieMapIterator<'a, V> {
-    fn new(root: &'a TrieNode<V>) -> Self {
+impl<'a, V, B: NodeBackend> TrieMapIterator<'a, V, B> {
+    fn new(root: &'a TrieNode<V, B>) -> Self {
         let mut iter = Self {
             stack: Vec::new(),
+            hi: None,
         };
         iter.stack.push((Vec::new(), root));
         iter
     }
+    
+    /// Iterator positioned at the first key `>= lo`, stopping before `hi`
+    fn seek(root: &'a TrieNode<V, B>, lo: &[u8], hi: Option<&[u8]>) -> Self {
+        let mut iter = Self {
+            stack: Vec::new(),
+            hi: hi.map(<[u8]>::to_vec),
+        };
+        let mut node = root;
+        for (depth, &byte) in lo.iter().enumerate() {
+            // Siblings after `byte` hold keys greater than `lo`; they are visited once the branch along `lo` is done
+            for (sibling, child) in node.children.sorted().rev().take_while(|&(sibling, _)| sibling > byte) {
+                let mut child_path = lo[..depth].to_vec();
+                child_path.push(sibling);
+                iter.stack.push((child_path, child));
+            }
+            match node.children.get(byte) {
+                Some(child) => node = child,
+                None => return iter,
+            }
+        }
+        iter.stack.push((lo.to_vec(), node));
+        iter
+    }
 }
 
-impl<'a, V> Iterator for TrieMapIterator<'a, V> {
+impl<'a, V, B: NodeBackend> Iterator for TrieMapIterator<'a, V, B> {
     type Item = (Vec<u8>, &'a V);
     
     fn next(&mut self) -> Option<Self::Item> {
         while let Some((path, node)) = self.stack.pop() {
+            // Keys come out in order, so the first one past the bound ends the scan
+            if self.hi.as_ref().is_some_and(|hi| path >= *hi) {
+                self.stack.clear();
+                return None;
+            }
+            
             // Add children to stack in reverse order for lexicographic iteration
-            for (&byte, child) in node.children.iter().rev() {
+            for (byte, child) in node.children.sorted().rev() {
                 let mut child_path = path.clone();
                 child_path.push(byte);
                 self.stack.push((child_path, child));
             }
             
-            // Return value if present
+            // Return value if present; the children just pushed all extend `path`, so it precedes them
             if let Some(value) = &node.value {
                 return Some((path, value));
             }
@@ -237,6 +634,364 @@ impl<'a, V> Iterator for TrieMapIterator<'a, V> {
     }
 }
 
+/// Arena-backed triemap
+///
+/// Nodes live in a single `Vec` and refer to their children by index, so `clear` releases
+/// every node at once while keeping the allocation for the next fill, and slots freed by
+/// `remove` are recycled by later inserts.
+#[derive(Debug, Clone)]
+pub struct ArenaTrieMap<V> {
+    nodes: Vec<ArenaNode<V>>,
+    free: Vec<usize>,
+    len: usize,
+}
+
+#[derive(Debug, Clone)]
+struct ArenaNode<V> {
+    value: Option<V>,
+    /// Sorted by edge byte
+    children: Vec<(u8, usize)>,
+}
+
+const ARENA_ROOT: usize = 0;
+
+impl<V> ArenaTrieMap<V> {
+    /// Remove every entry, keeping the node storage for reuse
+    pub fn clear(&mut self) {
+        self.nodes.truncate(1);
+        self.nodes[ARENA_ROOT] = ArenaNode { value: None, children: Vec::new() };
+        self.free.clear();
+        self.len = 0;
+    }
+    
+    /// Remove and return every entry in lexicographic key order, keeping the node storage for reuse
+    pub fn drain(&mut self) -> Vec<(Vec<u8>, V)> {
+        let mut out = Vec::with_capacity(self.len);
+        let mut stack = vec![(Vec::new(), ARENA_ROOT)];
+        while let Some((path, index)) = stack.pop() {
+            if let Some(value) = self.nodes[index].value.take() {
+                out.push((path.clone(), value));
+            }
+            for &(byte, child) in self.nodes[index].children.iter().rev() {
+                let mut child_path = path.clone();
+                child_path.push(byte);
+                stack.push((child_path, child));
+            }
+        }
+        self.clear();
+        out
+    }
+    
+    /// Number of node slots the arena can hold without reallocating
+    pub fn node_capacity(&self) -> usize {
+        self.nodes.capacity()
+    }
+    
+    /// All entries in lexicographic key order
+    pub fn entries(&self) -> Vec<(Vec<u8>, &V)> {
+        let mut out = Vec::with_capacity(self.len);
+        let mut stack = vec![(Vec::new(), ARENA_ROOT)];
+        while let Some((path, index)) = stack.pop() {
+            let node = &self.nodes[index];
+            for &(byte, child) in node.children.iter().rev() {
+                let mut child_path = path.clone();
+                child_path.push(byte);
+                stack.push((child_path, child));
+            }
+            if let Some(value) = &node.value {
+                out.push((path, value));
+            }
+        }
+        out
+    }
+    
+    fn alloc_node(&mut self) -> usize {
+        let node = ArenaNode { value: None, children: Vec::new() };
+        match self.free.pop() {
+            Some(index) => { self.nodes[index] = node; index }
+            None => { self.nodes.push(node); self.nodes.len() - 1 }
+        }
+    }
+    
+    fn child(&self, index: usize, byte: u8) -> Option<usize> {
+        let children = &self.nodes[index].children;
+        children.binary_search_by_key(&byte, |&(b, _)| b).ok().map(|i| children[i].1)
+    }
+    
+    fn find(&self, key: &[u8]) -> Option<usize> {
+        key.iter().try_fold(ARENA_ROOT, |index, &byte| self.child(index, byte))
+    }
+}
+
+impl<V> TrieMap<&[u8], V> for ArenaTrieMap<V> {
+    fn new() -> Self {
+        Self {
+            nodes: vec![ArenaNode { value: None, children: Vec::new() }],
+            free: Vec::new(),
+            len: 0,
+        }
+    }
+    
+    fn insert(&mut self, key: &[u8], value: V) -> Option<V> {
+        let mut index = ARENA_ROOT;
+        for &byte in key {
+            index = match self.nodes[index].children.binary_search_by_key(&byte, |&(b, _)| b) {
+                Ok(i) => self.nodes[index].children[i].1,
+                Err(i) => {
+                    let child = self.alloc_node();
+                    self.nodes[index].children.insert(i, (byte, child));
+                    child
+                }
+            };
+        }
+        let old = self.nodes[index].value.replace(value);
+        if old.is_none() {
+            self.len += 1;
+        }
+        old
+    }
+    
+    fn get(&self, key: &&[u8]) -> Option<&V> {
+        self.nodes[self.find(key)?].value.as_ref()
+    }
+    
+    fn remove(&mut self, key: &&[u8]) -> Option<V> {
+        let mut trail = Vec::with_capacity(key.len());
+        let mut index = ARENA_ROOT;
+        for &byte in *key {
+            trail.push(index);
+            index = self.child(index, byte)?;
+        }
+        let old = self.nodes[index].value.take()?;
+        self.len -= 1;
+        
+        // Release nodes that no longer lead to any value
+        while let Some(parent) = trail.pop() {
+            let node = &self.nodes[index];
+            if node.value.is_some() || !node.children.is_empty() {
+                break;
+            }
+            self.free.push(index);
+            let byte = key[trail.len()];
+            let children = &mut self.nodes[parent].children;
+            if let Ok(i) = children.binary_search_by_key(&byte, |&(b, _)| b) {
+                children.remove(i);
+            }
+            index = parent;
+        }
+        Some(old)
+    }
+    
+    fn contains_key(&self, key: &&[u8]) -> bool {
+        self.get(key).is_some()
+    }
+    
+    fn len(&self) -> usize {
+        self.len
+    }
+    
+    fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    
+    fn union(&self, other: &Self) -> Self where V: Clone {
+        let mut result = self.clone();
+        for (key, value) in other.entries() {
+            if !result.contains_key(&key.as_slice()) {
+                result.insert(&key, value.clone());
+            }
+        }
+        result
+    }
+    
+    fn intersection(&self, other: &Self) -> Self where V: Clone {
+        let mut result = Self::new();
+        for (key, value) in self.entries() {
+            if other.contains_key(&key.as_slice()) {
+                result.insert(&key, value.clone());
+            }
+        }
+        result
+    }
+    
+    fn difference(&self, other: &Self) -> Self where V: Clone {
+        let mut result = Self::new();
+        for (key, value) in self.entries() {
+            if !other.contains_key(&key.as_slice()) {
+                result.insert(&key, value.clone());
+            }
+        }
+        result
+    }
+}
+
+/// Persistent triemap with structurally shared nodes
+///
+/// Nodes are reference counted and copied only on the path a mutation touches, so `clone` is O(1)
+/// and `union`, `intersection` and `difference` reuse whole subtrees that one side leaves untouched.
+#[derive(Debug, Clone)]
+pub struct SharedTrieMap<V> {
+    root: Rc<SharedNode<V>>,
+}
+
+#[derive(Debug, Clone)]
+struct SharedNode<V> {
+    value: Option<V>,
+    children: BTreeMap<u8, Rc<SharedNode<V>>>,
+}
+
+impl<V> SharedNode<V> {
+    fn new() -> Self {
+        Self {
+            value: None,
+            children: BTreeMap::new(),
+        }
+    }
+    
+    fn is_empty(&self) -> bool {
+        self.value.is_none() && self.children.is_empty()
+    }
+    
+    fn count_values(&self) -> usize {
+        self.value.is_some() as usize + self.children.values().map(|child| child.count_values()).sum::<usize>()
+    }
+}
+
+impl<V: Clone> SharedTrieMap<V> {
+    fn remove_recursive(node: &mut Rc<SharedNode<V>>, key: &[u8]) -> Option<V> {
+        let node = Rc::make_mut(node);
+        match key.split_first() {
+            None => node.value.take(),
+            Some((byte, rest)) => {
+                let child = node.children.get_mut(byte)?;
+                let removed = Self::remove_recursive(child, rest);
+                if child.is_empty() {
+                    node.children.remove(byte);
+                }
+                removed
+            }
+        }
+    }
+    
+    fn union_recursive(node: &mut Rc<SharedNode<V>>, other: &Rc<SharedNode<V>>) {
+        if Rc::ptr_eq(node, other) {
+            return;
+        }
+        if node.is_empty() {
+            *node = other.clone();
+            return;
+        }
+        let node = Rc::make_mut(node);
+        if node.value.is_none() {
+            node.value = other.value.clone();
+        }
+        for (&byte, other_child) in &other.children {
+            match node.children.get_mut(&byte) {
+                Some(child) => Self::union_recursive(child, other_child),
+                None => { node.children.insert(byte, other_child.clone()); }
+            }
+        }
+    }
+    
+    fn intersection_recursive(node1: &Rc<SharedNode<V>>, node2: &Rc<SharedNode<V>>) -> Option<Rc<SharedNode<V>>> {
+        if Rc::ptr_eq(node1, node2) {
+            return Some(node1.clone());
+        }
+        let mut result = SharedNode::new();
+        if node2.value.is_some() {
+            result.value = node1.value.clone();
+        }
+        for (&byte, child1) in &node1.children {
+            if let Some(child) = node2.children.get(&byte).and_then(|child2| Self::intersection_recursive(child1, child2)) {
+                result.children.insert(byte, child);
+            }
+        }
+        (!result.is_empty()).then(|| Rc::new(result))
+    }
+    
+    fn difference_recursive(node1: &Rc<SharedNode<V>>, node2: &Rc<SharedNode<V>>) -> Option<Rc<SharedNode<V>>> {
+        if Rc::ptr_eq(node1, node2) {
+            return None;
+        }
+        let mut result = SharedNode::new();
+        if node2.value.is_none() {
+            result.value = node1.value.clone();
+        }
+        for (&byte, child1) in &node1.children {
+            let child = match node2.children.get(&byte) {
+                Some(child2) => Self::difference_recursive(child1, child2),
+                None => Some(child1.clone()),
+            };
+            if let Some(child) = child {
+                result.children.insert(byte, child);
+            }
+        }
+        (!result.is_empty()).then(|| Rc::new(result))
+    }
+}
+
+impl<V: Clone> TrieMap<&[u8], V> for SharedTrieMap<V> {
+    fn new() -> Self {
+        Self {
+            root: Rc::new(SharedNode::new()),
+        }
+    }
+    
+    fn insert(&mut self, key: &[u8], value: V) -> Option<V> {
+        let mut node = Rc::make_mut(&mut self.root);
+        for &byte in key {
+            node = Rc::make_mut(node.children.entry(byte).or_insert_with(|| Rc::new(SharedNode::new())));
+        }
+        node.value.replace(value)
+    }
+    
+    fn get(&self, key: &&[u8]) -> Option<&V> {
+        let mut node = &self.root;
+        for &byte in *key {
+            node = node.children.get(&byte)?;
+        }
+        node.value.as_ref()
+    }
+    
+    fn remove(&mut self, key: &&[u8]) -> Option<V> {
+        // check first so that a miss does not copy the path
+        if !self.contains_key(key) {
+            return None;
+        }
+        Self::remove_recursive(&mut self.root, key)
+    }
+    
+    fn contains_key(&self, key: &&[u8]) -> bool {
+        self.get(key).is_some()
+    }
+    
+    fn len(&self) -> usize {
+        self.root.count_values()
+    }
+    
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    
+    fn union(&self, other: &Self) -> Self {
+        let mut result = self.clone();
+        Self::union_recursive(&mut result.root, &other.root);
+        result
+    }
+    
+    fn intersection(&self, other: &Self) -> Self {
+        Self {
+            root: Self::intersection_recursive(&self.root, &other.root).unwrap_or_else(|| Rc::new(SharedNode::new())),
+        }
+    }
+    
+    fn difference(&self, other: &Self) -> Self {
+        Self {
+            root: Self::difference_recursive(&self.root, &other.root).unwrap_or_else(|| Rc::new(SharedNode::new())),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -305,6 +1060,275 @@ mod tests {
         assert_eq!(difference.get(&b"c"[..]), None); // Removed
     }
     
+    #[test]
+    fn test_into_union() {
+        let mut trie1 = BytesTrieMap::new();
+        trie1.insert_owned(b"a".to_vec(), 1);
+        trie1.insert_owned(b"ab".to_vec(), 2);
+        trie1.insert_owned(b"b".to_vec(), 3);
+        
+        let mut trie2 = BytesTrieMap::new();
+        trie2.insert_owned(b"ab".to_vec(), 20);
+        trie2.insert_owned(b"abc".to_vec(), 30);
+        trie2.insert_owned(b"c".to_vec(), 40);
+        
+        let expected: Vec<_> = trie1.union(&trie2).iter().map(|(k, v)| (k, *v)).collect();
+        let merged = trie1.into_union(&trie2);
+        let actual: Vec<_> = merged.iter().map(|(k, v)| (k, *v)).collect();
+        assert_eq!(actual, expected);
+        assert_eq!(merged.get_owned(&b"ab".to_vec()), Some(&2)); // Left value wins
+        assert_eq!(merged.len(), 5);
+    }
+    
+    #[test]
+    fn test_fold_many() {
+        let maps: Vec<BytesTrieMap<i32>> = [
+            &[&b"a"[..], b"ab", b"abc", b"b"][..],
+            &[b"ab", b"abc"],
+            &[b"ab", b"abc", b"b", b"c"],
+            &[b"x", b"y", b"z"],
+            &[b"a", b"ab", b"abc"],
+        ].iter().enumerate().map(|(n, keys)| {
+            let mut trie = BytesTrieMap::new();
+            for key in keys.iter() { trie.insert(*key, n as i32); }
+            trie
+        }).collect();
+        let refs: Vec<&BytesTrieMap<i32>> = maps.iter().collect();
+        
+        let union = BytesTrieMap::union_all(&refs);
+        assert_eq!(union.len(), 8);
+        assert_eq!(union.get(&b"ab"[..]), Some(&0));
+        assert_eq!(union.get(&b"x"[..]), Some(&3));
+        
+        let without_disjoint = [refs[0], refs[1], refs[2], refs[4]];
+        let common: Vec<_> = BytesTrieMap::intersection_all(&without_disjoint).iter().map(|(k, v)| (k, *v)).collect();
+        assert_eq!(common, vec![(b"ab".to_vec(), 1), (b"abc".to_vec(), 1)]);
+        
+        // the smallest map (1) goes first, then 0 and 2, and the disjoint 3 empties the accumulator before 4
+        let (empty, folded) = BytesTrieMap::intersection_all_counted(&refs);
+        assert!(empty.is_empty());
+        assert_eq!(folded, 4);
+        assert!(BytesTrieMap::<i32>::intersection_all(&[]).is_empty());
+    }
+    
+    #[test]
+    fn test_difference_report() {
+        let mut trie1 = BytesTrieMap::new();
+        trie1.insert_owned(b"apple".to_vec(), 1);
+        trie1.insert_owned(b"banana".to_vec(), 2);
+        trie1.insert_owned(b"cherry".to_vec(), 3);
+        
+        let mut trie2 = BytesTrieMap::new();
+        trie2.insert_owned(b"banana".to_vec(), 20);
+        trie2.insert_owned(b"cherry".to_vec(), 30);
+        trie2.insert_owned(b"date".to_vec(), 40);
+        
+        let (difference, removed) = trie1.difference_report(&trie2);
+        assert_eq!(difference.len(), 1);
+        assert_eq!(difference.get_owned(&b"apple".to_vec()), Some(&1));
+        
+        let intersection_keys: Vec<Vec<u8>> = trie1.intersection(&trie2).iter().map(|(k, _)| k).collect();
+        assert_eq!(removed, vec![b"banana".to_vec(), b"cherry".to_vec()]);
+        assert_eq!(removed, intersection_keys);
+    }
+    
+    #[test]
+    fn test_intersection_right() {
+        let mut trie1 = BytesTrieMap::new();
+        trie1.insert_owned(b"apple".to_vec(), 1);
+        trie1.insert_owned(b"banana".to_vec(), 2);
+        trie1.insert_owned(b"cherry".to_vec(), 3);
+        
+        let mut trie2 = BytesTrieMap::new();
+        trie2.insert_owned(b"banana".to_vec(), 20);
+        trie2.insert_owned(b"cherry".to_vec(), 30);
+        trie2.insert_owned(b"date".to_vec(), 40);
+        
+        let right = trie1.intersection_right(&trie2);
+        assert_eq!(right.len(), 2);
+        assert_eq!(right.get_owned(&b"banana".to_vec()), Some(&20));
+        assert_eq!(right.get_owned(&b"cherry".to_vec()), Some(&30));
+        assert_eq!(right.get_owned(&b"apple".to_vec()), None);
+        
+        let summed = trie1.intersection_with(&trie2, |l, r| l + r);
+        assert_eq!(summed.get_owned(&b"banana".to_vec()), Some(&22));
+        assert_eq!(summed.get_owned(&b"cherry".to_vec()), Some(&33));
+    }
+    
+    #[test]
+    fn test_get_many() {
+        let mut trie = BytesTrieMap::new();
+        trie.insert_owned(b"app".to_vec(), 1);
+        trie.insert_owned(b"apple".to_vec(), 2);
+        trie.insert_owned(b"application".to_vec(), 3);
+        trie.insert_owned(b"banana".to_vec(), 4);
+        
+        let keys: [&[u8]; 6] = [b"banana", b"apple", b"appl", b"app", b"cherry", b"application"];
+        assert_eq!(trie.get_many(&keys), vec![Some(&4), Some(&2), None, Some(&1), None, Some(&3)]);
+        
+        assert!(trie.contains_all(&[b"app", b"apple", b"banana"]));
+        assert!(!trie.contains_all(&[b"app", b"appl"]));
+        assert!(trie.contains_all(&[]));
+    }
+    
+    #[test]
+    fn test_arena_clear_reuses_nodes() {
+        let mut trie = ArenaTrieMap::new();
+        let mut capacity = None;
+        for _ in 0..5 {
+            for i in 0..100_000u32 {
+                trie.insert(&i.to_be_bytes()[..], i);
+            }
+            assert_eq!(trie.len(), 100_000);
+            assert_eq!(trie.get(&&7u32.to_be_bytes()[..]), Some(&7));
+            trie.clear();
+            assert!(trie.is_empty());
+            
+            // the first fill sizes the arena, later fills must fit in it
+            let current = trie.node_capacity();
+            assert_eq!(*capacity.get_or_insert(current), current);
+        }
+    }
+    
+    #[test]
+    fn test_arena_remove_recycles_slots() {
+        let mut trie = ArenaTrieMap::new();
+        trie.insert(&b"apple"[..], 1);
+        trie.insert(&b"banana"[..], 2);
+        let nodes = trie.nodes.len();
+        
+        assert_eq!(trie.remove(&&b"banana"[..]), Some(2));
+        trie.insert(&b"cherry"[..], 3);
+        assert_eq!(trie.nodes.len(), nodes);
+        
+        let keys: Vec<Vec<u8>> = trie.entries().into_iter().map(|(k, _)| k).collect();
+        assert_eq!(keys, vec![b"apple".to_vec(), b"cherry".to_vec()]);
+        assert_eq!(trie.drain(), vec![(b"apple".to_vec(), 1), (b"cherry".to_vec(), 3)]);
+        assert!(trie.is_empty());
+    }
+    
+    #[test]
+    fn test_shared_clone_copies_only_the_touched_path() {
+        let mut original = SharedTrieMap::new();
+        for i in 0..1000 {
+            original.insert(format!("key{:04}", i).as_bytes(), i);
+        }
+        
+        let mut copy = original.clone();
+        assert_eq!(copy.insert(&b"key0500"[..], 9999), Some(500));
+        assert_eq!(original.get(&&b"key0500"[..]), Some(&500));
+        assert_eq!(copy.get(&&b"key0500"[..]), Some(&9999));
+        assert_eq!(original.len(), 1000);
+        
+        fn node_at<'a, V>(map: &'a SharedTrieMap<V>, path: &[u8]) -> &'a Rc<SharedNode<V>> {
+            path.iter().fold(&map.root, |node, byte| &node.children[byte])
+        }
+        // `key01..` is off the copied path, so both maps hold the very same node
+        assert!(Rc::ptr_eq(node_at(&original, b"key01"), node_at(&copy, b"key01")));
+        assert_eq!(Rc::strong_count(node_at(&original, b"key01")), 2);
+        assert!(!Rc::ptr_eq(node_at(&original, b"key05"), node_at(&copy, b"key05")));
+        
+        let union = original.union(&copy);
+        assert_eq!(union.get(&&b"key0500"[..]), Some(&500));
+        assert_eq!(original.difference(&copy).len(), 0);
+        assert_eq!(original.intersection(&copy).len(), 1000);
+    }
+    
+    fn backend_summary<B: NodeBackend>() -> (usize, Vec<Option<usize>>, Vec<Vec<u8>>, Vec<Vec<u8>>, usize) {
+        let keys: Vec<Vec<u8>> = (0..500u32).map(|i| (i * 7919 % 1000).to_string().into_bytes()).collect();
+        let mut trie = BytesTrieMap::<usize, B>::new();
+        for (i, key) in keys.iter().enumerate() {
+            trie.insert(key, i);
+        }
+        let probes: Vec<Option<usize>> = [&b"0"[..], b"7919", b"919", b"1", b"", b"999", b"abc"].iter().map(|k| trie.get(k).copied()).collect();
+        let all = trie.iter().map(|(k, _)| k).collect();
+        let ranged = trie.range(b"2", b"3").map(|(k, _)| k).collect();
+        for key in keys.iter().step_by(2) {
+            trie.remove(&&key[..]);
+        }
+        (trie.len() + keys.len(), probes, all, ranged, trie.len())
+    }
+    
+    #[test]
+    fn test_backends_agree() {
+        let ordered = backend_summary::<Ordered>();
+        assert_eq!(ordered.0, 750);
+        assert_eq!(backend_summary::<Dense>(), ordered);
+        assert_eq!(backend_summary::<Hashed>(), ordered);
+        
+        let mut dense = BytesTrieMap::<i32, Dense>::new();
+        dense.insert(b"ab", 1);
+        let mut hashed = BytesTrieMap::<i32, Hashed>::new();
+        hashed.insert(b"ab", 1);
+        hashed.insert(b"b", 2);
+        assert_eq!(dense.union(&BytesTrieMap::<i32, Dense>::new()).len(), 1);
+        assert_eq!(hashed.difference(&hashed.clone()).len(), 0);
+        assert_eq!(hashed.intersection(&hashed).get(&&b"b"[..]), Some(&2));
+    }
+    
+    #[test]
+    fn test_range() {
+        let mut trie = BytesTrieMap::new();
+        for (i, c) in (b'a'..=b'z').enumerate() {
+            trie.insert_owned(vec![c], i);
+        }
+        
+        let keys = |it: TrieMapIterator<usize>| it.map(|(k, _)| k).collect::<Vec<_>>();
+        assert_eq!(keys(trie.range(b"c", b"f")), vec![b"c".to_vec(), b"d".to_vec(), b"e".to_vec()]);
+        
+        trie.insert_owned(b"cc".to_vec(), 100);
+        assert_eq!(keys(trie.range(b"c", b"f")), vec![b"c".to_vec(), b"cc".to_vec(), b"d".to_vec(), b"e".to_vec()]);
+        assert_eq!(keys(trie.range(b"ca", b"d")), vec![b"cc".to_vec()]);
+        assert!(keys(trie.range(b"f", b"c")).is_empty());
+        assert!(keys(trie.range(b"c", b"c")).is_empty());
+        assert_eq!(keys(trie.range_from(b"y")), vec![b"y".to_vec(), b"z".to_vec()]);
+        assert_eq!(keys(trie.range(b"", b"b")), vec![b"a".to_vec()]);
+    }
+    
+    #[test]
+    fn test_iter_prefix_before_extensions() {
+        let mut trie = BytesTrieMap::new();
+        trie.insert_owned(b"application".to_vec(), 3);
+        trie.insert_owned(b"apple".to_vec(), 2);
+        trie.insert_owned(b"app".to_vec(), 1);
+        trie.insert_owned(b"ap".to_vec(), 0);
+        
+        let keys: Vec<_> = trie.iter().map(|(k, _)| k).collect();
+        assert_eq!(keys, vec![b"ap".to_vec(), b"app".to_vec(), b"apple".to_vec(), b"application".to_vec()]);
+        let mut sorted = keys.clone();
+        sorted.sort();
+        assert_eq!(keys, sorted);
+        assert_eq!(trie.range_from(b"app").next().map(|(k, _)| k), Some(b"app".to_vec()));
+    }
+    
+    #[test]
+    fn test_fuzzy_get() {
+        let mut trie = BytesTrieMap::new();
+        trie.insert_owned(b"apple".to_vec(), 1);
+        trie.insert_owned(b"ample".to_vec(), 2);
+        trie.insert_owned(b"apples".to_vec(), 3);
+        trie.insert_owned(b"banana".to_vec(), 4);
+        
+        let keys = |found: Vec<(Vec<u8>, &i32)>| found.into_iter().map(|(k, _)| k).collect::<Vec<_>>();
+        // "aple" is one insertion away from both "apple" and "ample", two from "apples"
+        assert_eq!(keys(trie.fuzzy_get(b"aple", 1)), vec![b"ample".to_vec(), b"apple".to_vec()]);
+        assert_eq!(keys(trie.fuzzy_get(b"aple", 2)), vec![b"ample".to_vec(), b"apple".to_vec(), b"apples".to_vec()]);
+        assert_eq!(keys(trie.fuzzy_get(b"apple", 0)), vec![b"apple".to_vec()]);
+        assert!(trie.fuzzy_get(b"cherry", 2).is_empty());
+    }
+    
+    #[test]
+    fn test_debug_tree() {
+        let mut trie = BytesTrieMap::new();
+        trie.insert_owned(b"ab".to_vec(), 1);
+        trie.insert_owned(b"ac".to_vec(), 2);
+        trie.insert_owned(vec![b'a', 0x01], 3);
+        
+        let rendered = trie.debug_tree();
+        assert_eq!(rendered, ".\n  'a'\n    0x01 *\n    'b' *\n    'c' *\n");
+        assert_eq!(rendered.matches(" *").count(), 3);
+    }
+    
     #[test]
     fn test_iterator() {
         let mut trie = BytesTrieMap::new();
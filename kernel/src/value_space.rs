@@ -0,0 +1,69 @@
+use std::io::Write;
+use mork_bytestring::ExprZipper;
+use mork_frontend::bytestring_parser::{Context, Parser, ParserError};
+use crate::space::{load_buffers, serialize_fact, SexprParser};
+use crate::stubs::{BytesTrieMap, Expr, SharedMappingHandle};
+
+/// Like [`crate::space::Space`], but every fact carries a value (a timestamp, a weight, ...) instead of `()`,
+/// making the trie a map from facts to `V` rather than a set of facts
+pub struct ValueSpace<V> {
+    pub btm: BytesTrieMap<V>,
+    pub sm: SharedMappingHandle,
+}
+
+impl <V> Default for ValueSpace<V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl <V> ValueSpace<V> {
+    pub fn new() -> Self {
+        Self { btm: BytesTrieMap::new(), sm: SharedMappingHandle::new() }
+    }
+
+    /// `load_sexpr`, storing `value(fact)` with every transformed fact; a fact loaded again gets the new value.
+    /// Returns the number of expressions read.
+    pub fn load_sexpr_with_values<F : FnMut(Expr) -> V>(&mut self, r: &[u8], pattern: Expr, template: Expr, mut value: F) -> Result<usize, String> {
+        let (mut stack, mut buffer) = load_buffers(r, template);
+        let mut it = Context::new(r);
+        let mut i = 0;
+        let mut parser = SexprParser::new(&self.sm);
+        loop {
+            let mut ez = ExprZipper::new(Expr{ptr: stack.as_mut_ptr()});
+            match parser.sexpr(&mut it, &mut ez) {
+                Ok(()) => {
+                    let data = &stack[..ez.loc];
+                    let mut oz = ExprZipper::new(Expr{ ptr: buffer.as_mut_ptr() });
+                    if (Expr{ ptr: data.as_ptr().cast_mut() }.transformData(pattern, template, &mut oz)).is_ok() {
+                        let v = value(Expr{ ptr: buffer.as_mut_ptr() });
+                        self.btm.insert(&buffer[..oz.loc], v);
+                    }
+                }
+                Err(ParserError::InputFinished) => { break }
+                Err(other) => { return Err(format!("parse error {:?} in expression {} (byte {})", other, i, it.loc)) }
+            }
+            i += 1;
+            it.variables.clear();
+        }
+        Ok(i)
+    }
+
+    /// The value stored with `fact`, if it is in the space
+    pub fn get(&self, fact: Expr) -> Option<&V> {
+        self.btm.get(unsafe { fact.span().as_ref().unwrap() })
+    }
+
+    /// Writes every fact followed by a tab and `show` of its value, one per line; returns the number of facts
+    pub fn dump_sexpr_with_values<W : Write, S : FnMut(&V) -> String>(&self, w: &mut W, mut show: S) -> Result<usize, String> {
+        let mut rz = self.btm.read_zipper();
+        let mut i = 0usize;
+        while rz.to_next_val() {
+            serialize_fact(&self.sm, Expr{ ptr: rz.path().as_ptr().cast_mut() }, w);
+            let v = rz.get_value().unwrap();
+            writeln!(w, "\t{}", show(v)).map_err(|x| x.to_string())?;
+            i += 1;
+        }
+        Ok(i)
+    }
+}